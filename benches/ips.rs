@@ -0,0 +1,57 @@
+//! Instructions-per-second benchmark
+//!
+//! Runs a busy loop (ALU ops, a call, BCD, a register store and a sprite
+//! draw) with the decoded-instruction cache off and then on, and prints the
+//! throughput of each. Run with `cargo bench --bench ips`.
+
+use chip8_emulator::machine::{Machine, MachineConfig};
+use std::time::Instant;
+
+const FRAMES: u32 = 200;
+const CYCLES_PER_FRAME: u32 = 100_000;
+
+#[rustfmt::skip]
+const ROM: [u8; 30] = [
+    0x60, 0x00, // 0x200: LD V0, 0x00
+    0x61, 0x01, // 0x202: LD V1, 0x01
+    0x70, 0x03, // 0x204: ADD V0, 0x03   <- loop
+    0x80, 0x14, // 0x206: ADD V0, V1
+    0x82, 0x06, // 0x208: SHR V2, V0
+    0x22, 0x16, // 0x20A: CALL 0x216
+    0xD2, 0x15, // 0x20C: DRW V2, V1, 5
+    0x30, 0xFF, // 0x20E: SE V0, 0xFF
+    0x12, 0x04, // 0x210: JP 0x204
+    0x12, 0x00, // 0x212: JP 0x200
+    0x00, 0x00, // 0x214: (unused)
+    0xA3, 0x00, // 0x216: LD I, 0x300
+    0xF0, 0x33, // 0x218: LD B, V0
+    0xF2, 0x55, // 0x21A: LD [I], V2
+    0x00, 0xEE, // 0x21C: RET
+];
+
+/// Runs the ROM and returns instructions per second
+fn measure(decode_cache: bool) -> f64 {
+    let mut config = MachineConfig::for_profile("chip48").expect("chip48 is a preset");
+    config.cycles_per_frame = CYCLES_PER_FRAME;
+    let mut machine = Machine::new(config);
+    machine.load_rom(&ROM).expect("ROM fits");
+    machine.cpu_mut().set_decode_cache(decode_cache);
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        machine.run_frame().expect("benchmark ROM does not fault");
+    }
+    let instructions = FRAMES as f64 * CYCLES_PER_FRAME as f64;
+    instructions / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    // Warm up caches and the CPU clock before timing
+    measure(true);
+
+    let uncached = measure(false);
+    let cached = measure(true);
+    println!("decode cache off: {:>12.0} instructions/s", uncached);
+    println!("decode cache on:  {:>12.0} instructions/s", cached);
+    println!("speedup:          {:>12.2}x", cached / uncached);
+}
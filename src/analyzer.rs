@@ -0,0 +1,352 @@
+//! Analyzer module for CHIP-8
+//!
+//! Scans the code a ROM can reach (see `Disassembly::trace`) without running
+//! it, and reports which opcodes it uses, which CHIP-8 variant those require,
+//! and which quirks its code looks sensitive to. `Analysis::profile` names the
+//! quirk profile to run it with, so a runner can pick one automatically.
+//!
+//! Quirk sensitivity is a heuristic: after a logic op or Fx55/Fx65, the
+//! straight-line code that follows is checked for a read of the result whose
+//! value depends on the quirk.
+
+use crate::disassembler::Disassembly;
+use crate::instruction::Instruction;
+use crate::variant::Variant;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Instructions followed when looking for a read of a quirk-dependent result
+const LOOKAHEAD: usize = 16;
+
+/// A quirk whose setting changes what a program does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QuirkUse {
+    /// VF is read after 8xy1/8xy2/8xy3 before anything else sets it
+    VfReset,
+    /// 8xy6/8xyE shift with x != y
+    ShiftVx,
+    /// I is used after Fx55/Fx65 without being reloaded
+    Memory,
+    /// Bnnn with a non-zero second nibble (Bxnn reads Vx instead of V0)
+    JumpVx,
+}
+
+impl QuirkUse {
+    /// Name of the `Quirks` field this depends on
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuirkUse::VfReset => "vf_reset",
+            QuirkUse::ShiftVx => "shift_vx",
+            QuirkUse::Memory => "memory",
+            QuirkUse::JumpVx => "jump_vx",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            QuirkUse::VfReset => "VF read after 8xy1/8xy2/8xy3",
+            QuirkUse::ShiftVx => "8xy6/8xyE with x != y",
+            QuirkUse::Memory => "I used after Fx55/Fx65 without reloading",
+            QuirkUse::JumpVx => "Bnnn with nnn >= 0x100",
+        }
+    }
+}
+
+/// Where a quirk-sensitive instruction occurs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occurrences {
+    pub count: usize,
+    /// Address of the first occurrence
+    pub first: u16,
+}
+
+/// Uses of one opcode pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeUse {
+    pub count: usize,
+    /// Address of the first use
+    pub first: u16,
+    /// Oldest variant that defines the pattern
+    pub variant: Variant,
+}
+
+/// What a ROM's reachable code needs to run correctly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analysis {
+    /// Oldest variant that supports every reachable instruction and the ROM's size
+    pub variant: Variant,
+    /// Opcode patterns used (see `Instruction::pattern`), in opcode order
+    pub opcodes: BTreeMap<&'static str, OpcodeUse>,
+    /// Quirks the code looks sensitive to
+    pub quirks: BTreeMap<QuirkUse, Occurrences>,
+    /// Bytes that are part of reachable instructions
+    pub code_bytes: usize,
+    pub data_bytes: usize,
+}
+
+impl Analysis {
+    /// Quirk profile to run the ROM with (see `quirks::PRESET_NAMES`)
+    pub fn profile(&self) -> &'static str {
+        self.variant.default_profile()
+    }
+}
+
+/// Analyzes `rom` loaded at `load_address`
+pub fn analyze(rom: &[u8], load_address: u16) -> Analysis {
+    let disassembly = Disassembly::trace(rom, load_address);
+    let mut opcodes: BTreeMap<&'static str, OpcodeUse> = BTreeMap::new();
+    let mut quirks: BTreeMap<QuirkUse, Occurrences> = BTreeMap::new();
+    // Code or data past 4KB can only be addressed by XO-CHIP
+    let end = load_address as usize + rom.len();
+    let mut variant = if end > Variant::Chip8.memory_size() { Variant::XoChip } else { Variant::Chip8 };
+
+    for (addr, instruction) in disassembly.instructions() {
+        let pattern = instruction.pattern();
+        let required = instruction.required_variant();
+        opcodes
+            .entry(pattern)
+            .and_modify(|used| used.count += 1)
+            .or_insert(OpcodeUse { count: 1, first: addr, variant: required });
+        variant = variant.max(required);
+
+        let next = addr.wrapping_add(instruction.size());
+        let sensitive = match instruction {
+            Instruction::Or { x, .. } | Instruction::And { x, .. } | Instruction::Xor { x, .. } => {
+                let vf_read = |i: &Instruction| reads_register(i, 0xF);
+                let vf_written = |i: &Instruction| writes_register(i, 0xF);
+                (x == 0xF || result_used(&disassembly, next, vf_read, vf_written)).then_some(QuirkUse::VfReset)
+            }
+            Instruction::ShiftRight { x, y } | Instruction::ShiftLeft { x, y } => {
+                (x != y).then_some(QuirkUse::ShiftVx)
+            }
+            Instruction::StoreRegisters { .. } | Instruction::LoadRegisters { .. } => {
+                result_used(&disassembly, next, reads_index, writes_index).then_some(QuirkUse::Memory)
+            }
+            Instruction::JumpOffset(nnn) => (nnn & 0xF00 != 0).then_some(QuirkUse::JumpVx),
+            _ => None,
+        };
+        if let Some(quirk) = sensitive {
+            quirks
+                .entry(quirk)
+                .and_modify(|occurrences| occurrences.count += 1)
+                .or_insert(Occurrences { count: 1, first: addr });
+        }
+    }
+
+    Analysis {
+        variant,
+        opcodes,
+        quirks,
+        code_bytes: disassembly.code_bytes(),
+        data_bytes: disassembly.data_bytes(),
+    }
+}
+
+/// Follows straight-line code from `addr`, returning true if `reads` matches
+/// an instruction before `writes` does
+fn result_used(
+    disassembly: &Disassembly,
+    mut addr: u16,
+    reads: impl Fn(&Instruction) -> bool,
+    writes: impl Fn(&Instruction) -> bool,
+) -> bool {
+    let mut visited = BTreeSet::new();
+    for _ in 0..LOOKAHEAD {
+        let Some(instruction) = disassembly.instruction_at(addr) else {
+            return false;
+        };
+        if !visited.insert(addr) {
+            return false;
+        }
+        if reads(&instruction) {
+            return true;
+        }
+        if writes(&instruction) {
+            return false;
+        }
+        addr = match instruction {
+            Instruction::Jump(target) => target,
+            // Where these go (or what a subroutine does) is beyond a straight-line scan
+            Instruction::Call(_) | Instruction::Return | Instruction::Exit | Instruction::JumpOffset(_) => {
+                return false;
+            }
+            _ => addr.wrapping_add(instruction.size()),
+        };
+    }
+    false
+}
+
+fn reads_index(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Draw { .. }
+            | Instruction::StoreBcd { .. }
+            | Instruction::StoreRegisters { .. }
+            | Instruction::LoadRegisters { .. }
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::AddIndex { .. }
+            | Instruction::Audio
+    )
+}
+
+fn writes_index(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::LoadIndex(_)
+            | Instruction::LoadIndexLong
+            | Instruction::LoadFont { .. }
+            | Instruction::LoadBigFont { .. }
+    )
+}
+
+/// True if the instruction's result depends on register `r`
+fn reads_register(instruction: &Instruction, r: u8) -> bool {
+    let in_range = |x: u8, y: u8| x.min(y) <= r && r <= x.max(y);
+    match *instruction {
+        Instruction::SkipEqByte { x, .. }
+        | Instruction::SkipNeByte { x, .. }
+        | Instruction::AddByte { x, .. }
+        | Instruction::SkipKey { x }
+        | Instruction::SkipNotKey { x }
+        | Instruction::SetDelay { x }
+        | Instruction::SetSound { x }
+        | Instruction::AddIndex { x }
+        | Instruction::LoadFont { x }
+        | Instruction::LoadBigFont { x }
+        | Instruction::StoreBcd { x }
+        | Instruction::Pitch { x } => x == r,
+        Instruction::SkipEqReg { x, y }
+        | Instruction::SkipNeReg { x, y }
+        | Instruction::Or { x, y }
+        | Instruction::And { x, y }
+        | Instruction::Xor { x, y }
+        | Instruction::Add { x, y }
+        | Instruction::Sub { x, y }
+        | Instruction::SubN { x, y }
+        | Instruction::ShiftRight { x, y }
+        | Instruction::ShiftLeft { x, y }
+        | Instruction::Draw { x, y, .. } => x == r || y == r,
+        Instruction::Move { y, .. } => y == r,
+        Instruction::StoreRegisters { x } | Instruction::StoreFlags { x } => r <= x,
+        Instruction::SaveRange { x, y } => in_range(x, y),
+        Instruction::JumpOffset(_) => r == 0,
+        _ => false,
+    }
+}
+
+/// True if the instruction sets register `r` (without reading it first)
+fn writes_register(instruction: &Instruction, r: u8) -> bool {
+    let in_range = |x: u8, y: u8| x.min(y) <= r && r <= x.max(y);
+    match *instruction {
+        Instruction::LoadByte { x, .. }
+        | Instruction::Move { x, .. }
+        | Instruction::Random { x, .. }
+        | Instruction::LoadDelay { x }
+        | Instruction::WaitKey { x } => x == r,
+        // These set VF as a flag
+        Instruction::Add { x, .. }
+        | Instruction::Sub { x, .. }
+        | Instruction::SubN { x, .. }
+        | Instruction::ShiftRight { x, .. }
+        | Instruction::ShiftLeft { x, .. } => x == r || r == 0xF,
+        Instruction::Draw { .. } => r == 0xF,
+        Instruction::LoadRegisters { x } | Instruction::LoadFlags { x } => r <= x,
+        Instruction::LoadRange { x, y } => in_range(x, y),
+        _ => false,
+    }
+}
+
+impl fmt::Display for Analysis {
+    /// Human-readable report: variant, byte counts, opcodes and quirk sensitivity
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Variant: {} (profile: {})", self.variant.name(), self.profile())?;
+        writeln!(f, "Reachable code: {} bytes, data: {} bytes", self.code_bytes, self.data_bytes)?;
+        writeln!(f)?;
+        writeln!(f, "Opcodes used:")?;
+        for (pattern, used) in &self.opcodes {
+            write!(f, "  {}  {:>5}x  first at 0x{:04X}", pattern, used.count, used.first)?;
+            match used.variant {
+                Variant::Chip8 => writeln!(f)?,
+                variant => writeln!(f, "  ({})", variant.name())?,
+            }
+        }
+        writeln!(f)?;
+        if self.quirks.is_empty() {
+            return writeln!(f, "Quirk sensitivity: none found");
+        }
+        writeln!(f, "Quirk sensitivity:")?;
+        for (quirk, occurrences) in &self.quirks {
+            writeln!(
+                f,
+                "  {:<9} {} ({}x, first at 0x{:04X})",
+                quirk.name(),
+                quirk.description(),
+                occurrences.count,
+                occurrences.first
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_chip8_rom() {
+        // 0x200: CLS / 0x202: LD V0, 1 / 0x204: JP 0x204
+        let analysis = analyze(&[0x00, 0xE0, 0x60, 0x01, 0x12, 0x04], 0x200);
+        assert_eq!(analysis.variant, Variant::Chip8);
+        assert_eq!(analysis.profile(), "vip");
+        assert_eq!(analysis.opcodes.keys().copied().collect::<Vec<_>>(), ["00E0", "1nnn", "6xkk"]);
+        assert_eq!(analysis.opcodes["1nnn"], OpcodeUse { count: 1, first: 0x204, variant: Variant::Chip8 });
+        assert!(analysis.quirks.is_empty());
+    }
+
+    #[test]
+    fn test_detects_variant_from_reachable_code_only() {
+        // 0x200: HIGH / 0x202: JP 0x202 / 0x204: F000 0300 (data, never reached)
+        let rom = [0x00, 0xFF, 0x12, 0x02, 0xF0, 0x00, 0x03, 0x00];
+        let analysis = analyze(&rom, 0x200);
+        assert_eq!(analysis.variant, Variant::SuperChip);
+        assert_eq!(analysis.profile(), "schip-modern");
+        assert!(!analysis.opcodes.contains_key("F000"));
+        assert_eq!(analysis.opcodes["00FF"].variant, Variant::SuperChip);
+
+        // 0x200: DRW V0, V0, 0 (16x16) / 0x202: JP 0x202
+        assert_eq!(analyze(&[0xD0, 0x00, 0x12, 0x02], 0x200).variant, Variant::SuperChip);
+        // 0x200: LD I, LONG 0x0300 / 0x204: JP 0x204
+        assert_eq!(analyze(&[0xF0, 0x00, 0x03, 0x00, 0x12, 0x04], 0x200).variant, Variant::XoChip);
+        // Too big for 4KB
+        assert_eq!(analyze(&[0x12, 0x00].repeat(0x800), 0x200).variant, Variant::XoChip);
+    }
+
+    #[test]
+    fn test_detects_quirk_sensitivity() {
+        #[rustfmt::skip]
+        let rom = [
+            0x81, 0x26, // 0x200: SHR V1, V2        shift_vx
+            0x83, 0x36, // 0x202: SHR V3, V3
+            0xF2, 0x55, // 0x204: LD [I], V2
+            0xD0, 0x15, // 0x206: DRW V0, V1, 5     memory: I not reloaded
+            0xF2, 0x65, // 0x208: LD V2, [I]
+            0xA3, 0x00, // 0x20A: LD I, 0x300       reloaded first: fine
+            0xD0, 0x15, // 0x20C: DRW V0, V1, 5
+            0x81, 0x21, // 0x20E: OR V1, V2
+            0x3F, 0x00, // 0x210: SE VF, 0x00       vf_reset
+            0x81, 0x22, // 0x212: AND V1, V2
+            0x6F, 0x01, // 0x214: LD VF, 0x01       overwritten first: fine
+            0xB1, 0x00, // 0x216: JP V0, 0x100      jump_vx
+        ];
+        let analysis = analyze(&rom, 0x200);
+        assert_eq!(analysis.quirks[&QuirkUse::ShiftVx], Occurrences { count: 1, first: 0x200 });
+        assert_eq!(analysis.quirks[&QuirkUse::Memory], Occurrences { count: 1, first: 0x204 });
+        assert_eq!(analysis.quirks[&QuirkUse::VfReset], Occurrences { count: 1, first: 0x20E });
+        assert_eq!(analysis.quirks[&QuirkUse::JumpVx], Occurrences { count: 1, first: 0x216 });
+
+        let report = analysis.to_string();
+        assert!(report.contains("shift_vx  8xy6/8xyE with x != y (1x, first at 0x0200)"), "{}", report);
+    }
+}
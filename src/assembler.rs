@@ -0,0 +1,943 @@
+//! Assembler module for CHIP-8
+//!
+//! Turns assembly source into a ROM. Two syntaxes can be mixed freely:
+//! - The mnemonics the disassembler prints (`LD V1, 0x42`, `DRW V0, V1, 5`),
+//!   written in upper case, one instruction per line, with `name:` labels
+//! - Octo's statements (`v1 := 0x42`, `sprite v0 v1 5`, `if v0 == 3 then jump done`),
+//!   written in lower case, with `: name` labels, `:const`, `:alias`, `:macro`,
+//!   `:byte`, `:org`, and `loop`/`while`/`again` and `if`/`begin`/`else`/`end` blocks
+//!
+//! A program with `: name` labels is laid out like Octo's: execution starts
+//! with a `jump main` at the load address, left out when `main` comes first.
+//!
+//! Comments start with `;`, or with `#` at the start of a word (Octo). In a
+//! program with `: name` labels only `#` starts a comment, and a `;` word is
+//! Octo's shorthand for `return`. A bare
+//! number emits one byte. `0b` literals may use `#` and `.` for set and clear
+//! bits, so sprite rows can be drawn in the source: `0b##..#...`.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+/// Most macro expansions in one program; stops a macro that invokes itself forever
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Instruction mnemonics, as printed by `Instruction`'s `Display`
+/// Upper case only, so Octo's lower case `save` and `load` keep their own meaning.
+const MNEMONICS: [&str; 32] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS", "JP", "CALL", "SE", "SNE", "SAVE", "LOAD",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE", "AUDIO",
+    "PITCH",
+];
+
+/// Non-register operand keywords used by the mnemonics
+const KEYWORDS: [&str; 10] = ["I", "[I]", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+/// A problem in the source, with the line it was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// An assembled program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// Address the ROM must be loaded at
+    pub load_address: u16,
+    /// ROM image, starting at `load_address`
+    pub rom: Vec<u8>,
+    /// Every label and its address
+    pub labels: BTreeMap<String, u16>,
+}
+
+impl Assembly {
+    /// Symbol map text: one `0xADDR name` line per label, in address order
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<(&u16, &String)> = self.labels.iter().map(|(name, addr)| (addr, name)).collect();
+        symbols.sort();
+        symbols.iter().map(|(addr, name)| format!("0x{:04X} {}\n", addr, name)).collect()
+    }
+}
+
+/// Assembles `source` for a ROM loaded at `load_address`
+pub fn assemble(source: &str, load_address: u16) -> Result<Assembly, AssembleError> {
+    let mut assembler = Assembler::new(source, load_address);
+    if assembler.octo {
+        assembler.add_fixup(&Token { text: "main".to_string(), line: 1 }, Patch::Address);
+        assembler.emit(0x1000)?;
+    }
+    while let Some(token) = assembler.tokens.pop_front() {
+        assembler.line = token.line;
+        assembler.statement(token)?;
+    }
+    assembler.finish()
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+/// Splits source into words; commas count as spaces so `LD V1, 0x42` is three words
+/// With `octo` set, `;` is a word (`return`) instead of the start of a comment.
+fn tokenize(source: &str, octo: bool) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = if octo { line } else { line.split(';').next().unwrap_or("") };
+        for word in code.split(|c: char| c.is_whitespace() || c == ',') {
+            if word.starts_with('#') {
+                break;
+            }
+            if !word.is_empty() {
+                let text = if word == ";" { "return" } else { word };
+                tokens.push_back(Token { text: text.to_string(), line: index + 1 });
+            }
+        }
+    }
+    tokens
+}
+
+/// Parses a decimal, `0x` hex or `0b` binary literal (binary may use `#` and `.`)
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bits) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        let bits: String = bits.chars().map(|c| if c == '#' { '1' } else if c == '.' { '0' } else { c }).collect();
+        i64::from_str_radix(&bits, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Where a label's address goes once it is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Patch {
+    /// Low 12 bits of the opcode at the offset
+    Address,
+    /// The 16-bit word at the offset (F000 nnnn)
+    Long,
+}
+
+struct Fixup {
+    offset: usize,
+    name: String,
+    patch: Patch,
+    line: usize,
+}
+
+/// An operand of a mnemonic
+enum Arg {
+    Reg(u8),
+    Keyword(&'static str),
+    Value(Token),
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An open `loop`: its start and the `while` jumps that leave it
+struct Loop {
+    start: u16,
+    exits: Vec<usize>,
+    line: usize,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    load_address: u16,
+    rom: Vec<u8>,
+    /// Offset in `rom` of the next byte
+    position: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    /// Offsets of the jumps opened by `begin` or `else`, waiting for `else` or `end`
+    blocks: Vec<(usize, usize)>,
+    expansions: usize,
+    /// Octo layout: the program starts with `jump main` (see the module docs)
+    octo: bool,
+    /// Line of the statement being assembled
+    line: usize,
+}
+
+impl Assembler {
+    fn new(source: &str, load_address: u16) -> Self {
+        let octo = tokenize(source, false).iter().any(|token| token.text == ":");
+        Assembler {
+            octo,
+            tokens: tokenize(source, octo),
+            load_address,
+            rom: Vec::new(),
+            position: 0,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+            line: 0,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, AssembleError> {
+        Err(AssembleError { line: self.line, message })
+    }
+
+    /// Takes the next word, which `what` must describe
+    fn expect(&mut self, what: &str) -> Result<Token, AssembleError> {
+        match self.tokens.pop_front() {
+            Some(token) => Ok(token),
+            None => self.error(format!("Expected {} at end of input", what)),
+        }
+    }
+
+    /// Takes the next word and checks it is `word`
+    fn expect_word(&mut self, word: &str) -> Result<(), AssembleError> {
+        let token = self.expect(&format!("'{}'", word))?;
+        if token.text != word {
+            return self.error(format!("Expected '{}', found '{}'", word, token.text));
+        }
+        Ok(())
+    }
+
+    /// Address the next byte is assembled at
+    fn address(&self) -> Result<u16, AssembleError> {
+        match u16::try_from(self.load_address as usize + self.position) {
+            Ok(addr) => Ok(addr),
+            Err(_) => self.error("Program runs past the end of the address space".to_string()),
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AssembleError> {
+        self.address()?;
+        if self.position < self.rom.len() {
+            self.rom[self.position] = byte;
+        } else {
+            self.rom.resize(self.position, 0);
+            self.rom.push(byte);
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), AssembleError> {
+        self.emit_byte((opcode >> 8) as u8)?;
+        self.emit_byte(opcode as u8)
+    }
+
+    /// Emits `base | nnn` for an address operand, patching in labels later
+    fn emit_target(&mut self, base: u16, token: &Token) -> Result<(), AssembleError> {
+        if let Some(value) = self.constant(token) {
+            if !(0..=0xFFF).contains(&value) {
+                return self.error(format!("Address {} does not fit in 12 bits", token.text));
+            }
+            return self.emit(base | value as u16);
+        }
+        self.add_fixup(token, Patch::Address);
+        self.emit(base)
+    }
+
+    fn add_fixup(&mut self, token: &Token, patch: Patch) {
+        self.fixups.push(Fixup { offset: self.position, name: token.text.clone(), patch, line: token.line });
+    }
+
+    /// Emits a placeholder jump and returns its offset for `patch_jump`
+    fn emit_jump_placeholder(&mut self) -> Result<usize, AssembleError> {
+        let offset = self.position;
+        self.emit(0x1000)?;
+        Ok(offset)
+    }
+
+    /// Points the jump at `offset` to the current address
+    fn patch_jump(&mut self, offset: usize) -> Result<(), AssembleError> {
+        let target = self.address()?;
+        if target > 0xFFF {
+            return self.error(format!("Jump target {:#06x} does not fit in 12 bits", target));
+        }
+        self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8;
+        self.rom[offset + 1] = target as u8;
+        Ok(())
+    }
+
+    /// Value of a number literal or `:const` name
+    fn constant(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+    }
+
+    fn value(&self, token: &Token, min: i64, max: i64, what: &str) -> Result<i64, AssembleError> {
+        match self.constant(token) {
+            Some(value) if (min..=max).contains(&value) => Ok(value),
+            Some(_) => self.error(format!("{} {} is out of range", what, token.text)),
+            None => self.error(format!("Expected a {}, found '{}'", what.to_lowercase(), token.text)),
+        }
+    }
+
+    /// A byte operand; negative values are stored as two's complement
+    fn byte(&self, token: &Token) -> Result<u16, AssembleError> {
+        Ok(self.value(token, -128, 255, "Byte")? as u8 as u16)
+    }
+
+    fn nibble(&self, token: &Token) -> Result<u16, AssembleError> {
+        Ok(self.value(token, 0, 15, "Nibble")? as u16)
+    }
+
+    /// Register number of `v0`-`vF` (either case) or an `:alias`
+    fn register(&self, token: &Token) -> Option<u8> {
+        let text = &token.text;
+        if text.len() == 2 && text.starts_with(['v', 'V']) {
+            return u8::from_str_radix(&text[1..], 16).ok();
+        }
+        self.aliases.get(text).copied()
+    }
+
+    fn expect_register(&mut self) -> Result<u16, AssembleError> {
+        let token = self.expect("a register")?;
+        match self.register(&token) {
+            Some(reg) => Ok(reg as u16),
+            None => self.error(format!("Expected a register, found '{}'", token.text)),
+        }
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), AssembleError> {
+        if name.is_empty() || parse_number(name).is_some() || name.starts_with(':') {
+            return self.error(format!("Invalid label name '{}'", name));
+        }
+        let mut addr = self.address()?;
+        if self.octo && name == "main" && self.rom.len() == 2 && self.position == 2 {
+            // Octo drops the `jump main` when main would follow it directly
+            self.fixups.retain(|fixup| fixup.offset != 0);
+            self.rom.clear();
+            self.position = 0;
+            for label in self.labels.values_mut().filter(|label| **label == addr) {
+                *label = self.load_address;
+            }
+            addr = self.load_address;
+        }
+        if self.labels.insert(name.to_string(), addr).is_some() {
+            return self.error(format!("Label '{}' is defined twice", name));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        let text = token.text.as_str();
+        if MNEMONICS.contains(&text) {
+            return self.mnemonic(token);
+        }
+        if let Some(name) = text.strip_suffix(':').filter(|_| !text.starts_with(':')) {
+            return self.define_label(name);
+        }
+        if let Some(x) = self.register(&token) {
+            return self.assignment(x as u16);
+        }
+
+        match text {
+            ":" => {
+                let name = self.expect("a label name")?;
+                self.define_label(&name.text)
+            }
+            ":const" => {
+                let name = self.expect("a constant name")?;
+                let value = self.expect("a value")?;
+                let value = self.value(&value, -0x8000, 0xFFFF, "Value")?;
+                self.constants.insert(name.text, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.expect("an alias name")?;
+                let reg = self.expect_register()?;
+                self.aliases.insert(name.text, reg as u8);
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            ":byte" => {
+                let value = self.expect("a byte")?;
+                let byte = self.byte(&value)?;
+                self.emit_byte(byte as u8)
+            }
+            ":org" => {
+                let value = self.expect("an address")?;
+                let addr = self.value(&value, self.load_address as i64, 0xFFFF, "Address")?;
+                self.position = (addr - self.load_address as i64) as usize;
+                Ok(())
+            }
+            ":call" => {
+                let target = self.expect("an address")?;
+                self.emit_target(0x2000, &target)
+            }
+            "clear" => self.emit(0x00E0),
+            "return" => self.emit(0x00EE),
+            "scroll-down" => {
+                let n = self.expect("a nibble")?;
+                let n = self.nibble(&n)?;
+                self.emit(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.expect("a nibble")?;
+                let n = self.nibble(&n)?;
+                self.emit(0x00D0 | n)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "exit" => self.emit(0x00FD),
+            "lores" => self.emit(0x00FE),
+            "hires" => self.emit(0x00FF),
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let n = self.expect("a nibble")?;
+                let n = self.nibble(&n)?;
+                self.emit(0xF001 | n << 8)
+            }
+            "native" | "jump" | "jump0" => {
+                let base = match text {
+                    "native" => 0x0000,
+                    "jump" => 0x1000,
+                    _ => 0xB000,
+                };
+                let target = self.expect("an address")?;
+                self.emit_target(base, &target)
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect("a nibble")?;
+                let n = self.nibble(&n)?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)
+            }
+            "bcd" | "saveflags" | "loadflags" => {
+                let x = self.expect_register()?;
+                let low = match text {
+                    "bcd" => 0x33,
+                    "saveflags" => 0x75,
+                    _ => 0x85,
+                };
+                self.emit(0xF000 | x << 8 | low)
+            }
+            "save" | "load" => {
+                let x = self.expect_register()?;
+                if self.tokens.front().is_some_and(|next| next.text == "-") {
+                    // save vx - vy / load vx - vy (XO-CHIP)
+                    self.tokens.pop_front();
+                    let y = self.expect_register()?;
+                    self.emit(0x5000 | x << 8 | y << 4 | if text == "save" { 2 } else { 3 })
+                } else {
+                    self.emit(0xF000 | x << 8 | if text == "save" { 0x55 } else { 0x65 })
+                }
+            }
+            "i" => self.index_assignment(),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect_word(":=")?;
+                let x = self.expect_register()?;
+                let low = match text {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit(0xF000 | x << 8 | low)
+            }
+            "if" => {
+                let (skip_if_true, skip_if_false) = self.condition()?;
+                let keyword = self.expect("'then' or 'begin'")?;
+                match keyword.text.as_str() {
+                    "then" => self.emit(skip_if_false),
+                    "begin" => {
+                        self.emit(skip_if_true)?;
+                        let jump = self.emit_jump_placeholder()?;
+                        self.blocks.push((jump, token.line));
+                        Ok(())
+                    }
+                    other => self.error(format!("Expected 'then' or 'begin', found '{}'", other)),
+                }
+            }
+            "else" => {
+                let Some((jump, line)) = self.blocks.pop() else {
+                    return self.error("'else' without 'if ... begin'".to_string());
+                };
+                let skip_else = self.emit_jump_placeholder()?;
+                self.patch_jump(jump)?;
+                self.blocks.push((skip_else, line));
+                Ok(())
+            }
+            "end" => {
+                let Some((jump, _)) = self.blocks.pop() else {
+                    return self.error("'end' without 'if ... begin'".to_string());
+                };
+                self.patch_jump(jump)
+            }
+            "loop" => {
+                let start = self.address()?;
+                self.loops.push(Loop { start, exits: Vec::new(), line: token.line });
+                Ok(())
+            }
+            "while" => {
+                let (skip_if_true, _) = self.condition()?;
+                self.emit(skip_if_true)?;
+                let exit = self.emit_jump_placeholder()?;
+                match self.loops.last_mut() {
+                    Some(open) => {
+                        open.exits.push(exit);
+                        Ok(())
+                    }
+                    None => self.error("'while' outside 'loop ... again'".to_string()),
+                }
+            }
+            "again" => {
+                let Some(open) = self.loops.pop() else {
+                    return self.error("'again' without 'loop'".to_string());
+                };
+                if open.start > 0xFFF {
+                    return self.error(format!("Loop start {:#06x} does not fit in 12 bits", open.start));
+                }
+                self.emit(0x1000 | open.start)?;
+                for exit in open.exits {
+                    self.patch_jump(exit)?;
+                }
+                Ok(())
+            }
+            _ => {
+                if self.constant(&token).is_some() {
+                    // Octo: a bare number is a byte of data
+                    let byte = self.byte(&token)?;
+                    return self.emit_byte(byte as u8);
+                }
+                if self.macros.contains_key(text) {
+                    return self.expand_macro(&token);
+                }
+                if text.starts_with(':') || text.contains(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')) {
+                    return self.error(format!("Unknown statement '{}'", text));
+                }
+                // Octo: a bare label name calls it
+                self.emit_target(0x2000, &token)
+            }
+        }
+    }
+
+    /// `vx := ...`, `vx += ...` and the other register operators (Octo)
+    fn assignment(&mut self, x: u16) -> Result<(), AssembleError> {
+        let op = self.expect("an operator")?;
+        let rhs = self.expect("an operand")?;
+        let y = self.register(&rhs).map(|reg| reg as u16);
+        let register_op = |low: u16| 0x8000 | x << 8 | y.unwrap_or(0) << 4 | low;
+        match (op.text.as_str(), y) {
+            (":=", Some(_)) => self.emit(register_op(0x0)),
+            ("|=", Some(_)) => self.emit(register_op(0x1)),
+            ("&=", Some(_)) => self.emit(register_op(0x2)),
+            ("^=", Some(_)) => self.emit(register_op(0x3)),
+            ("+=", Some(_)) => self.emit(register_op(0x4)),
+            ("-=", Some(_)) => self.emit(register_op(0x5)),
+            (">>=", Some(_)) => self.emit(register_op(0x6)),
+            ("=-", Some(_)) => self.emit(register_op(0x7)),
+            ("<<=", Some(_)) => self.emit(register_op(0xE)),
+            (":=", None) => match rhs.text.as_str() {
+                "random" => {
+                    let mask = self.expect("a byte")?;
+                    let mask = self.byte(&mask)?;
+                    self.emit(0xC000 | x << 8 | mask)
+                }
+                "delay" => self.emit(0xF007 | x << 8),
+                "key" => self.emit(0xF00A | x << 8),
+                _ => {
+                    let kk = self.byte(&rhs)?;
+                    self.emit(0x6000 | x << 8 | kk)
+                }
+            },
+            ("+=", None) => {
+                let kk = self.byte(&rhs)?;
+                self.emit(0x7000 | x << 8 | kk)
+            }
+            (other, _) => self.error(format!("Unsupported operator '{}' for v{:X}", other, x)),
+        }
+    }
+
+    /// `i := addr`, `i := long addr`, `i := hex vx`, `i := bighex vx`, `i += vx` (Octo)
+    fn index_assignment(&mut self) -> Result<(), AssembleError> {
+        let op = self.expect("an operator")?;
+        match op.text.as_str() {
+            ":=" => {
+                let rhs = self.expect("an address")?;
+                match rhs.text.as_str() {
+                    "hex" => {
+                        let x = self.expect_register()?;
+                        self.emit(0xF029 | x << 8)
+                    }
+                    "bighex" => {
+                        let x = self.expect_register()?;
+                        self.emit(0xF030 | x << 8)
+                    }
+                    "long" => {
+                        let target = self.expect("an address")?;
+                        self.emit_long(&target)
+                    }
+                    _ => self.emit_target(0xA000, &rhs),
+                }
+            }
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(0xF01E | x << 8)
+            }
+            other => self.error(format!("Unsupported operator '{}' for i", other)),
+        }
+    }
+
+    /// F000 nnnn: LD I with a 16-bit address (XO-CHIP)
+    fn emit_long(&mut self, target: &Token) -> Result<(), AssembleError> {
+        match self.constant(target) {
+            Some(_) => {
+                let addr = self.value(target, 0, 0xFFFF, "Address")?;
+                self.emit(0xF000)?;
+                self.emit(addr as u16)
+            }
+            None => {
+                self.add_fixup(target, Patch::Long);
+                self.emit(0xF000)?;
+                self.emit(0)
+            }
+        }
+    }
+
+    /// Parses `vx == kk`, `vx != vy`, `vx key`, `vx -key`
+    /// Returns the opcodes that skip when the condition holds and when it does not.
+    fn condition(&mut self) -> Result<(u16, u16), AssembleError> {
+        let x = self.expect_register()?;
+        let op = self.expect("a comparison")?;
+        match op.text.as_str() {
+            "key" => Ok((0xE09E | x << 8, 0xE0A1 | x << 8)),
+            "-key" => Ok((0xE0A1 | x << 8, 0xE09E | x << 8)),
+            "==" | "!=" => {
+                let rhs = self.expect("an operand")?;
+                let (equal, not_equal) = match self.register(&rhs) {
+                    Some(y) => (0x5000 | x << 8 | (y as u16) << 4, 0x9000 | x << 8 | (y as u16) << 4),
+                    None => {
+                        let kk = self.byte(&rhs)?;
+                        (0x3000 | x << 8 | kk, 0x4000 | x << 8 | kk)
+                    }
+                };
+                Ok(if op.text == "==" { (equal, not_equal) } else { (not_equal, equal) })
+            }
+            other => self.error(format!("Unsupported comparison '{}'", other)),
+        }
+    }
+
+    /// `:macro name params... { body }`
+    fn define_macro(&mut self) -> Result<(), AssembleError> {
+        let name = self.expect("a macro name")?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.expect("'{'")?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.expect("'}'")?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces a macro invocation with its body, substituting the arguments
+    fn expand_macro(&mut self, name: &Token) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return self.error(format!("Too many macro expansions (is '{}' recursive?)", name.text));
+        }
+        let params = self.macros[&name.text].params.clone();
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.expect(&format!("argument '{}' of macro '{}'", param, name.text))?;
+            args.insert(param, arg.text);
+        }
+        let body = &self.macros[&name.text].body;
+        for token in body.iter().rev() {
+            let text = args.get(&token.text).cloned().unwrap_or_else(|| token.text.clone());
+            self.tokens.push_front(Token { text, line: token.line });
+        }
+        Ok(())
+    }
+
+    /// An upper case mnemonic; its operands are the rest of the line
+    fn mnemonic(&mut self, name: Token) -> Result<(), AssembleError> {
+        let mut args = Vec::new();
+        while let Some(next) = self.tokens.front() {
+            if next.line != name.line || MNEMONICS.contains(&next.text.as_str()) {
+                break;
+            }
+            let token = self.tokens.pop_front().expect("front exists");
+            let upper = token.text.to_ascii_uppercase();
+            args.push(match (self.register(&token), KEYWORDS.iter().find(|&&word| word == upper)) {
+                (Some(reg), _) => Arg::Reg(reg),
+                (None, Some(keyword)) => Arg::Keyword(keyword),
+                (None, None) => Arg::Value(token),
+            });
+        }
+
+        use Arg::{Keyword, Reg, Value};
+        let xy = |x: &u8, y: &u8| (*x as u16) << 8 | (*y as u16) << 4;
+        let x = |x: &u8| (*x as u16) << 8;
+        let opcode = match (name.text.as_str(), args.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("AUDIO", []) => 0xF002,
+            ("SCD", [Value(n)]) => 0x00C0 | self.nibble(n)?,
+            ("SCU", [Value(n)]) => 0x00D0 | self.nibble(n)?,
+            ("PLANE", [Value(n)]) => 0xF001 | self.nibble(n)? << 8,
+            ("SYS", [Value(target)]) => return self.emit_target(0x0000, target),
+            ("JP", [Value(target)]) => return self.emit_target(0x1000, target),
+            ("JP", [Reg(0), Value(target)]) => return self.emit_target(0xB000, target),
+            ("CALL", [Value(target)]) => return self.emit_target(0x2000, target),
+            ("SE", [Reg(vx), Reg(vy)]) => 0x5000 | xy(vx, vy),
+            ("SE", [Reg(vx), Value(kk)]) => 0x3000 | x(vx) | self.byte(kk)?,
+            ("SNE", [Reg(vx), Reg(vy)]) => 0x9000 | xy(vx, vy),
+            ("SNE", [Reg(vx), Value(kk)]) => 0x4000 | x(vx) | self.byte(kk)?,
+            ("SAVE", [Reg(vx), Reg(vy)]) => 0x5002 | xy(vx, vy),
+            ("LOAD", [Reg(vx), Reg(vy)]) => 0x5003 | xy(vx, vy),
+            ("LD", [Reg(vx), Reg(vy)]) => 0x8000 | xy(vx, vy),
+            ("LD", [Reg(vx), Keyword("DT")]) => 0xF007 | x(vx),
+            ("LD", [Reg(vx), Keyword("K")]) => 0xF00A | x(vx),
+            ("LD", [Reg(vx), Keyword("[I]")]) => 0xF065 | x(vx),
+            ("LD", [Reg(vx), Keyword("R")]) => 0xF085 | x(vx),
+            ("LD", [Reg(vx), Value(kk)]) => 0x6000 | x(vx) | self.byte(kk)?,
+            ("LD", [Keyword("I"), Keyword("LONG"), Value(target)]) => return self.emit_long(target),
+            ("LD", [Keyword("I"), Value(target)]) => return self.emit_target(0xA000, target),
+            ("LD", [Keyword("DT"), Reg(vx)]) => 0xF015 | x(vx),
+            ("LD", [Keyword("ST"), Reg(vx)]) => 0xF018 | x(vx),
+            ("LD", [Keyword("F"), Reg(vx)]) => 0xF029 | x(vx),
+            ("LD", [Keyword("HF"), Reg(vx)]) => 0xF030 | x(vx),
+            ("LD", [Keyword("B"), Reg(vx)]) => 0xF033 | x(vx),
+            ("LD", [Keyword("[I]"), Reg(vx)]) => 0xF055 | x(vx),
+            ("LD", [Keyword("R"), Reg(vx)]) => 0xF075 | x(vx),
+            ("ADD", [Reg(vx), Reg(vy)]) => 0x8004 | xy(vx, vy),
+            ("ADD", [Reg(vx), Value(kk)]) => 0x7000 | x(vx) | self.byte(kk)?,
+            ("ADD", [Keyword("I"), Reg(vx)]) => 0xF01E | x(vx),
+            ("OR", [Reg(vx), Reg(vy)]) => 0x8001 | xy(vx, vy),
+            ("AND", [Reg(vx), Reg(vy)]) => 0x8002 | xy(vx, vy),
+            ("XOR", [Reg(vx), Reg(vy)]) => 0x8003 | xy(vx, vy),
+            ("SUB", [Reg(vx), Reg(vy)]) => 0x8005 | xy(vx, vy),
+            ("SUBN", [Reg(vx), Reg(vy)]) => 0x8007 | xy(vx, vy),
+            // The one-operand form shifts Vx in place under either shift quirk
+            ("SHR", [Reg(vx)]) => 0x8006 | xy(vx, vx),
+            ("SHR", [Reg(vx), Reg(vy)]) => 0x8006 | xy(vx, vy),
+            ("SHL", [Reg(vx)]) => 0x800E | xy(vx, vx),
+            ("SHL", [Reg(vx), Reg(vy)]) => 0x800E | xy(vx, vy),
+            ("RND", [Reg(vx), Value(kk)]) => 0xC000 | x(vx) | self.byte(kk)?,
+            ("DRW", [Reg(vx), Reg(vy), Value(n)]) => 0xD000 | xy(vx, vy) | self.nibble(n)?,
+            ("SKP", [Reg(vx)]) => 0xE09E | x(vx),
+            ("SKNP", [Reg(vx)]) => 0xE0A1 | x(vx),
+            ("PITCH", [Reg(vx)]) => 0xF03A | x(vx),
+            (mnemonic, _) => return self.error(format!("Invalid operands for {}", mnemonic)),
+        };
+        self.emit(opcode)
+    }
+
+    /// Checks that every block was closed and patches in label addresses
+    fn finish(mut self) -> Result<Assembly, AssembleError> {
+        if let Some(open) = self.loops.last() {
+            return Err(AssembleError { line: open.line, message: "'loop' without 'again'".to_string() });
+        }
+        if let Some(&(_, line)) = self.blocks.last() {
+            return Err(AssembleError { line, message: "'begin' without 'end'".to_string() });
+        }
+        if self.octo && !self.labels.contains_key("main") {
+            return Err(AssembleError { line: 1, message: "Octo programs need a ': main' label".to_string() });
+        }
+
+        for fixup in &self.fixups {
+            let error = |message: String| Err(AssembleError { line: fixup.line, message });
+            let Some(&addr) = self.labels.get(&fixup.name) else {
+                return error(format!("Undefined label '{}'", fixup.name));
+            };
+            match fixup.patch {
+                Patch::Address if addr > 0xFFF => {
+                    return error(format!("Label '{}' at {:#06x} does not fit in 12 bits", fixup.name, addr));
+                }
+                Patch::Address => {
+                    self.rom[fixup.offset] |= (addr >> 8) as u8;
+                    self.rom[fixup.offset + 1] |= addr as u8;
+                }
+                Patch::Long => {
+                    self.rom[fixup.offset + 2] = (addr >> 8) as u8;
+                    self.rom[fixup.offset + 3] = addr as u8;
+                }
+            }
+        }
+
+        Ok(Assembly { load_address: self.load_address, rom: self.rom, labels: self.labels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction;
+    use crate::variant::Variant;
+
+    fn rom(source: &str) -> Vec<u8> {
+        assemble(source, 0x200).unwrap_or_else(|e| panic!("{}", e)).rom
+    }
+
+    #[test]
+    fn test_assembles_every_disassembled_instruction() {
+        for opcode in 0..=0xFFFF {
+            let Ok(instruction) = Instruction::decode(opcode, Variant::XoChip) else {
+                continue;
+            };
+            if instruction == Instruction::LoadIndexLong {
+                continue; // Needs its address word; covered below
+            }
+            assert_eq!(rom(&instruction.to_string()), opcode.to_be_bytes(), "{}", instruction);
+        }
+        assert_eq!(rom("LD I, LONG 0x1234"), [0xF0, 0x00, 0x12, 0x34]);
+        assert_eq!(rom("here: LD I, LONG here"), [0xF0, 0x00, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn test_mnemonic_labels() {
+        let assembly = assemble("start:\n  CALL sub\n  JP start\nsub: LD I, data\n  RET\ndata:\n  0xF0", 0x200).unwrap();
+        assert_eq!(assembly.rom, [0x22, 0x04, 0x12, 0x00, 0xA2, 0x08, 0x00, 0xEE, 0xF0]);
+        assert_eq!(assembly.symbol_map(), "0x0200 start\n0x0204 sub\n0x0208 data\n");
+    }
+
+    #[test]
+    fn test_octo_statements() {
+        let source = "
+            :const SPEED 3
+            :alias px v1
+            : main
+                clear
+                px := 0
+                i := ball           # Octo comment
+                loop
+                    px += SPEED
+                    v0 := random 0x0F
+                    if v0 == 5 then px := 1
+                    sprite px v2 2
+                    while v1 != 60
+                again
+                if v3 key begin
+                    v4 := delay
+                else
+                    buzzer := v4
+                end
+                v5 >>= v6
+                save v2 - v3
+                exit
+            : ball
+                0b##...... 0b##......
+            : blink sprite v0 v1 8 ;
+        ";
+        assert_eq!(
+            rom(source),
+            [
+                0x00, 0xE0, // clear
+                0x61, 0x00, // px := 0
+                0xA2, 0x26, // i := ball
+                0x71, 0x03, // 0x206: px += SPEED
+                0xC0, 0x0F, // v0 := random 0x0F
+                0x40, 0x05, // if v0 == 5 then (skip when not equal)
+                0x61, 0x01, // px := 1
+                0xD1, 0x22, // sprite px v2 2
+                0x41, 0x3C, // while v1 != 60 (skip the exit jump when v1 != 60)
+                0x12, 0x16, // jump past again
+                0x12, 0x06, // again
+                0xE3, 0x9E, // 0x216: if v3 key begin (skip the else jump when pressed)
+                0x12, 0x1E, // jump to else
+                0xF4, 0x07, // v4 := delay
+                0x12, 0x20, // jump past end
+                0xF4, 0x18, // 0x21E: buzzer := v4
+                0x85, 0x66, // 0x220: v5 >>= v6
+                0x52, 0x32, // save v2 - v3
+                0x00, 0xFD, // exit
+                0xC0, 0xC0, // 0x226: ball
+                0xD0, 0x18, // 0x228: blink
+                0x00, 0xEE, // ;
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macros_and_octo_calls() {
+        let source = "
+            :macro twice op reg { op reg op reg }
+            : main
+                twice bcd v3
+                draw
+                return
+            : draw
+                i := long 0x8000
+                :byte 0xAB
+        ";
+        assert_eq!(
+            rom(source),
+            [0xF3, 0x33, 0xF3, 0x33, 0x22, 0x08, 0x00, 0xEE, 0xF0, 0x00, 0x80, 0x00, 0xAB]
+        );
+    }
+
+    #[test]
+    fn test_octo_jumps_to_main() {
+        // Data before main: the program starts with a jump over it
+        let assembly = assemble(": spr 0xFF 0x81\n: main\n  i := spr\n  sprite v0 v0 2", 0x200).unwrap();
+        assert_eq!(assembly.rom, [0x12, 0x04, 0xFF, 0x81, 0xA2, 0x02, 0xD0, 0x02]);
+        assert_eq!(assembly.labels["main"], 0x204);
+
+        // main first: no jump
+        assert_eq!(rom(":const N 1\n: main\n  v0 := N"), [0x60, 0x01]);
+        assert_eq!(
+            assemble(": sub\n  return", 0x200).unwrap_err().message,
+            "Octo programs need a ': main' label"
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source: &str| assemble(source, 0x200).unwrap_err();
+        assert_eq!(error("CLS\nJP nowhere"), AssembleError { line: 2, message: "Undefined label 'nowhere'".to_string() });
+        assert_eq!(error("LD V1, 0x100").message, "Byte 0x100 is out of range");
+        assert_eq!(error("\n\nDRW V1, 5").to_string(), "line 3: Invalid operands for DRW");
+        assert_eq!(error("loop\nv0 += 1").message, "'loop' without 'again'");
+        assert_eq!(error("a:\na:").message, "Label 'a' is defined twice");
+        assert_eq!(error(":macro m { m }\nm").message, "Too many macro expansions (is 'm' recursive?)");
+        assert_eq!(error(":org 0x1200\nloop\nagain").message, "Loop start 0x1200 does not fit in 12 bits");
+    }
+}
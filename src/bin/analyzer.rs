@@ -0,0 +1,53 @@
+//! CHIP-8 ROM Analyzer
+//!
+//! Reports which opcodes a ROM's reachable code uses, the CHIP-8 variant
+//! they require, the quirks the code looks sensitive to, and the quirk
+//! profile to run it with. The ROM is never executed.
+
+use chip8_emulator::analyzer::analyze;
+use chip8_emulator::memory::PROGRAM_START;
+use std::env;
+use std::fs;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // Usage: analyzer <rom_file> [--load-address <addr>]
+    let mut rom_path: Option<&String> = None;
+    let mut load_address = PROGRAM_START;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--load-address" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            load_address = u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                .unwrap_or_else(|_| fail(&format!("Invalid load address '{}'. Expected hex, e.g. 0x600", text)));
+        } else if rom_path.is_none() {
+            rom_path = Some(arg);
+        } else {
+            fail(&format!("Unexpected argument '{}'", arg));
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        println!("CHIP-8 ROM Analyzer");
+        println!("===================");
+        println!();
+        println!("Usage: {} <rom_file> [options]", args[0]);
+        println!();
+        println!("Options:");
+        println!("  --load-address <addr>      Load address and entry point in hex (default: 0x200)");
+        println!();
+        println!("Example: {} roms/pong.ch8", args[0]);
+        process::exit(1);
+    };
+
+    let rom_data = fs::read(rom_path).unwrap_or_else(|e| fail(&format!("Error loading ROM '{}': {}", rom_path, e)));
+    println!("ROM: {} ({} bytes)", rom_path, rom_data.len());
+    print!("{}", analyze(&rom_data, load_address));
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,82 @@
+//! CHIP-8 Assembler
+//!
+//! Assembles a source file (disassembler mnemonics or Octo syntax) into a
+//! `.ch8` ROM and writes a symbol map with the address of every label.
+
+use chip8_emulator::assembler::assemble;
+use chip8_emulator::memory::PROGRAM_START;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // Usage: assembler <source> [-o <rom>] [--symbols <file>] [--load-address <addr>]
+    let mut source_path: Option<&String> = None;
+    let mut rom_path: Option<&String> = None;
+    let mut symbols_path: Option<&String> = None;
+    let mut load_address = PROGRAM_START;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "-o" || arg == "--output" {
+            rom_path = arg_iter.next();
+        } else if arg == "--symbols" {
+            symbols_path = arg_iter.next();
+        } else if arg == "--load-address" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            load_address = u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                .unwrap_or_else(|_| fail(&format!("Invalid load address '{}'. Expected hex, e.g. 0x600", text)));
+        } else if source_path.is_none() {
+            source_path = Some(arg);
+        } else {
+            fail(&format!("Unexpected argument '{}'", arg));
+        }
+    }
+
+    let Some(source_path) = source_path else {
+        println!("CHIP-8 Assembler");
+        println!("================");
+        println!();
+        println!("Usage: {} <source> [options]", args[0]);
+        println!();
+        println!("Options:");
+        println!("  -o, --output <rom>         ROM to write (default: the source with a .ch8 extension)");
+        println!("  --symbols <file>           Symbol map to write (default: the ROM with a .sym extension)");
+        println!("  --load-address <addr>      Load address in hex (default: 0x200)");
+        println!();
+        println!("Example: {} game.8o -o game.ch8", args[0]);
+        process::exit(1);
+    };
+
+    let source = fs::read_to_string(source_path)
+        .unwrap_or_else(|e| fail(&format!("Error reading '{}': {}", source_path, e)));
+    let assembly = assemble(&source, load_address).unwrap_or_else(|e| fail(&format!("{}: {}", source_path, e)));
+
+    let rom_path = match rom_path {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => Path::new(source_path).with_extension("ch8"),
+    };
+    let symbols_path = match symbols_path {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => rom_path.with_extension("sym"),
+    };
+    fs::write(&rom_path, &assembly.rom)
+        .unwrap_or_else(|e| fail(&format!("Error writing '{}': {}", rom_path.display(), e)));
+    fs::write(&symbols_path, assembly.symbol_map())
+        .unwrap_or_else(|e| fail(&format!("Error writing '{}': {}", symbols_path.display(), e)));
+
+    println!(
+        "Assembled {} bytes and {} labels to {} (symbols: {})",
+        assembly.rom.len(),
+        assembly.labels.len(),
+        rom_path.display(),
+        symbols_path.display()
+    );
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
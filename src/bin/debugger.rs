@@ -0,0 +1,322 @@
+//! CHIP-8 Debugger
+//!
+//! Stdin-driven debugger REPL: breakpoints, watchpoints, register conditions,
+//! single-stepping and memory/disassembly views. Runs without a window.
+//! With `--symbols <file>` addresses print as labels and commands accept them.
+//! With `--gdb <port>` it serves the GDB remote protocol instead.
+
+use chip8_emulator::debugger::{BreakReason, Command, Debugger, COMMAND_HELP};
+use chip8_emulator::gdbstub::GdbStub;
+use chip8_emulator::machine::{Machine, MachineConfig};
+use chip8_emulator::memory::{AddressPolicy, POLICY_NAMES};
+use chip8_emulator::quirks::PRESET_NAMES;
+use chip8_emulator::rng::{RngKind, RNG_NAMES};
+use chip8_emulator::symbols::Symbols;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::net::TcpListener;
+use std::process;
+
+/// Frames `continue`, `next` and `finish` run before giving up (one minute at 60 Hz)
+const DEFAULT_MAX_FRAMES: u64 = 3600;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // Parse command line: <rom_file> [--quirks <profile>] [--memory-policy <policy>] [--load-address <addr>]
+    //                     [--gdb <port>] [--seed <n>] [--rng <generator>] [--symbols <file>]
+    let mut rom_path: Option<&String> = None;
+    let mut symbols_path: Option<&String> = None;
+    let mut config = MachineConfig::default();
+    let mut gdb_port: Option<u16> = None;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--quirks" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            let Some(profile) = MachineConfig::for_profile(name) else {
+                fail(&format!("Unknown quirk profile '{}'. Available: {}", name, PRESET_NAMES.join(", ")));
+            };
+            config = MachineConfig {
+                address_policy: config.address_policy,
+                load_address: config.load_address,
+                rng: config.rng,
+                seed: config.seed,
+                ..profile
+            };
+        } else if arg == "--memory-policy" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.address_policy = AddressPolicy::from_name(name).unwrap_or_else(|| {
+                fail(&format!("Unknown memory policy '{}'. Available: {}", name, POLICY_NAMES.join(", ")))
+            });
+        } else if arg == "--seed" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.seed = Some(text.parse().unwrap_or_else(|_| fail(&format!("Invalid seed '{}'", text))));
+        } else if arg == "--rng" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.rng = RngKind::from_name(name).unwrap_or_else(|| {
+                fail(&format!("Unknown random generator '{}'. Available: {}", name, RNG_NAMES.join(", ")))
+            });
+        } else if arg == "--load-address" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.load_address = u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                .unwrap_or_else(|_| fail(&format!("Invalid load address '{}'. Expected hex, e.g. 0x600", text)));
+        } else if arg == "--gdb" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            gdb_port = Some(text.parse().unwrap_or_else(|_| fail(&format!("Invalid port '{}'", text))));
+        } else if arg == "--symbols" {
+            symbols_path = arg_iter.next();
+        } else {
+            rom_path = Some(arg);
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        println!("CHIP-8 Debugger");
+        println!("===============");
+        println!();
+        println!("Usage: {} <rom_file> [options]", args[0]);
+        println!();
+        println!("Example: {} roms/pong.ch8 --quirks chip48", args[0]);
+        println!();
+        println!("Options:");
+        println!("  --quirks <profile>         Interpreter behavior: {} (default: vip)", PRESET_NAMES.join(", "));
+        println!("  --memory-policy <policy>   Out-of-range addresses: {} (default: wrap)", POLICY_NAMES.join(", "));
+        println!("  --load-address <addr>      Load address and entry point in hex (default: 0x200)");
+        println!("  --seed <n>                 Seed the RND generator for a reproducible run");
+        println!("  --rng <generator>          RND generator: {} (default: std)", RNG_NAMES.join(", "));
+        println!("  --gdb <port>               Serve the GDB remote protocol on 127.0.0.1:<port>");
+        println!("  --symbols <file>           Symbol map (addr name lines or Octo JSON) for labels");
+        println!();
+        println!("Connect with: gdb -ex 'target remote 127.0.0.1:<port>'");
+        process::exit(1);
+    };
+
+    let rom_data = fs::read(rom_path).unwrap_or_else(|e| fail(&format!("Error loading ROM '{}': {}", rom_path, e)));
+    let mut machine = Machine::new(config);
+    if let Err(e) = machine.load_rom(&rom_data) {
+        fail(&format!("Error loading ROM '{}': {}", rom_path, e));
+    }
+
+    let symbols = match symbols_path {
+        Some(path) => {
+            let text = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("Error reading '{}': {}", path, e)));
+            Symbols::parse(&text).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+        }
+        None => Symbols::new(),
+    };
+
+    if let Some(port) = gdb_port {
+        serve_gdb(&mut machine, port);
+        return;
+    }
+
+    println!("Loaded ROM: {} ({} bytes). Type 'help' for commands.", rom_path, rom_data.len());
+    if !symbols.is_empty() {
+        println!("Loaded {} symbols", symbols.len());
+    }
+    print_current(&machine, &symbols);
+
+    let mut debugger = Debugger::new();
+    let mut last_line = String::new();
+    let stdin = io::stdin();
+    loop {
+        print!("(chip8) ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break; // EOF
+        }
+        // An empty line repeats the last command
+        if line.trim().is_empty() {
+            line = last_line.clone();
+        } else {
+            last_line = line.clone();
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match Command::parse_with_symbols(&line, &symbols) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+
+        match command {
+            Command::Break(pc) => {
+                debugger.add_breakpoint(pc);
+                println!("Breakpoint at {}", symbols.describe(pc));
+            }
+            Command::BreakOpcode(mnemonic) => {
+                debugger.add_opcode_class(&mnemonic);
+                println!("Break before every {}", mnemonic);
+            }
+            Command::BreakWhen(condition) => {
+                debugger.add_condition(condition);
+                println!("Break when {}", condition);
+            }
+            Command::Watch(addr) => {
+                debugger.add_watchpoint(addr);
+                println!("Watchpoint at {}", symbols.describe(addr));
+            }
+            Command::Delete(Some(addr)) => {
+                if !debugger.remove(addr) {
+                    println!("No breakpoint or watchpoint at {}", symbols.describe(addr));
+                }
+            }
+            Command::Delete(None) => debugger.clear(),
+            Command::Step(count) => {
+                let reason = debugger.step(&mut machine, count);
+                report(&machine, reason, &symbols);
+            }
+            Command::Next => {
+                let reason = debugger.next(&mut machine, DEFAULT_MAX_FRAMES);
+                report(&machine, reason, &symbols);
+            }
+            Command::Finish => match debugger.finish(&mut machine, DEFAULT_MAX_FRAMES) {
+                Some(reason) => report(&machine, reason, &symbols),
+                None => println!("Not inside a subroutine"),
+            },
+            Command::Continue(frames) => {
+                let reason = debugger.cont(&mut machine, frames.unwrap_or(DEFAULT_MAX_FRAMES));
+                report(&machine, reason, &symbols);
+            }
+            Command::StepBack(count) => {
+                let reason = debugger.step_back(&mut machine, count);
+                report(&machine, reason, &symbols);
+            }
+            Command::ReverseContinue => {
+                let reason = debugger.reverse_cont(&mut machine);
+                report(&machine, reason, &symbols);
+            }
+            Command::Regs => print_registers(&machine, &symbols),
+            Command::Mem { addr, len } => print_memory(&machine, addr, len),
+            Command::Disasm { addr, count } => {
+                print_disassembly(&machine, &debugger, &symbols, addr.unwrap_or(machine.cpu().pc), count)
+            }
+            Command::Key { key, pressed } => machine.keyboard_mut().set_key(key, pressed),
+            Command::Info => print_info(&debugger, &symbols),
+            Command::Help => {
+                for line in COMMAND_HELP.iter() {
+                    println!("  {}", line);
+                }
+            }
+            Command::Quit => break,
+        }
+    }
+}
+
+/// Waits for one GDB connection on the loopback interface and serves it
+fn serve_gdb(machine: &mut Machine, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .unwrap_or_else(|e| fail(&format!("Cannot listen on port {}: {}", port, e)));
+    println!("Waiting for GDB on 127.0.0.1:{}", port);
+    let (stream, peer) = listener.accept().unwrap_or_else(|e| fail(&format!("Accept failed: {}", e)));
+    println!("GDB connected from {}", peer);
+    if let Err(e) = GdbStub::new().serve(machine, stream) {
+        fail(&format!("GDB connection failed: {}", e));
+    }
+    println!("GDB disconnected");
+}
+
+/// Prints why execution stopped (plain steps need no explanation) and where
+fn report(machine: &Machine, reason: BreakReason, symbols: &Symbols) {
+    if reason != BreakReason::Step {
+        println!("{}", reason.describe(symbols));
+    }
+    print_current(machine, symbols);
+}
+
+fn print_current(machine: &Machine, symbols: &Symbols) {
+    let pc = machine.cpu().pc;
+    let label = symbols.resolve(pc).map(|name| format!(" <{}>", name)).unwrap_or_default();
+    println!("=> 0x{:04X}{}  {}", pc, label, symbols.disassemble(machine.memory().read_opcode(pc)));
+}
+
+fn print_registers(machine: &Machine, symbols: &Symbols) {
+    let cpu = machine.cpu();
+    println!(
+        "PC {}  I {}  SP {}  DT {}  ST {}",
+        symbols.describe(cpu.pc),
+        symbols.describe(cpu.i),
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer
+    );
+    for row in 0..2 {
+        let line: Vec<String> = (row * 8..row * 8 + 8).map(|x| format!("V{:X} {:02X}", x, cpu.v[x])).collect();
+        println!("{}", line.join("  "));
+    }
+    if cpu.sp > 0 {
+        let stack: Vec<String> = cpu.stack[..cpu.sp as usize].iter().map(|&addr| symbols.describe(addr)).collect();
+        println!("Stack: {}", stack.join(" "));
+    }
+    print_current(machine, symbols);
+}
+
+fn print_memory(machine: &Machine, addr: u16, len: u16) {
+    for row_start in (0..len).step_by(16) {
+        let row_addr = addr.wrapping_add(row_start);
+        let bytes: Vec<String> = (0..16.min(len - row_start))
+            .map(|offset| format!("{:02X}", machine.memory().read(row_addr.wrapping_add(offset))))
+            .collect();
+        println!("0x{:04X}  {}", row_addr, bytes.join(" "));
+    }
+}
+
+fn print_disassembly(machine: &Machine, debugger: &Debugger, symbols: &Symbols, addr: u16, count: u16) {
+    let breakpoints: Vec<u16> = debugger.breakpoints().collect();
+    let mut pc = addr;
+    for _ in 0..count {
+        if let Some(name) = symbols.name_at(pc) {
+            println!("{}:", name);
+        }
+        let opcode = machine.memory().read_opcode(pc);
+        let marker = if pc == machine.cpu().pc { "=>" } else { "  " };
+        let flag = if breakpoints.contains(&pc) { '*' } else { ' ' };
+        // XO-CHIP: F000 nnnn is a 4-byte instruction
+        if opcode == 0xF000 {
+            let long = machine.memory().read_opcode(pc.wrapping_add(2));
+            let target = symbols.resolve(long).unwrap_or_else(|| format!("0x{:04X}", long));
+            println!("{}{}0x{:04X}  {:04X}  LD I, {}", marker, flag, pc, opcode, target);
+            pc = pc.wrapping_add(4);
+        } else {
+            println!("{}{}0x{:04X}  {:04X}  {}", marker, flag, pc, opcode, symbols.disassemble(opcode));
+            pc = pc.wrapping_add(2);
+        }
+    }
+}
+
+fn print_info(debugger: &Debugger, symbols: &Symbols) {
+    for pc in debugger.breakpoints() {
+        println!("Breakpoint  {}", symbols.describe(pc));
+    }
+    for addr in debugger.watchpoints() {
+        println!("Watchpoint  {}", symbols.describe(addr));
+    }
+    for condition in debugger.conditions() {
+        println!("Condition   {}", condition);
+    }
+    for mnemonic in debugger.opcode_classes() {
+        println!("Opcode      {}", mnemonic);
+    }
+    let history = debugger.history();
+    if let Some(oldest) = history.oldest() {
+        println!(
+            "History     cycle {} (back to {}, {} checkpoints)",
+            history.position(),
+            oldest,
+            history.checkpoints()
+        );
+    }
+}
+
+/// Prints an error and exits with status 1
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,219 @@
+//! CHIP-8 Headless Runner
+//!
+//! Runs a ROM without a window or audio device and dumps the final screen
+//! and registers. Intended for CI and batch testing of ROMs.
+
+use chip8_emulator::headless::{self, StopReason};
+use chip8_emulator::machine::{CycleHook, Machine, MachineConfig};
+use chip8_emulator::memory::{AddressPolicy, POLICY_NAMES};
+use chip8_emulator::movie::Movie;
+use chip8_emulator::quirks::PRESET_NAMES;
+use chip8_emulator::rng::{RngKind, RNG_NAMES};
+use chip8_emulator::symbols::Symbols;
+use chip8_emulator::trace::{self, Tracer};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const DEFAULT_FRAMES: u64 = 600; // 10 seconds at 60 Hz
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    // Parse command line: <rom_file> [--frames <n>] [--quirks <profile>] [--memory-policy <policy>]
+    //                     [--load-address <addr>] [--png <file>] [--scale <n>] [--json <file>]
+    //                     [--trace <file>] [--trace-range <start>-<end>] [--seed <n>] [--rng <generator>]
+    //                     [--replay <movie>] [--symbols <file>]
+    let mut rom_path: Option<&String> = None;
+    let mut config = MachineConfig::default();
+    let mut frames = DEFAULT_FRAMES;
+    let mut png_path: Option<&String> = None;
+    let mut png_scale = 1;
+    let mut json_path: Option<&String> = None;
+    let mut trace_path: Option<&String> = None;
+    let mut trace_range = None;
+    let mut symbols_path: Option<&String> = None;
+    let mut replay_path: Option<&String> = None;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--frames" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            frames = text.parse().unwrap_or_else(|_| fail(&format!("Invalid frame count '{}'", text)));
+        } else if arg == "--quirks" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            let profile = MachineConfig::for_profile(name).unwrap_or_else(|| {
+                fail(&format!("Unknown quirk profile '{}'. Available: {}", name, PRESET_NAMES.join(", ")))
+            });
+            config = MachineConfig {
+                address_policy: config.address_policy,
+                load_address: config.load_address,
+                rng: config.rng,
+                seed: config.seed,
+                ..profile
+            };
+        } else if arg == "--memory-policy" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.address_policy = AddressPolicy::from_name(name).unwrap_or_else(|| {
+                fail(&format!("Unknown memory policy '{}'. Available: {}", name, POLICY_NAMES.join(", ")))
+            });
+        } else if arg == "--seed" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.seed = Some(text.parse().unwrap_or_else(|_| fail(&format!("Invalid seed '{}'", text))));
+        } else if arg == "--rng" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.rng = RngKind::from_name(name).unwrap_or_else(|| {
+                fail(&format!("Unknown random generator '{}'. Available: {}", name, RNG_NAMES.join(", ")))
+            });
+        } else if arg == "--load-address" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            config.load_address = u16::from_str_radix(text.trim_start_matches("0x"), 16)
+                .unwrap_or_else(|_| fail(&format!("Invalid load address '{}'. Expected hex, e.g. 0x600", text)));
+        } else if arg == "--png" {
+            png_path = arg_iter.next();
+        } else if arg == "--scale" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            png_scale = text.parse().unwrap_or_else(|_| fail(&format!("Invalid scale '{}'", text)));
+        } else if arg == "--json" {
+            json_path = arg_iter.next();
+        } else if arg == "--replay" {
+            replay_path = arg_iter.next();
+        } else if arg == "--trace" {
+            trace_path = arg_iter.next();
+        } else if arg == "--trace-range" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            trace_range = Some(trace::parse_pc_range(text).unwrap_or_else(|| {
+                fail(&format!("Invalid trace range '{}'. Expected hex start-end, e.g. 200-2FF", text))
+            }));
+        } else if arg == "--symbols" {
+            symbols_path = arg_iter.next();
+        } else {
+            rom_path = Some(arg);
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        println!("CHIP-8 Headless Runner");
+        println!("======================");
+        println!();
+        println!("Usage: {} <rom_file> [options]", args[0]);
+        println!();
+        println!("Example: {} roms/IBM_Logo.ch8 --frames 60 --png ibm.png", args[0]);
+        println!();
+        println!("Options:");
+        println!("  --frames <n>               Frames to run before stopping (default: {})", DEFAULT_FRAMES);
+        println!("  --quirks <profile>         Interpreter behavior: {} (default: vip)", PRESET_NAMES.join(", "));
+        println!("  --memory-policy <policy>   Out-of-range addresses: {} (default: wrap)", POLICY_NAMES.join(", "));
+        println!("  --load-address <addr>      Load address and entry point in hex (default: 0x200)");
+        println!("  --seed <n>                 Seed the RND generator for a reproducible run");
+        println!("  --rng <generator>          RND generator: {} (default: std)", RNG_NAMES.join(", "));
+        println!("  --png <file>               Also write the final screen as a PNG");
+        println!("  --scale <n>                PNG pixel scale (default: 1)");
+        println!("  --json <file>              Write the report to a file instead of stdout");
+        println!("  --replay <movie>           Play a movie recorded with --record (uses its settings)");
+        println!("  --trace <file>             Write a per-instruction trace ('-' for stderr)");
+        println!("  --trace-range <start-end>  Only trace instructions with PC in this hex range");
+        println!("  --symbols <file>           Symbol map (addr name lines or Octo JSON) to label trace lines");
+        println!();
+        println!("The run stops early when the program jumps to itself, exits, or the CPU halts.");
+        println!("A replay runs every recorded frame and checks the final state against the movie.");
+        println!("Exit status is 1 if the CPU halted with an error or a replay diverged.");
+        process::exit(1);
+    };
+
+    let rom_data = fs::read(rom_path).unwrap_or_else(|e| fail(&format!("Error loading ROM '{}': {}", rom_path, e)));
+    let movie = replay_path.map(|path| {
+        let data = fs::read(path).unwrap_or_else(|e| fail(&format!("Error loading movie '{}': {}", path, e)));
+        Movie::from_bytes(&data).unwrap_or_else(|e| fail(&format!("Error loading movie '{}': {}", path, e)))
+    });
+    // A movie carries the config it was recorded with
+    let mut machine = match &movie {
+        Some(movie) => movie.machine(&rom_data).unwrap_or_else(|e| fail(&format!("Cannot replay: {}", e))),
+        None => {
+            let mut machine = Machine::new(config);
+            if let Err(e) = machine.load_rom(&rom_data) {
+                fail(&format!("Error loading ROM '{}': {}", rom_path, e));
+            }
+            machine
+        }
+    };
+
+    let symbols = match symbols_path {
+        Some(path) => {
+            let text = fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("Error reading '{}': {}", path, e)));
+            Symbols::parse(&text).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)))
+        }
+        None => Symbols::new(),
+    };
+
+    let (frames_run, reason) = match trace_path {
+        Some(path) => {
+            let out: Box<dyn Write> = if path == "-" {
+                Box::new(io::stderr())
+            } else {
+                let file = File::create(path).unwrap_or_else(|e| fail(&format!("Error creating trace '{}': {}", path, e)));
+                Box::new(BufWriter::new(file))
+            };
+            let mut tracer = Tracer::new(out, trace_range).with_symbols(symbols);
+            let result = execute(&mut machine, frames, movie.as_ref(), &mut tracer);
+            if let Err(e) = tracer.finish() {
+                fail(&format!("Error writing trace '{}': {}", path, e));
+            }
+            result
+        }
+        None => execute(&mut machine, frames, movie.as_ref(), &mut ()),
+    };
+    let movie_match = movie.as_ref().and_then(|movie| movie.matches_final_state(&machine));
+
+    print!("{}", headless::framebuffer_ascii(machine.display()));
+
+    if let Some(path) = png_path {
+        let png = headless::framebuffer_png(machine.display(), png_scale);
+        if let Err(e) = fs::write(path, png) {
+            fail(&format!("Error writing PNG '{}': {}", path, e));
+        }
+    }
+
+    let error = match reason {
+        StopReason::Halted(error) => format!("\"{}\"", error),
+        _ => "null".to_string(),
+    };
+    let report = format!(
+        "{{\"frames\": {}, \"stop_reason\": \"{}\", \"error\": {}, \"movie_match\": {}, \"registers\": {}}}\n",
+        frames_run,
+        reason.name(),
+        error,
+        movie_match.map_or("null".to_string(), |matched| matched.to_string()),
+        headless::registers_json(machine.cpu())
+    );
+    match json_path {
+        Some(path) => {
+            if let Err(e) = fs::write(path, report) {
+                fail(&format!("Error writing JSON '{}': {}", path, e));
+            }
+        }
+        None => print!("{}", report),
+    }
+
+    if let StopReason::Halted(_) = reason {
+        process::exit(1);
+    }
+    if movie_match == Some(false) {
+        eprintln!("Replay diverged from the recorded final state");
+        process::exit(1);
+    }
+}
+
+/// Replays the movie if there is one, otherwise runs up to `frames` frames
+fn execute(machine: &mut Machine, frames: u64, movie: Option<&Movie>, hook: &mut impl CycleHook) -> (u64, StopReason) {
+    match movie {
+        Some(movie) => headless::replay_with(machine, movie, hook),
+        None => headless::run_with(machine, frames, hook),
+    }
+}
+
+/// Prints an error and exits with status 1
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
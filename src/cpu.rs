@@ -4,9 +4,94 @@
 //! all registers and the stack.
 
 use crate::display::Display;
+use crate::instruction::Instruction;
 use crate::keyboard::Keyboard;
-use crate::memory::Memory;
-use rand;
+use crate::memory::{Memory, MemoryError, BIG_FONT_ADDR, FONT_ADDR, PROGRAM_START};
+use crate::rng::{RandomSource, SeededRandom};
+use crate::quirks::{MemoryQuirk, Quirks};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::variant::Variant;
+use std::fmt;
+
+/// Result of a successful `Cpu::cycle`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction ran (or the CPU is blocked on Fx0A); keep going
+    Continue,
+    /// A DRW executed with DISP.WAIT enabled; stop this frame until the next vblank
+    WaitForVblank,
+    /// The program executed 00FD (EXIT) and will not run further
+    Exited,
+}
+
+/// A fault that stops the CPU. Each variant carries the address and opcode of the
+/// instruction that caused it; `pc` is left pointing at that instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode is not part of any supported instruction set
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// 2nnn with all 16 stack levels in use
+    StackOverflow { pc: u16, opcode: u16 },
+    /// 00EE with an empty stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// A memory access outside RAM under the `Strict` address policy.
+    /// `opcode` is 0 if the fault happened while fetching the opcode itself.
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: u16 },
+}
+
+impl CpuError {
+    /// Address of the faulting instruction
+    pub fn pc(&self) -> u16 {
+        match *self {
+            CpuError::UnknownOpcode { pc, .. }
+            | CpuError::StackOverflow { pc, .. }
+            | CpuError::StackUnderflow { pc, .. }
+            | CpuError::MemoryOutOfBounds { pc, .. } => pc,
+        }
+    }
+
+    /// The faulting opcode
+    pub fn opcode(&self) -> u16 {
+        match *self {
+            CpuError::UnknownOpcode { opcode, .. }
+            | CpuError::StackOverflow { opcode, .. }
+            | CpuError::StackUnderflow { opcode, .. }
+            | CpuError::MemoryOutOfBounds { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode {:#06x} at {:#05x}", opcode, pc)
+            }
+            CpuError::StackOverflow { pc, opcode } => write!(
+                f,
+                "Stack overflow: Maximum call depth of 16 exceeded ({:#06x} at {:#05x})",
+                opcode, pc
+            ),
+            CpuError::StackUnderflow { pc, opcode } => write!(
+                f,
+                "Stack underflow: RET called with empty stack ({:#06x} at {:#05x})",
+                opcode, pc
+            ),
+            CpuError::MemoryOutOfBounds { pc, opcode, addr } => write!(
+                f,
+                "Memory access out of bounds at {:#06x} ({:#06x} at {:#05x})",
+                addr, opcode, pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// Audio pattern used until a program loads its own with F002 (a 500 Hz square wave at pitch 64)
+pub const DEFAULT_AUDIO_PATTERN: [u8; 16] = [0xF0; 16];
+/// Initial XO-CHIP pitch register value
+pub const DEFAULT_PITCH: u8 = 64;
 
 /// The CHIP-8 CPU
 pub struct Cpu {
@@ -28,30 +113,85 @@ pub struct Cpu {
     waiting_for_key: Option<u8>,
     /// Display wait state for DISP.WAIT quirk: true = waiting for VBlank after draw
     waiting_for_vblank: bool,
+    /// Super-CHIP RPL user flags (Fx75/Fx85); XO-CHIP extends them to 16
+    pub rpl_flags: [u8; 16],
+    /// XO-CHIP 128-bit audio pattern buffer (F002), played while sound_timer > 0
+    pub audio_pattern: [u8; 16],
+    /// XO-CHIP playback pitch register (Fx3A); 64 = 4000 bits per second
+    pub pitch: u8,
+    /// Set by the Super-CHIP 00FD (EXIT) instruction; the CPU stops executing
+    exited: bool,
+    /// Interpreter-specific behavior switches (COSMAC VIP by default)
+    pub quirks: Quirks,
+    /// Source of Cxkk random bytes (OS-seeded by default); not part of save states
+    pub(crate) rng: Box<dyn RandomSource>,
+    /// Reuse decodings cached in `Memory` instead of decoding every fetch
+    pub(crate) decode_cache: bool,
+    /// Instruction set accepted when decoding (XO-CHIP, the superset, by default)
+    pub(crate) variant: Variant,
 }
 
 impl Cpu {
-    /// Creates a new CPU with initial state
+    /// Creates a new CPU with initial state and COSMAC VIP quirks
     /// PC starts at 0x200 where programs are loaded
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::vip())
+    }
+
+    /// Creates a new CPU that uses the given quirk profile
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_entry_point(quirks, PROGRAM_START)
+    }
+
+    /// Creates a new CPU with the given quirk profile that starts executing at
+    /// `entry_point` (the address the ROM was loaded at, e.g. 0x600 for ETI-660)
+    pub fn with_entry_point(quirks: Quirks, entry_point: u16) -> Self {
         Self {
             v: [0; 16],
             i: 0,
-            pc: 0x200, // Programs start at 0x200
+            pc: entry_point,
             sp: 0,
             stack: [0; 16],
             delay_timer: 0,
             sound_timer: 0,
             waiting_for_key: None,
             waiting_for_vblank: false,
+            rpl_flags: [0; 16],
+            audio_pattern: DEFAULT_AUDIO_PATTERN,
+            pitch: DEFAULT_PITCH,
+            exited: false,
+            quirks,
+            rng: Box::new(SeededRandom::from_entropy()),
+            decode_cache: true,
+            variant: Variant::XoChip,
         }
     }
 
+    /// Replaces the generator used by Cxkk (e.g. a seeded one for reproducible runs)
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    /// Turns the decoded-instruction cache on or off (on by default)
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+    }
+
+    /// Restricts decoding to the instructions `variant` defines; others raise `UnknownOpcode`
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
     /// Returns true if the CPU is halted waiting for VBlank (DISP.WAIT quirk)
     pub fn is_waiting_for_vblank(&self) -> bool {
         self.waiting_for_vblank
     }
 
+    /// Returns true if the program has executed 00FD (EXIT)
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
     /// Checks if the next instruction to be executed is a DRW (DXYN) opcode.
     /// Used for DISP.WAIT quirk - Octo's approach: check BEFORE executing.
     pub fn next_instruction_is_draw(&self, memory: &Memory) -> bool {
@@ -60,292 +200,438 @@ impl Cpu {
     }
 
     /// Executes one fetch-decode-execute cycle
-    /// Returns true if a DRW instruction was executed (for DISP.WAIT quirk)
-    pub fn cycle(&mut self, memory: &mut Memory, display: &mut Display, keyboard: &Keyboard) -> bool {
-        let opcode = self.fetch(memory);
-        self.execute(opcode, memory, display, keyboard);
+    /// Returns `WaitForVblank` if a DRW instruction was executed and the DISP.WAIT quirk is enabled.
+    /// On error, PC is rewound to the faulting instruction so the CPU stays halted there.
+    pub fn cycle(
+        &mut self,
+        memory: &mut Memory,
+        display: &mut Display,
+        keyboard: &Keyboard,
+    ) -> Result<StepOutcome, CpuError> {
+        if self.exited {
+            return Ok(StepOutcome::Exited); // 00FD halted the interpreter
+        }
+
+        let pc = self.pc;
+        let (opcode, instruction) = match memory.decoded(pc).filter(|_| self.decode_cache) {
+            Some(entry) => {
+                self.pc = pc.wrapping_add(2);
+                entry
+            }
+            None => {
+                let opcode = self
+                    .fetch(memory)
+                    .map_err(|e| CpuError::MemoryOutOfBounds { pc, opcode: 0, addr: e.addr })?;
+                let instruction = Instruction::decode(opcode, self.variant).map_err(|_| {
+                    self.pc = pc;
+                    CpuError::UnknownOpcode { pc, opcode }
+                })?;
+                if self.decode_cache {
+                    memory.cache_decoded(pc, opcode, instruction);
+                }
+                (opcode, instruction)
+            }
+        };
+        if let Err(error) = self.execute_instruction(opcode, instruction, memory, display, keyboard) {
+            self.pc = pc;
+            return Err(error);
+        }
+
+        if self.exited {
+            Ok(StepOutcome::Exited)
+        } else if self.display_wait_active(display) && (opcode & 0xF000) == 0xD000 {
+            // This was a DRW instruction (opcode 0xDxyn)
+            Ok(StepOutcome::WaitForVblank)
+        } else {
+            Ok(StepOutcome::Continue)
+        }
+    }
 
-        // Return true if this was a DRW instruction (opcode 0xDxyn)
-        (opcode & 0xF000) == 0xD000
+    /// DISP.WAIT applies in low-res mode only; the HP48 never waited in high-res
+    fn display_wait_active(&self, display: &Display) -> bool {
+        self.quirks.display_wait && !display.is_hires()
     }
 
     /// Fetches the next 2-byte opcode from memory
-    fn fetch(&mut self, memory: &Memory) -> u16 {
-        let high_byte = memory.read(self.pc) as u16;
-        let low_byte = memory.read(self.pc + 1) as u16;
-        self.pc += 2;
-        (high_byte << 8) | low_byte
+    fn fetch(&mut self, memory: &Memory) -> Result<u16, MemoryError> {
+        let high_byte = memory.try_read(self.pc)? as u16;
+        let low_byte = memory.try_read(self.pc.wrapping_add(1))? as u16;
+        self.pc = self.pc.wrapping_add(2);
+        Ok((high_byte << 8) | low_byte)
     }
 
-    /// Decodes and executes an opcode
-    fn execute(
+    /// Executes a decoded instruction; `opcode` is only used in error reports
+    fn execute_instruction(
         &mut self,
         opcode: u16,
+        instruction: Instruction,
         memory: &mut Memory,
         display: &mut Display,
         keyboard: &Keyboard,
-    ) {
-        // Extract opcode parts
-        let nnn = opcode & 0x0FFF;           // Lowest 12 bits
-        let kk = (opcode & 0x00FF) as u8;    // Lowest 8 bits
-        let x = ((opcode & 0x0F00) >> 8) as usize;  // Lower 4 bits of high byte
-        let y = ((opcode & 0x00F0) >> 4) as usize;  // Upper 4 bits of low byte
-        let n = (opcode & 0x000F) as u8;     // Lowest 4 bits
-
-        match opcode & 0xF000 {
-            0x0000 => match opcode {
-                0x00E0 => {
-                    // 00E0 - CLS: Clear the display
-                    display.clear();
-                }
-                0x00EE => {
-                    // 00EE - RET: Return from subroutine
-                    if self.sp == 0 {
-                        panic!("Stack underflow: RET called with empty stack");
-                    }
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp as usize];
+    ) -> Result<(), CpuError> {
+        // Address of this instruction, for error reports
+        let pc = self.pc.wrapping_sub(2);
+        let out_of_bounds = |e: MemoryError| CpuError::MemoryOutOfBounds { pc, opcode, addr: e.addr };
+
+        match instruction {
+            Instruction::Clear => {
+                // 00E0 - CLS: Clear the display
+                display.clear();
+            }
+            Instruction::Return => {
+                // 00EE - RET: Return from subroutine
+                if self.sp == 0 {
+                    return Err(CpuError::StackUnderflow { pc, opcode });
                 }
-                _ => panic!("Unknown opcode: {:#06x}", opcode),
-            },
-            0x1000 => {
+                self.sp -= 1;
+                self.pc = self.stack[self.sp as usize];
+            }
+            Instruction::ScrollDown(n) => {
+                // 00Cn - SCD nibble: Scroll display down n pixels (Super-CHIP)
+                display.scroll_down(n as usize);
+            }
+            Instruction::ScrollUp(n) => {
+                // 00Dn - SCU nibble: Scroll display up n pixels (XO-CHIP)
+                display.scroll_up(n as usize);
+            }
+            Instruction::ScrollRight => {
+                // 00FB - SCR: Scroll display right 4 pixels (Super-CHIP)
+                display.scroll_right(4);
+            }
+            Instruction::ScrollLeft => {
+                // 00FC - SCL: Scroll display left 4 pixels (Super-CHIP)
+                display.scroll_left(4);
+            }
+            Instruction::Exit => {
+                // 00FD - EXIT: Stop the interpreter (Super-CHIP)
+                self.exited = true;
+                self.pc = self.pc.wrapping_sub(2); // Stay on this instruction
+            }
+            Instruction::LowRes => {
+                // 00FE - LOW: Switch to 64x32 low-res mode (Super-CHIP)
+                display.set_hires(false);
+            }
+            Instruction::HighRes => {
+                // 00FF - HIGH: Switch to 128x64 high-res mode (Super-CHIP)
+                display.set_hires(true);
+            }
+            Instruction::Sys(_) => {
+                // 0nnn - SYS addr: Runs 1802 machine code on the VIP, which is not emulated
+                return Err(CpuError::UnknownOpcode { pc, opcode });
+            }
+            Instruction::Jump(nnn) => {
                 // 1nnn - JP addr: Jump to location nnn
                 self.pc = nnn;
             }
-            0x2000 => {
+            Instruction::Call(nnn) => {
                 // 2nnn - CALL addr: Call subroutine at nnn
                 if self.sp >= 16 {
-                    panic!("Stack overflow: Maximum call depth of 16 exceeded");
+                    return Err(CpuError::StackOverflow { pc, opcode });
                 }
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
                 self.pc = nnn;
             }
-            0x3000 => {
+            Instruction::SkipEqByte { x, kk } => {
                 // 3xkk - SE Vx, byte: Skip next instruction if Vx == kk
-                if self.v[x] == kk {
-                    self.pc += 2;
+                if self.v[x as usize] == kk {
+                    self.skip_next_instruction(memory);
                 }
             }
-            0x4000 => {
+            Instruction::SkipNeByte { x, kk } => {
                 // 4xkk - SNE Vx, byte: Skip next instruction if Vx != kk
-                if self.v[x] != kk {
-                    self.pc += 2;
+                if self.v[x as usize] != kk {
+                    self.skip_next_instruction(memory);
                 }
             }
-            0x5000 => {
+            Instruction::SkipEqReg { x, y } => {
                 // 5xy0 - SE Vx, Vy: Skip next instruction if Vx == Vy
-                if self.v[x] == self.v[y] {
-                    self.pc += 2;
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.skip_next_instruction(memory);
+                }
+            }
+            Instruction::SaveRange { x, y } => {
+                // 5xy2 - SAVE Vx - Vy: Store Vx through Vy (either direction) at I, I unchanged (XO-CHIP)
+                for (offset, reg) in Self::register_range(x as usize, y as usize).enumerate() {
+                    memory.try_write(self.i.wrapping_add(offset as u16), self.v[reg]).map_err(out_of_bounds)?;
                 }
             }
-            0x6000 => {
+            Instruction::LoadRange { x, y } => {
+                // 5xy3 - LOAD Vx - Vy: Read Vx through Vy (either direction) from I, I unchanged (XO-CHIP)
+                for (offset, reg) in Self::register_range(x as usize, y as usize).enumerate() {
+                    self.v[reg] = memory.try_read(self.i.wrapping_add(offset as u16)).map_err(out_of_bounds)?;
+                }
+            }
+            Instruction::LoadByte { x, kk } => {
                 // 6xkk - LD Vx, byte: Set Vx = kk
-                self.v[x] = kk;
+                self.v[x as usize] = kk;
             }
-            0x7000 => {
+            Instruction::AddByte { x, kk } => {
                 // 7xkk - ADD Vx, byte: Set Vx = Vx + kk
-                self.v[x] = self.v[x].wrapping_add(kk);
+                self.v[x as usize] = self.v[x as usize].wrapping_add(kk);
             }
-            0x8000 => {
-                match opcode & 0x000F {
-                    0x0000 => {
-                        // 8xy0 - LD Vx, Vy: Set Vx = Vy
-                        self.v[x] = self.v[y];
-                    }
-                    0x0001 => {
-                        // 8xy1 - OR Vx, Vy: Set Vx = Vx OR Vy, VF = 0
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        self.v[x] = vx | vy;
-                        self.v[0xF] = 0;
-                    }
-                    0x0002 => {
-                        // 8xy2 - AND Vx, Vy: Set Vx = Vx AND Vy, VF = 0
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        self.v[x] = vx & vy;
-                        self.v[0xF] = 0;
-                    }
-                    0x0003 => {
-                        // 8xy3 - XOR Vx, Vy: Set Vx = Vx XOR Vy, VF = 0
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        self.v[x] = vx ^ vy;
-                        self.v[0xF] = 0;
-                    }
-                    0x0004 => {
-                        // 8xy4 - ADD Vx, Vy: Set Vx = Vx + Vy, set VF = carry
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        let sum = vx as u16 + vy as u16;
-                        self.v[x] = sum as u8;
-                        self.v[0xF] = if sum > 0xFF { 1 } else { 0 };
-                    }
-                    0x0005 => {
-                        // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow
-                        // NOT borrow means: VF = 1 if Vx >= Vy (no borrow needed), 0 otherwise
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        self.v[x] = vx.wrapping_sub(vy);
-                        self.v[0xF] = if vx >= vy { 1 } else { 0 };
-                    }
-                    0x0006 => {
-                        // 8xy6 - SHR Vx {, Vy}: Set Vx = Vy >> 1, VF = least significant bit
-                        // COSMAC VIP quirk: copy Vy to Vx first, then shift
-                        let vy = self.v[y];
-                        self.v[x] = vy >> 1;
-                        self.v[0xF] = vy & 0x1;
-                    }
-                    0x0007 => {
-                        // 8xy7 - SUBN Vx, Vy: Set Vx = Vy - Vx, set VF = NOT borrow
-                        // NOT borrow means: VF = 1 if Vy >= Vx (no borrow needed), 0 otherwise
-                        let vx = self.v[x];
-                        let vy = self.v[y];
-                        self.v[x] = vy.wrapping_sub(vx);
-                        self.v[0xF] = if vy >= vx { 1 } else { 0 };
-                    }
-                    0x000E => {
-                        // 8xyE - SHL Vx {, Vy}: Set Vx = Vy << 1, VF = most significant bit
-                        // COSMAC VIP quirk: copy Vy to Vx first, then shift
-                        let vy = self.v[y];
-                        self.v[x] = vy << 1;
-                        self.v[0xF] = (vy & 0x80) >> 7;
-                    }
-                    _ => panic!("Unknown 8xy_ opcode: {:#06x}", opcode),
+            Instruction::Move { x, y } => {
+                // 8xy0 - LD Vx, Vy: Set Vx = Vy
+                self.v[x as usize] = self.v[y as usize];
+            }
+            Instruction::Or { x, y } => {
+                // 8xy1 - OR Vx, Vy: Set Vx = Vx OR Vy
+                // COSMAC VIP quirk: VF is reset to 0
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
             }
-            0x9000 => {
+            Instruction::And { x, y } => {
+                // 8xy2 - AND Vx, Vy: Set Vx = Vx AND Vy
+                // COSMAC VIP quirk: VF is reset to 0
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                // 8xy3 - XOR Vx, Vy: Set Vx = Vx XOR Vy
+                // COSMAC VIP quirk: VF is reset to 0
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            Instruction::Add { x, y } => {
+                // 8xy4 - ADD Vx, Vy: Set Vx = Vx + Vy, set VF = carry
+                let vx = self.v[x as usize];
+                let vy = self.v[y as usize];
+                let sum = vx as u16 + vy as u16;
+                self.v[x as usize] = sum as u8;
+                self.v[0xF] = if sum > 0xFF { 1 } else { 0 };
+            }
+            Instruction::Sub { x, y } => {
+                // 8xy5 - SUB Vx, Vy: Set Vx = Vx - Vy, set VF = NOT borrow
+                // NOT borrow means: VF = 1 if Vx >= Vy (no borrow needed), 0 otherwise
+                let vx = self.v[x as usize];
+                let vy = self.v[y as usize];
+                self.v[x as usize] = vx.wrapping_sub(vy);
+                self.v[0xF] = if vx >= vy { 1 } else { 0 };
+            }
+            Instruction::ShiftRight { x, y } => {
+                // 8xy6 - SHR Vx {, Vy}: Set Vx = Vy >> 1, VF = least significant bit
+                // COSMAC VIP quirk: copy Vy to Vx first, then shift
+                // CHIP-48/SCHIP: shift Vx in place, Vy is ignored
+                let source = if self.quirks.shift_vx { self.v[x as usize] } else { self.v[y as usize] };
+                self.v[x as usize] = source >> 1;
+                self.v[0xF] = source & 0x1;
+            }
+            Instruction::SubN { x, y } => {
+                // 8xy7 - SUBN Vx, Vy: Set Vx = Vy - Vx, set VF = NOT borrow
+                // NOT borrow means: VF = 1 if Vy >= Vx (no borrow needed), 0 otherwise
+                let vx = self.v[x as usize];
+                let vy = self.v[y as usize];
+                self.v[x as usize] = vy.wrapping_sub(vx);
+                self.v[0xF] = if vy >= vx { 1 } else { 0 };
+            }
+            Instruction::ShiftLeft { x, y } => {
+                // 8xyE - SHL Vx {, Vy}: Set Vx = Vy << 1, VF = most significant bit
+                // COSMAC VIP quirk: copy Vy to Vx first, then shift
+                // CHIP-48/SCHIP: shift Vx in place, Vy is ignored
+                let source = if self.quirks.shift_vx { self.v[x as usize] } else { self.v[y as usize] };
+                self.v[x as usize] = source << 1;
+                self.v[0xF] = (source & 0x80) >> 7;
+            }
+            Instruction::SkipNeReg { x, y } => {
                 // 9xy0 - SNE Vx, Vy: Skip next instruction if Vx != Vy
-                if self.v[x] != self.v[y] {
-                    self.pc += 2;
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.skip_next_instruction(memory);
                 }
             }
-            0xA000 => {
+            Instruction::LoadIndex(nnn) => {
                 // Annn - LD I, addr: Set I = nnn
                 self.i = nnn;
             }
-            0xB000 => {
+            Instruction::JumpOffset(nnn) => {
                 // Bnnn - JP V0, addr: Jump to location nnn + V0
-                self.pc = nnn + self.v[0] as u16;
+                // CHIP-48/SCHIP quirk: read as Bxnn and jump to xnn + Vx
+                let offset = if self.quirks.jump_vx { self.v[(nnn >> 8) as usize] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
             }
-            0xC000 => {
+            Instruction::Random { x, kk } => {
                 // Cxkk - RND Vx, byte: Set Vx = random byte AND kk
-                let random_byte: u8 = rand::random();
-                self.v[x] = random_byte & kk;
+                let random_byte = self.rng.next_byte();
+                self.v[x as usize] = random_byte & kk;
             }
-            0xD000 => {
+            Instruction::Draw { x, y, n } => {
                 // Dxyn - DRW Vx, Vy, nibble: Display n-byte sprite at (Vx, Vy), set VF = collision
                 // COSMAC VIP DISP.WAIT quirk: Wait for vblank BEFORE drawing
                 // On real VIP, the IDL instruction halted CPU until the display interrupt.
                 // If already drew this frame, wait until next vblank (re-execute instruction)
-                if self.waiting_for_vblank {
-                    self.pc -= 2; // Repeat this instruction next cycle
-                    return;       // Don't draw yet - wait for tick_timers to clear the flag
+                if self.display_wait_active(display) && self.waiting_for_vblank {
+                    self.pc = self.pc.wrapping_sub(2); // Repeat this instruction next cycle
+                    return Ok(()); // Don't draw yet - wait for tick_timers to clear the flag
                 }
 
-                let x_coord = self.v[x];
-                let y_coord = self.v[y];
-                let height = n;
+                let x_coord = self.v[x as usize];
+                let y_coord = self.v[y as usize];
+                // Super-CHIP: Dxy0 draws a 16x16 sprite (32 bytes)
+                // XO-CHIP: each selected plane reads its own copy of the sprite data
+                let plane_count = display.selected_planes().count_ones() as u16;
+                let byte_count = if n == 0 { 32 } else { n as u16 } * plane_count;
                 let mut sprite = Vec::new();
-                for row in 0..height {
-                    sprite.push(memory.read(self.i + row as u16));
+                for offset in 0..byte_count {
+                    sprite.push(memory.try_read(self.i.wrapping_add(offset)).map_err(out_of_bounds)?);
                 }
-                let collision = display.draw_sprite(x_coord, y_coord, &sprite);
+                let collision = if n == 0 {
+                    display.draw_sprite_16(x_coord, y_coord, &sprite, self.quirks.wrap_sprites)
+                } else {
+                    display.draw_sprite(x_coord, y_coord, &sprite, self.quirks.wrap_sprites)
+                };
                 self.v[0xF] = if collision { 1 } else { 0 };
                 // DISP.WAIT: Block subsequent draws until next vblank
-                self.waiting_for_vblank = true;
+                self.waiting_for_vblank = self.display_wait_active(display);
             }
-            0xE000 => match opcode & 0x00FF {
-                0x009E => {
-                    // Ex9E - SKP Vx: Skip next instruction if key with value of Vx is pressed
-                    if keyboard.is_key_pressed(self.v[x] & 0x0F) {
-                        self.pc += 2;
-                    }
+            Instruction::SkipKey { x } => {
+                // Ex9E - SKP Vx: Skip next instruction if key with value of Vx is pressed
+                if keyboard.is_key_pressed(self.v[x as usize] & 0x0F) {
+                    self.skip_next_instruction(memory);
                 }
-                0x00A1 => {
-                    // ExA1 - SKNP Vx: Skip next instruction if key with value of Vx is NOT pressed
-                    if !keyboard.is_key_pressed(self.v[x] & 0x0F) {
-                        self.pc += 2;
-                    }
+            }
+            Instruction::SkipNotKey { x } => {
+                // ExA1 - SKNP Vx: Skip next instruction if key with value of Vx is NOT pressed
+                if !keyboard.is_key_pressed(self.v[x as usize] & 0x0F) {
+                    self.skip_next_instruction(memory);
                 }
-                _ => panic!("Unknown opcode: {:#06x}", opcode),
-            },
-            0xF000 => match opcode & 0x00FF {
-                0x0007 => {
-                    // Fx07 - LD Vx, DT: Set Vx = delay timer value
-                    self.v[x] = self.delay_timer;
+            }
+            Instruction::LoadIndexLong => {
+                // F000 nnnn - LD I, long: Set I = 16-bit address in the next word (XO-CHIP)
+                self.i = self.fetch(memory).map_err(out_of_bounds)?;
+            }
+            Instruction::Plane(n) => {
+                // Fn01 - PLANE n: Select bitplanes for drawing (XO-CHIP)
+                display.select_planes(n);
+            }
+            Instruction::Audio => {
+                // F002 - AUDIO: Load 16-byte audio pattern buffer from memory at I (XO-CHIP)
+                for offset in 0..16 {
+                    self.audio_pattern[offset] = memory.try_read(self.i.wrapping_add(offset as u16)).map_err(out_of_bounds)?;
                 }
-                0x000A => {
-                    // Fx0A - LD Vx, K: Wait for a key press AND release, store the value in Vx
-                    match self.waiting_for_key {
-                        None => {
-                            // Not waiting yet - check if a key is pressed
-                            if let Some(key) = keyboard.get_pressed_key() {
-                                // Key pressed - remember it and wait for release
-                                self.waiting_for_key = Some(key);
-                                self.pc -= 2; // Repeat this instruction
-                            } else {
-                                // No key pressed - repeat this instruction
-                                self.pc -= 2;
-                            }
+            }
+            Instruction::LoadDelay { x } => {
+                // Fx07 - LD Vx, DT: Set Vx = delay timer value
+                self.v[x as usize] = self.delay_timer;
+            }
+            Instruction::WaitKey { x } => {
+                // Fx0A - LD Vx, K: Wait for a key press AND release, store the value in Vx
+                match self.waiting_for_key {
+                    None => {
+                        // Not waiting yet - check if a key is pressed
+                        if let Some(key) = keyboard.get_pressed_key() {
+                            // Key pressed - remember it and wait for release
+                            self.waiting_for_key = Some(key);
+                            self.pc = self.pc.wrapping_sub(2); // Repeat this instruction
+                        } else {
+                            // No key pressed - repeat this instruction
+                            self.pc = self.pc.wrapping_sub(2);
                         }
-                        Some(key) => {
-                            // Waiting for key release - check if it's released
-                            if !keyboard.is_key_pressed(key) {
-                                // Key released - store it and continue
-                                self.v[x] = key;
-                                self.waiting_for_key = None;
-                            } else {
-                                // Key still pressed - repeat this instruction
-                                self.pc -= 2;
-                            }
+                    }
+                    Some(key) => {
+                        // Waiting for key release - check if it's released
+                        if !keyboard.is_key_pressed(key) {
+                            // Key released - store it and continue
+                            self.v[x as usize] = key;
+                            self.waiting_for_key = None;
+                        } else {
+                            // Key still pressed - repeat this instruction
+                            self.pc = self.pc.wrapping_sub(2);
                         }
                     }
                 }
-                0x0015 => {
-                    // Fx15 - LD DT, Vx: Set delay timer = Vx
-                    self.delay_timer = self.v[x];
-                }
-                0x0018 => {
-                    // Fx18 - LD ST, Vx: Set sound timer = Vx
-                    self.sound_timer = self.v[x];
-                }
-                0x001E => {
-                    // Fx1E - ADD I, Vx: Set I = I + Vx
-                    self.i = self.i.wrapping_add(self.v[x] as u16);
-                }
-                0x0029 => {
-                    // Fx29 - LD F, Vx: Set I = location of sprite for digit Vx
-                    // Font sprites are 5 bytes each, starting at address 0x000
-                    self.i = ((self.v[x] & 0x0F) as u16) * 5;
-                }
-                0x0033 => {
-                    // Fx33 - LD B, Vx: Store BCD representation of Vx in memory locations I, I+1, I+2
-                    let value = self.v[x];
-                    memory.write(self.i, value / 100);         // Hundreds digit
-                    memory.write(self.i + 1, (value / 10) % 10); // Tens digit
-                    memory.write(self.i + 2, value % 10);      // Ones digit
+            }
+            Instruction::SetDelay { x } => {
+                // Fx15 - LD DT, Vx: Set delay timer = Vx
+                self.delay_timer = self.v[x as usize];
+            }
+            Instruction::SetSound { x } => {
+                // Fx18 - LD ST, Vx: Set sound timer = Vx
+                self.sound_timer = self.v[x as usize];
+            }
+            Instruction::AddIndex { x } => {
+                // Fx1E - ADD I, Vx: Set I = I + Vx
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
+            }
+            Instruction::LoadFont { x } => {
+                // Fx29 - LD F, Vx: Set I = location of sprite for digit Vx
+                // Font sprites are 5 bytes each, starting at address 0x000
+                self.i = FONT_ADDR + ((self.v[x as usize] & 0x0F) as u16) * 5;
+            }
+            Instruction::LoadBigFont { x } => {
+                // Fx30 - LD HF, Vx: Set I = location of 10-byte big sprite for digit Vx (Super-CHIP)
+                self.i = BIG_FONT_ADDR + ((self.v[x as usize] & 0x0F) as u16) * 10;
+            }
+            Instruction::Pitch { x } => {
+                // Fx3A - PITCH Vx: Set audio playback pitch = Vx (XO-CHIP)
+                self.pitch = self.v[x as usize];
+            }
+            Instruction::StoreBcd { x } => {
+                // Fx33 - LD B, Vx: Store BCD representation of Vx in memory locations I, I+1, I+2
+                let value = self.v[x as usize];
+                let digits = [value / 100, (value / 10) % 10, value % 10]; // Hundreds, tens, ones
+                for (offset, digit) in digits.into_iter().enumerate() {
+                    memory.try_write(self.i.wrapping_add(offset as u16), digit).map_err(out_of_bounds)?;
                 }
-                0x0055 => {
-                    // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
-                    // COSMAC VIP quirk: increment I by x+1 after storing
-                    for i in 0..=x {
-                        memory.write(self.i + i as u16, self.v[i]);
-                    }
-                    self.i += (x as u16) + 1;
+            }
+            Instruction::StoreRegisters { x } => {
+                // Fx55 - LD [I], Vx: Store registers V0 through Vx in memory starting at location I
+                // COSMAC VIP quirk: increment I by x+1 after storing
+                let x = x as usize;
+                for i in 0..=x {
+                    memory.try_write(self.i.wrapping_add(i as u16), self.v[i]).map_err(out_of_bounds)?;
                 }
-                0x0065 => {
-                    // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
-                    // COSMAC VIP quirk: increment I by x+1 after loading
-                    for i in 0..=x {
-                        self.v[i] = memory.read(self.i + i as u16);
-                    }
-                    self.i += (x as u16) + 1;
+                self.increment_i_after_memory_op(x);
+            }
+            Instruction::LoadRegisters { x } => {
+                // Fx65 - LD Vx, [I]: Read registers V0 through Vx from memory starting at location I
+                // COSMAC VIP quirk: increment I by x+1 after loading
+                let x = x as usize;
+                for i in 0..=x {
+                    self.v[i] = memory.try_read(self.i.wrapping_add(i as u16)).map_err(out_of_bounds)?;
                 }
-                _ => panic!("Unknown opcode: {:#06x}", opcode),
-            },
-            _ => panic!("Unknown opcode: {:#06x}", opcode),
+                self.increment_i_after_memory_op(x);
+            }
+            Instruction::StoreFlags { x } => {
+                // Fx75 - LD R, Vx: Store V0 through Vx in RPL user flags (Super-CHIP)
+                let x = x as usize;
+                self.rpl_flags[..=x].copy_from_slice(&self.v[..=x]);
+            }
+            Instruction::LoadFlags { x } => {
+                // Fx85 - LD Vx, R: Read V0 through Vx from RPL user flags (Super-CHIP)
+                let x = x as usize;
+                self.v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Skips the next instruction. XO-CHIP: F000 nnnn is 4 bytes long, so skip both words.
+    fn skip_next_instruction(&mut self, memory: &Memory) {
+        let next_is_long_load = memory.read(self.pc) == 0xF0 && memory.read(self.pc.wrapping_add(1)) == 0x00;
+        self.pc = self.pc.wrapping_add(if next_is_long_load { 4 } else { 2 });
+    }
+
+    /// Registers Vx through Vy for 5xy2/5xy3, counting down if x > y
+    fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x..=y)
+        } else {
+            Box::new((y..=x).rev())
+        }
+    }
+
+    /// Applies the memory quirk to I after Fx55/Fx65 transferred V0..=Vx
+    fn increment_i_after_memory_op(&mut self, x: usize) {
+        match self.quirks.memory {
+            MemoryQuirk::IncrementXPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+            MemoryQuirk::IncrementX => self.i = self.i.wrapping_add(x as u16),
+            MemoryQuirk::Unchanged => {}
         }
     }
 
@@ -359,6 +645,71 @@ impl Cpu {
         }
         // Clear VBlank wait flag (DISP.WAIT quirk - allows one draw per 60Hz tick)
         self.waiting_for_vblank = false;
+        self.rng.tick();
+    }
+
+    /// Writes every register, including the private wait and exit flags
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.v);
+        writer.u16(self.i);
+        writer.u16(self.pc);
+        writer.u8(self.sp);
+        for &addr in self.stack.iter() {
+            writer.u16(addr);
+        }
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        // 0xFF = not waiting for a key
+        writer.u8(self.waiting_for_key.unwrap_or(0xFF));
+        writer.bool(self.waiting_for_vblank);
+        writer.bytes(&self.rpl_flags);
+        writer.bytes(&self.audio_pattern);
+        writer.u8(self.pitch);
+        writer.bool(self.exited);
+        writer.quirks(&self.quirks);
+    }
+
+    /// Reads a CPU written by `save_state`
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        let v = reader.array()?;
+        let i = reader.u16()?;
+        let pc = reader.u16()?;
+        let sp = reader.u8()?;
+        if sp as usize > 16 {
+            return Err(SaveStateError::Corrupt("stack pointer"));
+        }
+        let mut stack = [0; 16];
+        for addr in stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let waiting_for_key = match reader.u8()? {
+            0xFF => None,
+            key if key < 16 => Some(key),
+            _ => return Err(SaveStateError::Corrupt("key wait")),
+        };
+        Ok(Cpu {
+            v,
+            i,
+            pc,
+            sp,
+            stack,
+            delay_timer,
+            sound_timer,
+            waiting_for_key,
+            waiting_for_vblank: reader.bool()?,
+            rpl_flags: reader.array()?,
+            audio_pattern: reader.array()?,
+            pitch: reader.u8()?,
+            exited: reader.bool()?,
+            quirks: reader.quirks()?,
+            // Placeholder: Machine::load_state keeps the running generator
+            rng: Box::new(SeededRandom::new(0)),
+            decode_cache: true,
+            // Set from the machine's configuration, like the generator
+            variant: Variant::XoChip,
+        })
     }
 }
 
@@ -369,8 +720,26 @@ impl Default for Cpu {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
+    use crate::memory::AddressPolicy;
+
+    impl Cpu {
+        /// Decodes and executes an opcode the way `cycle` does after fetching it
+        fn execute(
+            &mut self,
+            opcode: u16,
+            memory: &mut Memory,
+            display: &mut Display,
+            keyboard: &Keyboard,
+        ) -> Result<(), CpuError> {
+            let pc = self.pc.wrapping_sub(2);
+            let instruction =
+                Instruction::decode(opcode, self.variant).map_err(|_| CpuError::UnknownOpcode { pc, opcode })?;
+            self.execute_instruction(opcode, instruction, memory, display, keyboard)
+        }
+    }
 
     #[test]
     fn test_cpu_new_pc_starts_at_0x200() {
@@ -378,6 +747,12 @@ mod tests {
         assert_eq!(cpu.pc, 0x200);
     }
 
+    #[test]
+    fn test_cpu_with_entry_point() {
+        let cpu = Cpu::with_entry_point(Quirks::vip(), 0x600);
+        assert_eq!(cpu.pc, 0x600);
+    }
+
     #[test]
     fn test_cpu_new_registers_are_zero() {
         let cpu = Cpu::new();
@@ -448,7 +823,7 @@ mod tests {
         memory.write(0x200, 0x61);
         memory.write(0x201, 0xFF);
         
-        let opcode = cpu.fetch(&memory);
+        let opcode = cpu.fetch(&memory).unwrap();
         assert_eq!(opcode, 0x61FF);
     }
 
@@ -458,7 +833,7 @@ mod tests {
         let memory = Memory::new();
         
         assert_eq!(cpu.pc, 0x200);
-        cpu.fetch(&memory);
+        cpu.fetch(&memory).unwrap();
         assert_eq!(cpu.pc, 0x202);
     }
 
@@ -471,7 +846,7 @@ mod tests {
         memory.write(0x200, 0xA2); // High byte
         memory.write(0x201, 0x3C); // Low byte
         
-        let opcode = cpu.fetch(&memory);
+        let opcode = cpu.fetch(&memory).unwrap();
         assert_eq!(opcode, 0xA23C); // Should be combined as (0xA2 << 8) | 0x3C
     }
 
@@ -490,7 +865,7 @@ mod tests {
         assert!(display.get_pixel(10, 10));
 
         // Execute CLS
-        cpu.execute(0x00E0, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x00E0, &mut memory, &mut display, &keyboard).unwrap();
 
         // All pixels should be cleared
         assert!(!display.get_pixel(10, 10));
@@ -505,11 +880,11 @@ mod tests {
         let keyboard = Keyboard::new();
 
         // 6522 - LD V5, 0x22
-        cpu.execute(0x6522, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x6522, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[5], 0x22);
 
         // 6AFF - LD VA, 0xFF
-        cpu.execute(0x6AFF, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x6AFF, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[0xA], 0xFF);
     }
 
@@ -522,7 +897,7 @@ mod tests {
 
         cpu.v[3] = 10;
         // 7305 - ADD V3, 0x05
-        cpu.execute(0x7305, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x7305, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[3], 15);
     }
 
@@ -535,7 +910,7 @@ mod tests {
 
         cpu.v[2] = 0xFF;
         // 7202 - ADD V2, 0x02 (should wrap: 0xFF + 0x02 = 0x01)
-        cpu.execute(0x7202, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x7202, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[2], 0x01);
     }
 
@@ -547,11 +922,11 @@ mod tests {
         let keyboard = Keyboard::new();
 
         // A23C - LD I, 0x23C
-        cpu.execute(0xA23C, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xA23C, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.i, 0x23C);
 
         // AFFF - LD I, 0xFFF
-        cpu.execute(0xAFFF, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xAFFF, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.i, 0xFFF);
     }
 
@@ -563,7 +938,7 @@ mod tests {
         let keyboard = Keyboard::new();
 
         // 1ABC - JP 0xABC
-        cpu.execute(0x1ABC, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x1ABC, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0xABC);
     }
 
@@ -578,7 +953,7 @@ mod tests {
         cpu.pc = 0x200;
 
         // 3542 - SE V5, 0x42 (should skip)
-        cpu.execute(0x3542, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x3542, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x202); // PC incremented by 2
     }
 
@@ -593,7 +968,7 @@ mod tests {
         cpu.pc = 0x200;
 
         // 3543 - SE V5, 0x43 (should not skip)
-        cpu.execute(0x3543, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x3543, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x200); // PC unchanged
     }
 
@@ -608,7 +983,7 @@ mod tests {
         cpu.pc = 0x300;
 
         // 4320 - SNE V3, 0x20 (should skip because 0x10 != 0x20)
-        cpu.execute(0x4320, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x4320, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x302);
     }
 
@@ -623,7 +998,7 @@ mod tests {
         cpu.pc = 0x300;
 
         // 4320 - SNE V3, 0x20 (should not skip because equal)
-        cpu.execute(0x4320, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x4320, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x300); // Unchanged
     }
 
@@ -638,7 +1013,7 @@ mod tests {
         cpu.v[7] = 0x55;
 
         // 8370 - LD V3, V7
-        cpu.execute(0x8370, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8370, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[3], 0x55);
         assert_eq!(cpu.v[7], 0x55); // V7 unchanged
     }
@@ -654,7 +1029,7 @@ mod tests {
         cpu.v[5] = 0b01010101;
 
         // 8251 - OR V2, V5
-        cpu.execute(0x8251, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8251, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[2], 0b11111111);
     }
 
@@ -669,7 +1044,7 @@ mod tests {
         cpu.v[6] = 0b10101010;
 
         // 8462 - AND V4, V6
-        cpu.execute(0x8462, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8462, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[4], 0b10100000);
     }
 
@@ -684,7 +1059,7 @@ mod tests {
         cpu.v[3] = 0b10101010;
 
         // 8133 - XOR V1, V3
-        cpu.execute(0x8133, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8133, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[1], 0b01011010);
     }
 
@@ -699,7 +1074,7 @@ mod tests {
         cpu.v[3] = 20;
 
         // 8234 - ADD V2, V3
-        cpu.execute(0x8234, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8234, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[2], 30);
         assert_eq!(cpu.v[0xF], 0); // No carry
     }
@@ -715,7 +1090,7 @@ mod tests {
         cpu.v[7] = 100;
 
         // 8574 - ADD V5, V7 (200 + 100 = 300, wraps to 44)
-        cpu.execute(0x8574, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8574, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[5], 44); // 300 & 0xFF = 44
         assert_eq!(cpu.v[0xF], 1); // Carry set
     }
@@ -731,7 +1106,7 @@ mod tests {
         cpu.v[4] = 20;
 
         // 8345 - SUB V3, V4
-        cpu.execute(0x8345, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8345, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[3], 30);
         assert_eq!(cpu.v[0xF], 1); // NOT borrow (Vx > Vy)
     }
@@ -747,7 +1122,7 @@ mod tests {
         cpu.v[5] = 20;
 
         // 8255 - SUB V2, V5 (10 - 20 = -10, wraps to 246)
-        cpu.execute(0x8255, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8255, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[2], 246); // wrapping_sub
         assert_eq!(cpu.v[0xF], 0); // Borrow (Vx < Vy)
     }
@@ -762,7 +1137,7 @@ mod tests {
         cpu.v[6] = 0b10110101;  // Source is Vy (V6)
 
         // 8766 - SHR V7, V6 (COSMAC VIP quirk: copies V6 to V7, then shifts)
-        cpu.execute(0x8766, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8766, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[7], 0b01011010);
         assert_eq!(cpu.v[0xF], 1); // LSB was 1
     }
@@ -778,7 +1153,7 @@ mod tests {
         cpu.v[2] = 50;
 
         // 8127 - SUBN V1, V2 (V1 = V2 - V1 = 50 - 20 = 30)
-        cpu.execute(0x8127, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8127, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[1], 30);
         assert_eq!(cpu.v[0xF], 1); // NOT borrow (Vy > Vx)
     }
@@ -794,7 +1169,7 @@ mod tests {
         cpu.v[4] = 20;
 
         // 8347 - SUBN V3, V4 (V3 = V4 - V3 = 20 - 50 = -30, wraps)
-        cpu.execute(0x8347, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x8347, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[3], 226); // wrapping_sub
         assert_eq!(cpu.v[0xF], 0); // Borrow (Vy < Vx)
     }
@@ -809,13 +1184,12 @@ mod tests {
         cpu.v[3] = 0b10110101;  // Source is Vy (V3)
 
         // 853E - SHL V5, V3 (COSMAC VIP quirk: copies V3 to V5, then shifts)
-        cpu.execute(0x853E, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x853E, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[5], 0b01101010);
         assert_eq!(cpu.v[0xF], 1); // MSB was 1
     }
 
     #[test]
-    #[should_panic(expected = "Stack underflow: RET called with empty stack")]
     fn test_stack_underflow() {
         let mut cpu = Cpu::new();
         let mut memory = Memory::new();
@@ -823,7 +1197,11 @@ mod tests {
         let keyboard = Keyboard::new();
 
         // Try to return without any CALL (sp is 0)
-        cpu.execute(0x00EE, &mut memory, &mut display, &keyboard); // RET should panic
+        memory.write(0x200, 0x00);
+        memory.write(0x201, 0xEE);
+        let result = cpu.cycle(&mut memory, &mut display, &keyboard); // RET should fail
+        assert_eq!(result, Err(CpuError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+        assert_eq!(cpu.pc, 0x200); // Halted on the faulting instruction
     }
 
     #[test]
@@ -838,13 +1216,12 @@ mod tests {
         cpu.sp = 1;
 
         // 00EE - RET
-        cpu.execute(0x00EE, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x00EE, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x300);
         assert_eq!(cpu.sp, 0);
     }
 
     #[test]
-    #[should_panic(expected = "Stack overflow: Maximum call depth of 16 exceeded")]
     fn test_stack_overflow() {
         let mut cpu = Cpu::new();
         let mut memory = Memory::new();
@@ -853,11 +1230,132 @@ mod tests {
 
         // Fill the stack to maximum (16 levels)
         for _ in 0..16 {
-            cpu.execute(0x2200, &mut memory, &mut display, &keyboard); // CALL 0x200
+            cpu.execute(0x2200, &mut memory, &mut display, &keyboard).unwrap(); // CALL 0x200
         }
 
-        // This 17th call should panic
-        cpu.execute(0x2200, &mut memory, &mut display, &keyboard);
+        // This 17th call should fail and leave the stack untouched
+        let result = cpu.execute(0x2200, &mut memory, &mut display, &keyboard);
+        assert!(matches!(result, Err(CpuError::StackOverflow { opcode: 0x2200, .. })));
+        assert_eq!(cpu.sp, 16);
+    }
+
+    #[test]
+    fn test_unknown_opcode_error() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // 0x8008 has no 8xy_ instruction
+        memory.write(0x200, 0x80);
+        memory.write(0x201, 0x08);
+        let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+        assert_eq!(error, CpuError::UnknownOpcode { pc: 0x200, opcode: 0x8008 });
+        assert_eq!(error.to_string(), "Unknown opcode 0x8008 at 0x200");
+
+        // 9xyn is only defined for n = 0
+        let mut cpu = Cpu::new();
+        memory.write(0x200, 0x91);
+        memory.write(0x201, 0x21);
+        let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+        assert_eq!(error, CpuError::UnknownOpcode { pc: 0x200, opcode: 0x9121 });
+    }
+
+    #[test]
+    fn test_variant_rejects_later_extensions() {
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // 00FF (HIGH), Dxy0 (16x16 sprite), 5xy2 (save range) and F000 (long I load) are not CHIP-8
+        for opcode in [0x00FF, 0xD120, 0x5122, 0xF000] {
+            let mut cpu = Cpu::new();
+            cpu.set_variant(Variant::Chip8);
+            memory.write(0x200, (opcode >> 8) as u8);
+            memory.write(0x201, opcode as u8);
+            let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+            assert_eq!(error, CpuError::UnknownOpcode { pc: 0x200, opcode });
+            assert_eq!(cpu.pc, 0x200);
+        }
+
+        // Super-CHIP accepts 00FF but not the XO-CHIP additions
+        let mut cpu = Cpu::new();
+        cpu.set_variant(Variant::SuperChip);
+        memory.write(0x200, 0x00);
+        memory.write(0x201, 0xFF);
+        memory.write(0x202, 0x51);
+        memory.write(0x203, 0x22);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert!(display.is_hires());
+        let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+        assert_eq!(error, CpuError::UnknownOpcode { pc: 0x202, opcode: 0x5122 });
+    }
+
+    #[test]
+    fn test_decode_cache_sees_self_modifying_code() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // 0x200: LD V0, 0x05, cached by the first run
+        memory.write(0x200, 0x60);
+        memory.write(0x201, 0x05);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[0], 0x05);
+        assert!(memory.decoded(0x200).is_some());
+
+        // Rewrite the low byte, then the high byte: each write must drop the cached decoding
+        memory.write(0x201, 0x07);
+        cpu.pc = 0x200;
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[0], 0x07);
+        memory.write(0x200, 0x61);
+        cpu.pc = 0x200;
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[1], 0x07);
+
+        // Fx55 overwriting code goes through the same path
+        cpu.v[0] = 0x62;
+        cpu.v[1] = 0x09;
+        cpu.i = 0x200;
+        cpu.execute(0xF155, &mut memory, &mut display, &keyboard).unwrap();
+        cpu.pc = 0x200;
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[2], 0x09);
+    }
+
+    #[test]
+    fn test_decode_cache_can_be_disabled() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        cpu.set_decode_cache(false);
+
+        memory.write(0x200, 0x60);
+        memory.write(0x201, 0x05);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[0], 0x05);
+        assert!(memory.decoded(0x200).is_none());
+    }
+
+    #[test]
+    fn test_cycle_outcomes() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        memory.write(0x200, 0x60); // LD V0, 0
+        memory.write(0x202, 0xD0); // DRW V0, V0, 1
+        memory.write(0x203, 0x01);
+        memory.write(0x205, 0xFD); // EXIT
+        let mut step = || cpu.cycle(&mut memory, &mut display, &keyboard);
+        assert_eq!(step(), Ok(StepOutcome::Continue));
+        assert_eq!(step(), Ok(StepOutcome::WaitForVblank));
+        assert_eq!(step(), Ok(StepOutcome::Exited));
+        assert_eq!(step(), Ok(StepOutcome::Exited));
     }
 
     #[test]
@@ -870,7 +1368,7 @@ mod tests {
         cpu.pc = 0x200;
 
         // 2ABC - CALL 0xABC
-        cpu.execute(0x2ABC, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x2ABC, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0xABC);
         assert_eq!(cpu.sp, 1);
         assert_eq!(cpu.stack[0], 0x200); // Return address saved
@@ -888,7 +1386,7 @@ mod tests {
         cpu.pc = 0x200;
 
         // 5270 - SE V2, V7 (should skip)
-        cpu.execute(0x5270, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x5270, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x202);
     }
 
@@ -904,7 +1402,7 @@ mod tests {
         cpu.pc = 0x200;
 
         // 5270 - SE V2, V7 (should not skip)
-        cpu.execute(0x5270, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x5270, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x200);
     }
 
@@ -920,7 +1418,7 @@ mod tests {
         cpu.pc = 0x300;
 
         // 9350 - SNE V3, V5 (should skip)
-        cpu.execute(0x9350, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x9350, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x302);
     }
 
@@ -936,7 +1434,7 @@ mod tests {
         cpu.pc = 0x300;
 
         // 9350 - SNE V3, V5 (should not skip)
-        cpu.execute(0x9350, &mut memory, &mut display, &keyboard);
+        cpu.execute(0x9350, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x300);
     }
 
@@ -948,7 +1446,7 @@ mod tests {
         let keyboard = Keyboard::new();
 
         cpu.v[0] = 0x05;
-        cpu.execute(0xB200, &mut memory, &mut display, &keyboard); // JP V0, 0x200
+        cpu.execute(0xB200, &mut memory, &mut display, &keyboard).unwrap(); // JP V0, 0x200
         assert_eq!(cpu.pc, 0x205); // 0x200 + 0x05
     }
 
@@ -959,25 +1457,42 @@ mod tests {
         let mut display = Display::new();
         let keyboard = Keyboard::new();
 
-        // Execute RND V1, 0xFF multiple times
-        // The random value should be different at least once in 10 tries
-        cpu.execute(0xC1FF, &mut memory, &mut display, &keyboard);
-        let first_value = cpu.v[1];
-        
-        let mut different = false;
+        cpu.set_rng(Box::new(SeededRandom::new(1234)));
+        let mut expected = SeededRandom::new(1234);
+
+        // RND V1, 0xFF copies the generator's bytes unmasked
+        let mut values = Vec::new();
         for _ in 0..10 {
-            cpu.execute(0xC1FF, &mut memory, &mut display, &keyboard);
-            if cpu.v[1] != first_value {
-                different = true;
-                break;
+            cpu.execute(0xC1FF, &mut memory, &mut display, &keyboard).unwrap();
+            assert_eq!(cpu.v[1], expected.next_byte());
+            values.push(cpu.v[1]);
+        }
+        assert!(values.iter().any(|&value| value != values[0]));
+
+        // RND V2, 0x0F keeps only the lower 4 bits
+        cpu.execute(0xC20F, &mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[2], expected.next_byte() & 0x0F);
+    }
+
+    #[test]
+    fn test_opcode_cxkk_uses_injected_rng() {
+        struct Fixed(u8);
+        impl RandomSource for Fixed {
+            fn next_byte(&mut self) -> u8 {
+                self.0
             }
         }
-        // Should be different at least once (probabilistically)
-        assert!(different || first_value == cpu.v[1]); // Always passes but exercises the code
 
-        // Test masking: RND V2, 0x0F should only set lower 4 bits
-        cpu.execute(0xC20F, &mut memory, &mut display, &keyboard);
-        assert!(cpu.v[2] <= 0x0F);
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        cpu.set_rng(Box::new(Fixed(0xAB)));
+
+        cpu.execute(0xC30F, &mut memory, &mut display, &keyboard).unwrap(); // RND V3, 0x0F
+        assert_eq!(cpu.v[3], 0x0B);
+        cpu.execute(0xC4F0, &mut memory, &mut display, &keyboard).unwrap(); // RND V4, 0xF0
+        assert_eq!(cpu.v[4], 0xA0);
     }
 
     #[test]
@@ -996,7 +1511,7 @@ mod tests {
         // Draw at position (5, 10) with height 3
         cpu.v[2] = 5;  // x
         cpu.v[3] = 10; // y
-        cpu.execute(0xD233, &mut memory, &mut display, &keyboard); // DRW V2, V3, 3
+        cpu.execute(0xD233, &mut memory, &mut display, &keyboard).unwrap(); // DRW V2, V3, 3
 
         // VF should be 0 (no collision on first draw)
         assert_eq!(cpu.v[0xF], 0);
@@ -1021,17 +1536,39 @@ mod tests {
         cpu.v[2] = 0;
 
         // Draw first time - no collision
-        cpu.execute(0xD121, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xD121, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[0xF], 0);
 
         // Tick timers to clear VBlank wait flag
         cpu.tick_timers();
 
         // Draw second time at same position - should have collision
-        cpu.execute(0xD121, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xD121, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[0xF], 1);
     }
 
+    #[test]
+    fn test_opcode_dxyn_follows_wrap_quirk_changes() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.i = 0x300;
+        memory.write(0x300, 0xFF);
+        cpu.v[1] = 60;
+
+        // VIP profile clips at the right edge
+        cpu.execute(0xD121, &mut memory, &mut display, &keyboard).unwrap();
+        assert!(!display.get_pixel(0, 0));
+
+        // Switching the quirk on a live CPU takes effect on the next draw
+        cpu.quirks.wrap_sprites = true;
+        cpu.tick_timers();
+        cpu.execute(0xD121, &mut memory, &mut display, &keyboard).unwrap();
+        assert!(display.get_pixel(0, 0));
+    }
+
     #[test]
     fn test_opcode_ex9e_skp_vx_pressed() {
         let mut cpu = Cpu::new();
@@ -1043,7 +1580,7 @@ mod tests {
         keyboard.set_key(0x0A, true);
         
         let old_pc = cpu.pc;
-        cpu.execute(0xE59E, &mut memory, &mut display, &keyboard); // SKP V5
+        cpu.execute(0xE59E, &mut memory, &mut display, &keyboard).unwrap(); // SKP V5
         assert_eq!(cpu.pc, old_pc + 2); // Should skip
     }
 
@@ -1058,7 +1595,7 @@ mod tests {
         // Key not pressed
         
         let old_pc = cpu.pc;
-        cpu.execute(0xE59E, &mut memory, &mut display, &keyboard); // SKP V5
+        cpu.execute(0xE59E, &mut memory, &mut display, &keyboard).unwrap(); // SKP V5
         assert_eq!(cpu.pc, old_pc); // Should not skip
     }
 
@@ -1073,7 +1610,7 @@ mod tests {
         // Key not pressed
         
         let old_pc = cpu.pc;
-        cpu.execute(0xE5A1, &mut memory, &mut display, &keyboard); // SKNP V5
+        cpu.execute(0xE5A1, &mut memory, &mut display, &keyboard).unwrap(); // SKNP V5
         assert_eq!(cpu.pc, old_pc + 2); // Should skip
     }
 
@@ -1088,7 +1625,7 @@ mod tests {
         keyboard.set_key(0x0A, true);
         
         let old_pc = cpu.pc;
-        cpu.execute(0xE5A1, &mut memory, &mut display, &keyboard); // SKNP V5
+        cpu.execute(0xE5A1, &mut memory, &mut display, &keyboard).unwrap(); // SKNP V5
         assert_eq!(cpu.pc, old_pc); // Should not skip
     }
 
@@ -1100,7 +1637,7 @@ mod tests {
         let keyboard = Keyboard::new();
 
         cpu.delay_timer = 42;
-        cpu.execute(0xF307, &mut memory, &mut display, &keyboard); // LD V3, DT
+        cpu.execute(0xF307, &mut memory, &mut display, &keyboard).unwrap(); // LD V3, DT
         assert_eq!(cpu.v[3], 42);
     }
 
@@ -1112,7 +1649,7 @@ mod tests {
         let keyboard = Keyboard::new();
 
         cpu.v[7] = 100;
-        cpu.execute(0xF715, &mut memory, &mut display, &keyboard); // LD DT, V7
+        cpu.execute(0xF715, &mut memory, &mut display, &keyboard).unwrap(); // LD DT, V7
         assert_eq!(cpu.delay_timer, 100);
     }
 
@@ -1124,7 +1661,7 @@ mod tests {
         let keyboard = Keyboard::new();
 
         cpu.v[2] = 60;
-        cpu.execute(0xF218, &mut memory, &mut display, &keyboard); // LD ST, V2
+        cpu.execute(0xF218, &mut memory, &mut display, &keyboard).unwrap(); // LD ST, V2
         assert_eq!(cpu.sound_timer, 60);
     }
 
@@ -1137,7 +1674,7 @@ mod tests {
 
         cpu.i = 0x100;
         cpu.v[5] = 0x50;
-        cpu.execute(0xF51E, &mut memory, &mut display, &keyboard); // ADD I, V5
+        cpu.execute(0xF51E, &mut memory, &mut display, &keyboard).unwrap(); // ADD I, V5
         assert_eq!(cpu.i, 0x150);
     }
 
@@ -1150,7 +1687,7 @@ mod tests {
 
         cpu.i = 0xFFF0;
         cpu.v[5] = 0x20;
-        cpu.execute(0xF51E, &mut memory, &mut display, &keyboard); // ADD I, V5
+        cpu.execute(0xF51E, &mut memory, &mut display, &keyboard).unwrap(); // ADD I, V5
         assert_eq!(cpu.i, 0x0010); // Should wrap
     }
 
@@ -1163,7 +1700,7 @@ mod tests {
 
         cpu.i = 0x300;
         cpu.v[7] = 234; // 234 = 2 hundreds, 3 tens, 4 ones
-        cpu.execute(0xF733, &mut memory, &mut display, &keyboard); // LD B, V7
+        cpu.execute(0xF733, &mut memory, &mut display, &keyboard).unwrap(); // LD B, V7
         
         assert_eq!(memory.read(0x300), 2); // Hundreds
         assert_eq!(memory.read(0x301), 3); // Tens
@@ -1179,7 +1716,7 @@ mod tests {
 
         cpu.i = 0x400;
         cpu.v[2] = 5; // 005
-        cpu.execute(0xF233, &mut memory, &mut display, &keyboard); // LD B, V2
+        cpu.execute(0xF233, &mut memory, &mut display, &keyboard).unwrap(); // LD B, V2
         
         assert_eq!(memory.read(0x400), 0);
         assert_eq!(memory.read(0x401), 0);
@@ -1199,7 +1736,7 @@ mod tests {
         cpu.v[2] = 30;
         cpu.v[3] = 40;
         
-        cpu.execute(0xF355, &mut memory, &mut display, &keyboard); // LD [I], V3
+        cpu.execute(0xF355, &mut memory, &mut display, &keyboard).unwrap(); // LD [I], V3
         
         // Should store V0 through V3
         assert_eq!(memory.read(0x300), 10);
@@ -1220,7 +1757,7 @@ mod tests {
         memory.write(0x301, 200);
         memory.write(0x302, 150);
         
-        cpu.execute(0xF265, &mut memory, &mut display, &keyboard); // LD V2, [I]
+        cpu.execute(0xF265, &mut memory, &mut display, &keyboard).unwrap(); // LD V2, [I]
         
         // Should load into V0 through V2
         assert_eq!(cpu.v[0], 100);
@@ -1238,19 +1775,19 @@ mod tests {
         cpu.pc = 0x200;
         
         // First execution - no key pressed, should wait
-        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x1FE); // Decremented to repeat
         
         // Press key 0x0A
         keyboard.set_key(0x0A, true);
         cpu.pc = 0x200;
-        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.pc, 0x1FE); // Still waiting for release
         
         // Release key
         keyboard.set_key(0x0A, false);
         cpu.pc = 0x200;
-        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard);
+        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard).unwrap();
         assert_eq!(cpu.v[3], 0x0A); // Key stored
         assert_eq!(cpu.pc, 0x200); // PC advances normally
     }
@@ -1264,12 +1801,27 @@ mod tests {
 
         cpu.pc = 0x200;
         
-        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard); // LD V3, K
+        cpu.execute(0xF30A, &mut memory, &mut display, &keyboard).unwrap(); // LD V3, K
         
         // PC should be decremented by 2 to repeat the instruction
         assert_eq!(cpu.pc, 0x1FE);
     }
 
+    #[test]
+    fn test_repeated_instruction_at_top_of_memory() {
+        // PC wrapped to 0 after fetching from 0xFFFE; repeating must wrap back
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.pc = 0xFFFE;
+        memory.write(0xFFFE, 0xF3); // LD V3, K
+        memory.write(0xFFFF, 0x0A);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0xFFFE);
+    }
+
     #[test]
     fn test_opcode_fx29_ld_f_vx() {
         let mut cpu = Cpu::new();
@@ -1279,17 +1831,17 @@ mod tests {
 
         // Test digit 0 (font at 0x000)
         cpu.v[2] = 0;
-        cpu.execute(0xF229, &mut memory, &mut display, &keyboard); // LD F, V2
+        cpu.execute(0xF229, &mut memory, &mut display, &keyboard).unwrap(); // LD F, V2
         assert_eq!(cpu.i, 0x000);
 
         // Test digit 5 (font at 0x019 = 5 * 5)
         cpu.v[3] = 5;
-        cpu.execute(0xF329, &mut memory, &mut display, &keyboard); // LD F, V3
+        cpu.execute(0xF329, &mut memory, &mut display, &keyboard).unwrap(); // LD F, V3
         assert_eq!(cpu.i, 25); // 5 * 5
 
         // Test digit F (font at 0x04B = 15 * 5)
         cpu.v[4] = 0xF;
-        cpu.execute(0xF429, &mut memory, &mut display, &keyboard); // LD F, V4
+        cpu.execute(0xF429, &mut memory, &mut display, &keyboard).unwrap(); // LD F, V4
         assert_eq!(cpu.i, 75); // 15 * 5
     }
 
@@ -1304,11 +1856,390 @@ mod tests {
         memory.write(0x200, 0x61);
         memory.write(0x201, 0x42);
 
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
 
         // V1 should now be 0x42
         assert_eq!(cpu.v[1], 0x42);
         // PC should have advanced to 0x202
         assert_eq!(cpu.pc, 0x202);
     }
+
+    // === Quirk Tests ===
+
+    #[test]
+    fn test_quirk_vf_reset_disabled() {
+        let mut cpu = Cpu::with_quirks(Quirks::chip48());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[1] = 0b1100;
+        cpu.v[2] = 0b1010;
+        cpu.v[0xF] = 0x55;
+        cpu.execute(0x8121, &mut memory, &mut display, &keyboard).unwrap(); // OR V1, V2
+        assert_eq!(cpu.v[1], 0b1110);
+        assert_eq!(cpu.v[0xF], 0x55); // VF untouched
+    }
+
+    #[test]
+    fn test_quirk_shift_vx_in_place() {
+        let mut cpu = Cpu::with_quirks(Quirks::schip_modern());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[5] = 0b0000_0011;
+        cpu.v[3] = 0b1000_0000;
+        cpu.execute(0x8536, &mut memory, &mut display, &keyboard).unwrap(); // SHR V5 (Vy ignored)
+        assert_eq!(cpu.v[5], 0b0000_0001);
+        assert_eq!(cpu.v[0xF], 1);
+
+        cpu.v[5] = 0b1000_0001;
+        cpu.execute(0x853E, &mut memory, &mut display, &keyboard).unwrap(); // SHL V5 (Vy ignored)
+        assert_eq!(cpu.v[5], 0b0000_0010);
+        assert_eq!(cpu.v[0xF], 1);
+    }
+
+    #[test]
+    fn test_quirk_memory_increment_modes() {
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        let mut cpu = Cpu::with_quirks(Quirks::chip48());
+        cpu.i = 0x300;
+        cpu.execute(0xF355, &mut memory, &mut display, &keyboard).unwrap(); // LD [I], V3
+        assert_eq!(cpu.i, 0x303); // I += x
+
+        let mut cpu = Cpu::with_quirks(Quirks::schip_modern());
+        cpu.i = 0x300;
+        cpu.execute(0xF365, &mut memory, &mut display, &keyboard).unwrap(); // LD V3, [I]
+        assert_eq!(cpu.i, 0x300); // I unchanged
+    }
+
+    #[test]
+    fn test_quirk_jump_vx() {
+        let mut cpu = Cpu::with_quirks(Quirks::chip48());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[0] = 0x01;
+        cpu.v[2] = 0x10;
+        cpu.execute(0xB234, &mut memory, &mut display, &keyboard).unwrap(); // JP V2, 0x234
+        assert_eq!(cpu.pc, 0x244); // 0x234 + V2
+    }
+
+    #[test]
+    fn test_quirk_display_wait_disabled() {
+        let mut cpu = Cpu::with_quirks(Quirks::schip_modern());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // Two DRW instructions back to back
+        memory.write(0x200, 0xD0);
+        memory.write(0x201, 0x01);
+        memory.write(0x202, 0xD0);
+        memory.write(0x203, 0x01);
+
+        // Without DISP.WAIT, cycle never asks the frame loop to stop
+        assert_eq!(cpu.cycle(&mut memory, &mut display, &keyboard), Ok(StepOutcome::Continue));
+        assert!(!cpu.is_waiting_for_vblank());
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x204); // Second draw was not deferred
+    }
+
+    // === Super-CHIP Tests ===
+
+    #[test]
+    fn test_opcode_00ff_00fe_hires_toggle() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.execute(0x00FF, &mut memory, &mut display, &keyboard).unwrap(); // HIGH
+        assert!(display.is_hires());
+        cpu.execute(0x00FE, &mut memory, &mut display, &keyboard).unwrap(); // LOW
+        assert!(!display.is_hires());
+    }
+
+    #[test]
+    fn test_opcode_00cn_scroll_down() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        display.set_pixel(3, 0, true);
+        cpu.execute(0x00C3, &mut memory, &mut display, &keyboard).unwrap(); // SCD 3
+        assert!(display.get_pixel(3, 3));
+        assert!(!display.get_pixel(3, 0));
+    }
+
+    #[test]
+    fn test_opcode_00fb_00fc_scroll_horizontal() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        display.set_pixel(10, 0, true);
+        cpu.execute(0x00FB, &mut memory, &mut display, &keyboard).unwrap(); // SCR
+        assert!(display.get_pixel(14, 0));
+        cpu.execute(0x00FC, &mut memory, &mut display, &keyboard).unwrap(); // SCL
+        cpu.execute(0x00FC, &mut memory, &mut display, &keyboard).unwrap(); // SCL
+        assert!(display.get_pixel(6, 0));
+    }
+
+    #[test]
+    fn test_opcode_00fd_exit() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        memory.write(0x200, 0x00);
+        memory.write(0x201, 0xFD); // EXIT
+        memory.write(0x202, 0x61);
+        memory.write(0x203, 0x42); // LD V1, 0x42 (never reached)
+
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert!(cpu.has_exited());
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.v[1], 0);
+    }
+
+    #[test]
+    fn test_opcode_dxy0_draws_16x16() {
+        let mut cpu = Cpu::with_quirks(Quirks::schip_modern());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.i = 0x300;
+        for offset in 0..32 {
+            memory.write(0x300 + offset, 0xFF);
+        }
+        display.set_hires(true);
+        cpu.execute(0xD010, &mut memory, &mut display, &keyboard).unwrap(); // DRW V0, V1, 0
+        assert!(display.get_pixel(0, 0));
+        assert!(display.get_pixel(15, 15));
+        assert!(!display.get_pixel(16, 0));
+        assert_eq!(cpu.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_opcode_fx30_ld_hf_vx() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[2] = 3;
+        cpu.execute(0xF230, &mut memory, &mut display, &keyboard).unwrap(); // LD HF, V2
+        assert_eq!(cpu.i, BIG_FONT_ADDR + 30);
+    }
+
+    #[test]
+    fn test_opcode_fx75_fx85_rpl_flags() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[0] = 0x11;
+        cpu.v[1] = 0x22;
+        cpu.v[2] = 0x33;
+        cpu.execute(0xF275, &mut memory, &mut display, &keyboard).unwrap(); // LD R, V2
+        cpu.v = [0; 16];
+        cpu.execute(0xF185, &mut memory, &mut display, &keyboard).unwrap(); // LD V1, R
+        assert_eq!(cpu.v[0], 0x11);
+        assert_eq!(cpu.v[1], 0x22);
+        assert_eq!(cpu.v[2], 0x00); // Only V0..=V1 restored
+    }
+
+    // === XO-CHIP Tests ===
+
+    #[test]
+    fn test_opcode_f000_ld_i_long() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // F000 1234 - LD I, 0x1234
+        memory.write(0x200, 0xF0);
+        memory.write(0x201, 0x00);
+        memory.write(0x202, 0x12);
+        memory.write(0x203, 0x34);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.i, 0x1234);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn test_skip_over_long_load() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        // 3000 (SE V0, 0x00) followed by F000 nnnn: the skip covers all 4 bytes
+        memory.write(0x200, 0x30);
+        memory.write(0x201, 0x00);
+        memory.write(0x202, 0xF0);
+        memory.write(0x203, 0x00);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn test_opcode_5xy2_5xy3_register_range() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.i = 0x400;
+        cpu.v[2] = 0xAA;
+        cpu.v[3] = 0xBB;
+        cpu.v[4] = 0xCC;
+        cpu.execute(0x5242, &mut memory, &mut display, &keyboard).unwrap(); // SAVE V2 - V4
+        assert_eq!(memory.read(0x400), 0xAA);
+        assert_eq!(memory.read(0x402), 0xCC);
+        assert_eq!(cpu.i, 0x400); // I unchanged
+
+        // Reverse order: LOAD V4 - V2 reads V4 first
+        cpu.execute(0x5423, &mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(cpu.v[4], 0xAA);
+        assert_eq!(cpu.v[3], 0xBB);
+        assert_eq!(cpu.v[2], 0xCC);
+    }
+
+    #[test]
+    fn test_opcode_fn01_plane_and_two_plane_draw() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.execute(0xF301, &mut memory, &mut display, &keyboard).unwrap(); // PLANE 3
+        assert_eq!(display.selected_planes(), 3);
+
+        // DRW with n=1 reads two bytes: one per plane
+        cpu.i = 0x300;
+        memory.write(0x300, 0b10000000);
+        memory.write(0x301, 0b11000000);
+        cpu.execute(0xD011, &mut memory, &mut display, &keyboard).unwrap();
+        assert_eq!(display.get_pixel_value(0, 0), 3);
+        assert_eq!(display.get_pixel_value(1, 0), 2);
+    }
+
+    #[test]
+    fn test_opcode_00dn_scroll_up() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        display.set_pixel(0, 4, true);
+        cpu.execute(0x00D2, &mut memory, &mut display, &keyboard).unwrap(); // SCU 2
+        assert!(display.get_pixel(0, 2));
+    }
+
+    #[test]
+    fn test_opcode_f002_audio_pattern() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::for_variant(Variant::XoChip);
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        assert_eq!(cpu.audio_pattern, DEFAULT_AUDIO_PATTERN);
+        cpu.i = 0x500;
+        for offset in 0..16 {
+            memory.write(0x500 + offset, offset as u8);
+        }
+        cpu.execute(0xF002, &mut memory, &mut display, &keyboard).unwrap(); // AUDIO
+        assert_eq!(cpu.audio_pattern[0], 0);
+        assert_eq!(cpu.audio_pattern[15], 15);
+        assert_eq!(cpu.i, 0x500); // I unchanged
+    }
+
+    #[test]
+    fn test_opcode_fx3a_pitch() {
+        let mut cpu = Cpu::with_quirks(Quirks::xo_chip());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        assert_eq!(cpu.pitch, DEFAULT_PITCH);
+        cpu.v[7] = 112;
+        cpu.execute(0xF73A, &mut memory, &mut display, &keyboard).unwrap(); // PITCH V7
+        assert_eq!(cpu.pitch, 112);
+    }
+
+    // === Memory Policy Tests ===
+
+    #[test]
+    fn test_fx33_near_end_of_memory_wraps() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+
+        cpu.v[0] = 123;
+        cpu.i = 0xFFF;
+        cpu.execute(0xF033, &mut memory, &mut display, &keyboard).unwrap(); // LD B, V0
+        assert_eq!(memory.read(0xFFF), 1);
+        assert_eq!(memory.read(0x000), 2); // Wrapped (default policy)
+        assert_eq!(memory.read(0x001), 3);
+    }
+
+    #[test]
+    fn test_strict_policy_reports_memory_error() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        memory.set_policy(AddressPolicy::Strict);
+
+        // FF55 - LD [I], VF with I = 0xFF8 runs past 0xFFF
+        memory.write(0x200, 0xFF);
+        memory.write(0x201, 0x55);
+        cpu.i = 0xFF8;
+        let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+        assert_eq!(error, CpuError::MemoryOutOfBounds { pc: 0x200, opcode: 0xFF55, addr: 0x1000 });
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn test_strict_policy_fetch_at_end_of_memory() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        memory.set_policy(AddressPolicy::Strict);
+
+        cpu.pc = 0xFFF;
+        let error = cpu.cycle(&mut memory, &mut display, &keyboard).unwrap_err();
+        assert_eq!(error, CpuError::MemoryOutOfBounds { pc: 0xFFF, opcode: 0, addr: 0x1000 });
+    }
+
+    #[test]
+    fn test_open_bus_draw_reads_zero() {
+        let mut cpu = Cpu::with_quirks(Quirks::schip_modern());
+        let mut memory = Memory::new();
+        let mut display = Display::new();
+        let keyboard = Keyboard::new();
+        memory.set_policy(AddressPolicy::OpenBus);
+
+        cpu.i = 0xFFF;
+        memory.write(0xFFF, 0xFF);
+        cpu.execute(0xD002, &mut memory, &mut display, &keyboard).unwrap(); // DRW V0, V0, 2
+        assert!(display.get_pixel(0, 0));
+        assert!(!display.get_pixel(0, 1)); // Second row came from the open bus
+    }
 }
@@ -0,0 +1,812 @@
+//! Debugger module for CHIP-8
+//!
+//! A `Debugger` is a `CycleHook`: it runs a `Machine` frame by frame and
+//! stops before an instruction at a breakpoint or of a watched opcode class,
+//! or after an instruction that wrote a watched address or made a register
+//! condition true. Stepping commands (`step`, `next`, `finish`) are built on
+//! the same hook. Every run is recorded in a `History`, so `step_back` and
+//! `reverse_cont` can rebuild earlier states by re-executing from a checkpoint.
+//! `Command` parses the REPL syntax used by the `debugger` binary.
+
+use crate::cpu::{Cpu, CpuError, StepOutcome};
+use crate::disassembler::disassemble;
+use crate::history::History;
+use crate::machine::{CycleHook, Machine};
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// A register that can be used in a break condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Dt,
+    St,
+}
+
+impl Register {
+    /// Parses `V0`-`VF`, `I`, `DT` or `ST` (case-insensitive)
+    pub fn parse(text: &str) -> Option<Self> {
+        let upper = text.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Some(Register::I),
+            "DT" => Some(Register::Dt),
+            "ST" => Some(Register::St),
+            _ => {
+                let digit = upper.strip_prefix('V')?;
+                if digit.len() != 1 {
+                    return None;
+                }
+                u8::from_str_radix(digit, 16).ok().map(Register::V)
+            }
+        }
+    }
+
+    /// Current value of the register
+    pub fn value(&self, cpu: &Cpu) -> u16 {
+        match *self {
+            Register::V(x) => cpu.v[x as usize] as u16,
+            Register::I => cpu.i,
+            Register::Dt => cpu.delay_timer as u16,
+            Register::St => cpu.sound_timer as u16,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+/// Stops when a register becomes equal to a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterCondition {
+    pub register: Register,
+    pub value: u16,
+}
+
+impl RegisterCondition {
+    /// Returns true if the condition currently holds
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        self.register.value(cpu) == self.value
+    }
+}
+
+impl fmt::Display for RegisterCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} == {:#04x}", self.register, self.value)
+    }
+}
+
+/// Why the debugger stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakReason {
+    /// About to execute an instruction at a breakpoint
+    Breakpoint { pc: u16 },
+    /// About to execute an instruction of a watched opcode class
+    Opcode { pc: u16, opcode: u16 },
+    /// The instruction at `pc` wrote to a watched address
+    Watchpoint { addr: u16, pc: u16 },
+    /// A register condition became true
+    Condition(RegisterCondition),
+    /// A `step`, `next` or `finish` completed
+    Step,
+    /// The program executed 00FD (EXIT)
+    Exited,
+    /// The CPU faulted
+    Halted(CpuError),
+    /// The frame limit ran out before anything else stopped execution
+    FrameLimit,
+    /// The caller's interrupt check asked to stop (e.g. Ctrl-C from a remote debugger)
+    Interrupted,
+    /// Reverse execution reached the oldest recorded state
+    HistoryStart,
+}
+
+impl BreakReason {
+    /// Describes the stop, printing addresses with the symbols that cover them
+    pub fn describe(&self, symbols: &Symbols) -> String {
+        match self {
+            BreakReason::Breakpoint { pc } => format!("Breakpoint at {}", symbols.describe(*pc)),
+            BreakReason::Opcode { pc, opcode } => {
+                format!("Opcode break at {}: {}", symbols.describe(*pc), symbols.disassemble(*opcode))
+            }
+            BreakReason::Watchpoint { addr, pc } => format!(
+                "Watchpoint: {} written by instruction at {}",
+                symbols.describe(*addr),
+                symbols.describe(*pc)
+            ),
+            BreakReason::Condition(condition) => format!("Condition met: {}", condition),
+            BreakReason::Step => "Stepped".to_string(),
+            BreakReason::Exited => "Program exited".to_string(),
+            BreakReason::Halted(error) => format!("CPU halted: {}", error),
+            BreakReason::FrameLimit => "Frame limit reached".to_string(),
+            BreakReason::Interrupted => "Interrupted".to_string(),
+            BreakReason::HistoryStart => "Reached the start of the recorded history".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(&Symbols::new()))
+    }
+}
+
+/// How far the current run goes before stopping on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    /// Until a breakpoint, watchpoint or condition
+    Continue,
+    /// For this many more instructions
+    Step(u32),
+    /// Until PC reaches `pc` with the stack no deeper than `sp` (step over a CALL)
+    Until { pc: u16, sp: u8 },
+    /// Until the stack is shallower than `sp` (return from the current subroutine)
+    Finish { sp: u8 },
+    /// Re-executing history until cycle `target`, with or without break checks
+    Replay { target: u64, checks: bool },
+}
+
+/// Breakpoints, watchpoints and stepping for a `Machine`
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: BTreeSet<u16>,
+    conditions: Vec<RegisterCondition>,
+    /// Mnemonics (as printed by `disassemble`) that stop execution, e.g. "DRW"
+    opcode_classes: BTreeSet<String>,
+    mode: RunMode,
+    /// PC whose break checks are skipped: where a run resumed, or an instruction that is repeating (Fx0A, DISP.WAIT)
+    skip_pc: Option<u16>,
+    /// PC of the instruction being executed
+    current_pc: u16,
+    /// Which conditions held before the instruction, so only a change to true stops
+    held: Vec<bool>,
+    stop: Option<BreakReason>,
+    history: History,
+}
+
+impl Debugger {
+    /// Creates a debugger with no breakpoints
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            conditions: Vec::new(),
+            opcode_classes: BTreeSet::new(),
+            mode: RunMode::Continue,
+            skip_pc: None,
+            current_pc: 0,
+            held: Vec::new(),
+            stop: None,
+            history: History::new(),
+        }
+    }
+
+    /// Stops before executing the instruction at `pc`
+    pub fn add_breakpoint(&mut self, pc: u16) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// Stops after any instruction that writes to `addr`
+    pub fn add_watchpoint(&mut self, addr: u16) {
+        self.watchpoints.insert(addr);
+    }
+
+    /// Stops when the condition changes from false to true
+    pub fn add_condition(&mut self, condition: RegisterCondition) {
+        if !self.conditions.contains(&condition) {
+            self.conditions.push(condition);
+        }
+    }
+
+    /// Stops before every instruction with this mnemonic (e.g. "DRW", "CALL")
+    pub fn add_opcode_class(&mut self, mnemonic: &str) {
+        self.opcode_classes.insert(mnemonic.to_ascii_uppercase());
+    }
+
+    /// Removes a breakpoint or watchpoint at `addr`; returns false if there was none
+    pub fn remove(&mut self, addr: u16) -> bool {
+        let breakpoint = self.breakpoints.remove(&addr);
+        let watchpoint = self.watchpoints.remove(&addr);
+        breakpoint || watchpoint
+    }
+
+    /// Removes the breakpoint at `pc`; returns false if there was none
+    pub fn remove_breakpoint(&mut self, pc: u16) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    /// Removes the watchpoint at `addr`; returns false if there was none
+    pub fn remove_watchpoint(&mut self, addr: u16) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    /// Removes every breakpoint, watchpoint, condition and opcode class
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.conditions.clear();
+        self.opcode_classes.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.watchpoints.iter().copied()
+    }
+
+    pub fn conditions(&self) -> &[RegisterCondition] {
+        &self.conditions
+    }
+
+    pub fn opcode_classes(&self) -> impl Iterator<Item = &str> {
+        self.opcode_classes.iter().map(String::as_str)
+    }
+
+    /// The recorded execution used for reverse stepping
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Runs until something stops execution or `max_frames` frames complete
+    pub fn cont(&mut self, machine: &mut Machine, max_frames: u64) -> BreakReason {
+        self.run(machine, RunMode::Continue, max_frames, &mut || false)
+    }
+
+    /// Runs until something stops execution, checking `interrupt` after every frame
+    pub fn cont_until(&mut self, machine: &mut Machine, mut interrupt: impl FnMut() -> bool) -> BreakReason {
+        self.run(machine, RunMode::Continue, u64::MAX, &mut interrupt)
+    }
+
+    /// Executes `count` instructions (fewer if something else stops first)
+    pub fn step(&mut self, machine: &mut Machine, count: u32) -> BreakReason {
+        if count == 0 {
+            return BreakReason::Step;
+        }
+        self.run(machine, RunMode::Step(count), u64::MAX, &mut || false)
+    }
+
+    /// Executes one instruction, running a CALL through to its return
+    pub fn next(&mut self, machine: &mut Machine, max_frames: u64) -> BreakReason {
+        let cpu = machine.cpu();
+        if machine.memory().read_opcode(cpu.pc) & 0xF000 == 0x2000 {
+            let mode = RunMode::Until { pc: cpu.pc.wrapping_add(2), sp: cpu.sp };
+            self.run(machine, mode, max_frames, &mut || false)
+        } else {
+            self.step(machine, 1)
+        }
+    }
+
+    /// Runs until the current subroutine returns
+    /// Returns None if the CPU is not inside a subroutine.
+    pub fn finish(&mut self, machine: &mut Machine, max_frames: u64) -> Option<BreakReason> {
+        let sp = machine.cpu().sp;
+        if sp == 0 {
+            return None;
+        }
+        Some(self.run(machine, RunMode::Finish { sp }, max_frames, &mut || false))
+    }
+
+    /// Goes back `count` instructions
+    /// Stops at the oldest recorded state if the history is shorter than that.
+    pub fn step_back(&mut self, machine: &mut Machine, count: u32) -> BreakReason {
+        self.history.resume(machine, Some(machine.cpu().pc));
+        let position = self.history.position();
+        let target = position.saturating_sub(count as u64);
+        if self.rewind_to(machine, target) && position >= count as u64 {
+            BreakReason::Step
+        } else {
+            BreakReason::HistoryStart
+        }
+    }
+
+    /// Runs backwards to the most recent earlier point where a breakpoint,
+    /// watchpoint, opcode class or condition stopped (or would have stopped) execution
+    pub fn reverse_cont(&mut self, machine: &mut Machine) -> BreakReason {
+        self.history.resume(machine, Some(machine.cpu().pc));
+        let start = self.history.position();
+        let mut end = start;
+        // Replay one checkpoint interval at a time, newest first, keeping its last stop
+        while let Some(from) = self.history.checkpoint_before(end) {
+            let mut last_stop = None;
+            if let Some((_, skip_pc)) = self.history.restore(machine, from) {
+                self.skip_pc = skip_pc;
+                loop {
+                    match self.run(machine, RunMode::Replay { target: end, checks: true }, u64::MAX, &mut || false) {
+                        BreakReason::Step | BreakReason::Exited | BreakReason::Halted(_) => break,
+                        reason => last_stop = Some((self.history.position(), reason)),
+                    }
+                    self.skip_pc = Some(machine.cpu().pc);
+                }
+            }
+            if let Some((cycle, reason)) = last_stop.filter(|&(cycle, _)| cycle < start) {
+                self.rewind_to(machine, cycle);
+                return reason;
+            }
+            end = from;
+        }
+        self.rewind_to(machine, 0);
+        BreakReason::HistoryStart
+    }
+
+    /// Rebuilds the state at `target` (or the oldest recorded one if `target` is older)
+    /// Returns false if the target was out of reach.
+    fn rewind_to(&mut self, machine: &mut Machine, target: u64) -> bool {
+        let reached = match self.history.restore(machine, target) {
+            Some(restored) => Some(restored),
+            None => self.history.oldest().and_then(|oldest| self.history.restore(machine, oldest)),
+        };
+        let Some((cycle, skip_pc)) = reached else {
+            return false;
+        };
+        self.skip_pc = skip_pc;
+        self.run(machine, RunMode::Replay { target, checks: false }, u64::MAX, &mut || false);
+        cycle <= target
+    }
+
+    fn run(
+        &mut self,
+        machine: &mut Machine,
+        mode: RunMode,
+        max_frames: u64,
+        interrupt: &mut dyn FnMut() -> bool,
+    ) -> BreakReason {
+        self.mode = mode;
+        self.stop = None;
+        // Replays resume with the skip PC saved in their checkpoint
+        if !matches!(mode, RunMode::Replay { .. }) {
+            self.skip_pc = Some(machine.cpu().pc);
+            self.history.resume(machine, self.skip_pc);
+        }
+        let reason = self.run_frames(machine, max_frames, interrupt);
+        self.history.park(machine);
+        reason
+    }
+
+    fn run_frames(&mut self, machine: &mut Machine, max_frames: u64, interrupt: &mut dyn FnMut() -> bool) -> BreakReason {
+        let tracking = !self.watchpoints.is_empty();
+        machine.memory_mut().set_write_tracking(tracking);
+        machine.memory_mut().take_writes();
+
+        let mut frames = 0;
+        loop {
+            let result = machine.run_frame_with(self);
+            if let Some(reason) = self.stop.take() {
+                return reason;
+            }
+            match result {
+                Ok(Some(StepOutcome::Exited)) => return BreakReason::Exited,
+                Ok(_) => {}
+                Err(error) => return BreakReason::Halted(error),
+            }
+            frames += 1;
+            if frames >= max_frames {
+                return BreakReason::FrameLimit;
+            }
+            if interrupt() {
+                return BreakReason::Interrupted;
+            }
+        }
+    }
+}
+
+impl CycleHook for Debugger {
+    fn before_cycle(&mut self, machine: &mut Machine) -> bool {
+        let pc = machine.cpu().pc;
+        self.current_pc = pc;
+        self.held = self.conditions.iter().map(|c| c.holds(machine.cpu())).collect();
+
+        if let RunMode::Replay { target, .. } = self.mode {
+            if self.history.position() >= target {
+                self.stop = Some(BreakReason::Step);
+                return false;
+            }
+        }
+        self.history.before_cycle(machine, self.skip_pc);
+
+        let silent = matches!(self.mode, RunMode::Replay { checks: false, .. });
+        if self.skip_pc.take() == Some(pc) || silent {
+            return true;
+        }
+        if self.breakpoints.contains(&pc) {
+            self.stop = Some(BreakReason::Breakpoint { pc });
+            return false;
+        }
+        if let RunMode::Until { pc: target, sp } = self.mode {
+            if pc == target && machine.cpu().sp <= sp {
+                self.stop = Some(BreakReason::Step);
+                return false;
+            }
+        }
+        if !self.opcode_classes.is_empty() {
+            let opcode = machine.memory().read_opcode(pc);
+            let text = disassemble(opcode);
+            let mnemonic = text.split_whitespace().next().unwrap_or("");
+            if self.opcode_classes.contains(mnemonic) {
+                self.stop = Some(BreakReason::Opcode { pc, opcode });
+                return false;
+            }
+        }
+        true
+    }
+
+    fn after_cycle(&mut self, machine: &mut Machine) -> bool {
+        self.history.after_cycle(machine);
+        let pc = self.current_pc;
+        // An instruction that leaves PC unchanged is waiting (Fx0A, DISP.WAIT) or looping
+        // on itself; don't break on it again until it moves on
+        if machine.cpu().pc == pc {
+            self.skip_pc = Some(pc);
+        }
+
+        let writes = machine.memory_mut().take_writes();
+        if let RunMode::Replay { checks: false, .. } = self.mode {
+            return true;
+        }
+        if let Some(&addr) = writes.iter().find(|addr| self.watchpoints.contains(addr)) {
+            self.stop = Some(BreakReason::Watchpoint { addr, pc });
+            return false;
+        }
+
+        let cpu = machine.cpu();
+        let met = self.conditions.iter().zip(&self.held).find(|(c, &held)| !held && c.holds(cpu));
+        if let Some((&condition, _)) = met {
+            self.stop = Some(BreakReason::Condition(condition));
+            return false;
+        }
+
+        match self.mode {
+            RunMode::Step(remaining) => {
+                self.mode = RunMode::Step(remaining - 1);
+                if remaining == 1 {
+                    self.stop = Some(BreakReason::Step);
+                    return false;
+                }
+            }
+            RunMode::Finish { sp } if cpu.sp < sp => {
+                self.stop = Some(BreakReason::Step);
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A debugger REPL command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `break <addr>`
+    Break(u16),
+    /// `break op <mnemonic>`
+    BreakOpcode(String),
+    /// `break <reg> == <value>`
+    BreakWhen(RegisterCondition),
+    /// `watch <addr>`
+    Watch(u16),
+    /// `delete [addr]` (no address deletes everything)
+    Delete(Option<u16>),
+    /// `step [count]`
+    Step(u32),
+    /// `next`
+    Next,
+    /// `finish`
+    Finish,
+    /// `continue [frames]`
+    Continue(Option<u64>),
+    /// `step-back [count]`
+    StepBack(u32),
+    /// `reverse-continue`
+    ReverseContinue,
+    /// `regs`
+    Regs,
+    /// `mem <addr> [len]`
+    Mem { addr: u16, len: u16 },
+    /// `disasm [addr] [count]`
+    Disasm { addr: Option<u16>, count: u16 },
+    /// `key <key> <down|up>`
+    Key { key: u8, pressed: bool },
+    /// `info`
+    Info,
+    /// `help`
+    Help,
+    /// `quit`
+    Quit,
+}
+
+/// One line per command, for `help`
+pub const COMMAND_HELP: [&str; 17] = [
+    "break <addr|label>      Stop before the instruction at addr or label[+n] (b)",
+    "break op <mnemonic>     Stop before every instruction of a kind, e.g. break op DRW",
+    "break <reg> == <value>  Stop when V0-VF, I, DT or ST becomes value",
+    "watch <addr>            Stop after an instruction writes addr (w)",
+    "delete [addr]           Delete the break/watchpoint at addr, or everything (d)",
+    "step [n]                Execute n instructions (s)",
+    "next                    Step, running CALLs through to their return (n)",
+    "finish                  Run until the current subroutine returns",
+    "continue [frames]       Run until something stops execution (c)",
+    "step-back [n]           Go back n instructions (sb)",
+    "reverse-continue        Run backwards to the previous stop (rc)",
+    "regs                    Show registers (r)",
+    "mem <addr> [len]        Hex dump memory (x)",
+    "disasm [addr] [n]       Disassemble n instructions from addr (default PC)",
+    "key <key> <down|up>     Press or release a keypad key (0-F)",
+    "info                    List breakpoints, watchpoints and conditions",
+    "quit                    Exit (q); an empty line repeats the last command",
+];
+
+impl Command {
+    /// Parses a REPL line. Addresses are hex (0x optional), counts are decimal,
+    /// condition values are decimal or 0x-prefixed hex.
+    pub fn parse(line: &str) -> Result<Command, String> {
+        Self::parse_with_symbols(line, &Symbols::new())
+    }
+
+    /// Like `parse`, also accepting `label` or `label+n` wherever an address goes
+    pub fn parse_with_symbols(line: &str, symbols: &Symbols) -> Result<Command, String> {
+        let parse_addr = |text: &str| parse_addr(text, symbols);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return Err("Empty command".to_string());
+        };
+        let arg = |index: usize| args.get(index).copied();
+
+        match name {
+            "break" | "b" => match args {
+                ["op", mnemonic] => Ok(Command::BreakOpcode(mnemonic.to_ascii_uppercase())),
+                [register, "==", value] => {
+                    let register = Register::parse(register)
+                        .ok_or_else(|| format!("Unknown register '{}'", register))?;
+                    Ok(Command::BreakWhen(RegisterCondition { register, value: parse_value(value)? }))
+                }
+                [addr] => Ok(Command::Break(parse_addr(addr)?)),
+                _ => Err("Usage: break <addr> | break op <mnemonic> | break <reg> == <value>".to_string()),
+            },
+            "watch" | "w" => Ok(Command::Watch(parse_addr(arg(0).ok_or("Usage: watch <addr>")?)?)),
+            "delete" | "d" => Ok(Command::Delete(arg(0).map(parse_addr).transpose()?)),
+            "step" | "s" => Ok(Command::Step(arg(0).map(parse_count).transpose()?.unwrap_or(1))),
+            "next" | "n" => Ok(Command::Next),
+            "finish" => Ok(Command::Finish),
+            "continue" | "c" => Ok(Command::Continue(arg(0).map(parse_count).transpose()?)),
+            "step-back" | "sb" => Ok(Command::StepBack(arg(0).map(parse_count).transpose()?.unwrap_or(1))),
+            "reverse-continue" | "rc" => Ok(Command::ReverseContinue),
+            "regs" | "r" => Ok(Command::Regs),
+            "mem" | "x" => Ok(Command::Mem {
+                addr: parse_addr(arg(0).ok_or("Usage: mem <addr> [len]")?)?,
+                len: arg(1).map(parse_count).transpose()?.unwrap_or(64),
+            }),
+            "disasm" => Ok(Command::Disasm {
+                addr: arg(0).map(parse_addr).transpose()?,
+                count: arg(1).map(parse_count).transpose()?.unwrap_or(10),
+            }),
+            "key" => {
+                let key = arg(0).and_then(|k| u8::from_str_radix(k, 16).ok()).filter(|&k| k < 16);
+                let pressed = match arg(1) {
+                    Some("down") => Some(true),
+                    Some("up") => Some(false),
+                    _ => None,
+                };
+                match (key, pressed) {
+                    (Some(key), Some(pressed)) => Ok(Command::Key { key, pressed }),
+                    _ => Err("Usage: key <0-F> <down|up>".to_string()),
+                }
+            }
+            "info" | "i" => Ok(Command::Info),
+            "help" | "h" | "?" => Ok(Command::Help),
+            "quit" | "q" | "exit" => Ok(Command::Quit),
+            _ => Err(format!("Unknown command '{}'. Type 'help' for a list", name)),
+        }
+    }
+}
+
+/// Parses a symbol (checked first, so a label like `add` is not read as hex) or a hex address
+fn parse_addr(text: &str, symbols: &Symbols) -> Result<u16, String> {
+    if let Some(addr) = symbols.address_of(text) {
+        return Ok(addr);
+    }
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).map_err(|_| format!("Invalid address '{}'", text))
+}
+
+fn parse_value(text: &str) -> Result<u16, String> {
+    let result = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    result.map_err(|_| format!("Invalid value '{}'", text))
+}
+
+fn parse_count<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("Invalid count '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_with(rom: &[u8]) -> Machine {
+        let mut machine = Machine::default();
+        machine.load_rom(rom).unwrap();
+        machine
+    }
+
+    // 0x200: CALL 0x208 / 0x202: LD V1, 1 / 0x204: JP 0x204 / 0x206: (pad)
+    // 0x208: LD V0, 5 / 0x20A: LD I, 0x300 / 0x20C: LD [I], V0 / 0x20E: RET
+    const CALL_ROM: [u8; 16] = [
+        0x22, 0x08, 0x61, 0x01, 0x12, 0x04, 0x00, 0x00, 0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xEE,
+    ];
+
+    #[test]
+    fn test_breakpoint_stops_before_instruction() {
+        let mut machine = machine_with(&CALL_ROM);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x20A);
+        assert_eq!(debugger.cont(&mut machine, 10), BreakReason::Breakpoint { pc: 0x20A });
+        assert_eq!(machine.cpu().pc, 0x20A);
+        assert_eq!(machine.cpu().v[0], 5);
+
+        // Continuing from a breakpoint does not stop on it again
+        assert_eq!(debugger.cont(&mut machine, 2), BreakReason::FrameLimit);
+    }
+
+    #[test]
+    fn test_step_next_and_finish() {
+        let mut machine = machine_with(&CALL_ROM);
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.next(&mut machine, 10), BreakReason::Step);
+        assert_eq!(machine.cpu().pc, 0x202); // Ran the whole subroutine
+        assert_eq!(machine.cpu().v[0], 5);
+
+        let mut machine = machine_with(&CALL_ROM);
+        assert_eq!(debugger.finish(&mut machine, 10), None); // Not in a subroutine
+        assert_eq!(debugger.step(&mut machine, 2), BreakReason::Step);
+        assert_eq!(machine.cpu().pc, 0x20A);
+        assert_eq!(debugger.finish(&mut machine, 10), Some(BreakReason::Step));
+        assert_eq!(machine.cpu().pc, 0x202);
+    }
+
+    #[test]
+    fn test_watchpoint_reports_writer() {
+        let mut machine = machine_with(&CALL_ROM);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(0x300);
+        assert_eq!(debugger.cont(&mut machine, 10), BreakReason::Watchpoint { addr: 0x300, pc: 0x20C });
+        assert_eq!(machine.memory().read(0x300), 5);
+    }
+
+    #[test]
+    fn test_condition_and_opcode_class() {
+        let mut machine = machine_with(&CALL_ROM);
+        let mut debugger = Debugger::new();
+        let condition = RegisterCondition { register: Register::I, value: 0x300 };
+        debugger.add_condition(condition);
+        debugger.add_opcode_class("ret");
+
+        assert_eq!(debugger.cont(&mut machine, 10), BreakReason::Condition(condition));
+        assert_eq!(machine.cpu().pc, 0x20C);
+        assert_eq!(debugger.cont(&mut machine, 10), BreakReason::Opcode { pc: 0x20E, opcode: 0x00EE });
+    }
+
+    #[test]
+    fn test_halt_is_reported() {
+        let mut machine = machine_with(&[0x00, 0xEE]);
+        let reason = Debugger::new().cont(&mut machine, 10);
+        assert!(matches!(reason, BreakReason::Halted(CpuError::StackUnderflow { .. })));
+    }
+
+    // 0x200: RND V0, 0xFF / 0x202: ADD V1, 1 / 0x204: JP 0x200
+    const RANDOM_ROM: [u8; 6] = [0xC0, 0xFF, 0x71, 0x01, 0x12, 0x00];
+
+    #[test]
+    fn test_step_back_replays_random_bytes() {
+        let mut machine = machine_with(&RANDOM_ROM);
+        let mut debugger = Debugger::new();
+        let mut states = vec![machine.save_state()];
+        for _ in 0..30 {
+            debugger.step(&mut machine, 1);
+            states.push(machine.save_state());
+        }
+
+        assert_eq!(debugger.step_back(&mut machine, 10), BreakReason::Step);
+        assert_eq!(machine.save_state(), states[20]);
+        // Going forward again reproduces the same random bytes
+        debugger.step(&mut machine, 5);
+        assert_eq!(machine.save_state(), states[25]);
+
+        assert_eq!(debugger.step_back(&mut machine, 100), BreakReason::HistoryStart);
+        assert_eq!(machine.save_state(), states[0]);
+    }
+
+    #[test]
+    fn test_step_back_across_checkpoints() {
+        let mut machine = machine_with(&RANDOM_ROM);
+        let mut debugger = Debugger::new();
+        debugger.step(&mut machine, 2500);
+        let state = machine.save_state();
+        debugger.cont(&mut machine, 30);
+        assert!(debugger.history().checkpoints() > 2);
+
+        let back = (debugger.history().position() - 2500) as u32;
+        assert_eq!(debugger.step_back(&mut machine, back), BreakReason::Step);
+        assert_eq!(machine.save_state(), state);
+        assert_eq!(debugger.history().position(), 2500);
+    }
+
+    #[test]
+    fn test_reverse_continue_to_previous_breakpoint() {
+        let mut machine = machine_with(&RANDOM_ROM);
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x202);
+        let mut hits = Vec::new();
+        for _ in 0..3 {
+            assert_eq!(debugger.cont(&mut machine, 10), BreakReason::Breakpoint { pc: 0x202 });
+            hits.push(machine.save_state());
+        }
+
+        assert_eq!(debugger.reverse_cont(&mut machine), BreakReason::Breakpoint { pc: 0x202 });
+        assert_eq!(machine.save_state(), hits[1]);
+        assert_eq!(debugger.reverse_cont(&mut machine), BreakReason::Breakpoint { pc: 0x202 });
+        assert_eq!(machine.save_state(), hits[0]);
+        assert_eq!(debugger.reverse_cont(&mut machine), BreakReason::HistoryStart);
+        assert_eq!(machine.cpu().pc, 0x200);
+
+        // Edits made while stopped become part of the history
+        machine.keyboard_mut().set_key(5, true);
+        debugger.step(&mut machine, 3);
+        debugger.step_back(&mut machine, 1);
+        assert!(machine.keyboard().is_key_pressed(5));
+    }
+
+    #[test]
+    fn test_command_parse() {
+        assert_eq!(Command::parse("break 0x2A0"), Ok(Command::Break(0x2A0)));
+        assert_eq!(Command::parse("b 2a0"), Ok(Command::Break(0x2A0)));
+        assert_eq!(Command::parse("break op drw"), Ok(Command::BreakOpcode("DRW".to_string())));
+        assert_eq!(
+            Command::parse("break vA == 0x10"),
+            Ok(Command::BreakWhen(RegisterCondition { register: Register::V(0xA), value: 16 }))
+        );
+        assert_eq!(Command::parse("step"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("s 20"), Ok(Command::Step(20)));
+        assert_eq!(Command::parse("mem 300 16"), Ok(Command::Mem { addr: 0x300, len: 16 }));
+        assert_eq!(Command::parse("disasm"), Ok(Command::Disasm { addr: None, count: 10 }));
+        assert_eq!(Command::parse("key a down"), Ok(Command::Key { key: 0xA, pressed: true }));
+        assert_eq!(Command::parse("delete"), Ok(Command::Delete(None)));
+        assert_eq!(Command::parse("sb 3"), Ok(Command::StepBack(3)));
+        assert_eq!(Command::parse("reverse-continue"), Ok(Command::ReverseContinue));
+        assert!(Command::parse("break vG == 1").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn test_command_parse_with_symbols() {
+        let symbols = Symbols::parse("0x2A2 draw_paddle\n0x300 add\n").unwrap();
+        let parse = |line| Command::parse_with_symbols(line, &symbols);
+        assert_eq!(parse("break draw_paddle"), Ok(Command::Break(0x2A2)));
+        assert_eq!(parse("b draw_paddle+4"), Ok(Command::Break(0x2A6)));
+        assert_eq!(parse("watch add"), Ok(Command::Watch(0x300)));
+        assert_eq!(parse("b 0xadd"), Ok(Command::Break(0xADD)));
+        assert_eq!(parse("mem draw_paddle 2"), Ok(Command::Mem { addr: 0x2A2, len: 2 }));
+        assert!(parse("break missing").is_err());
+
+        let reason = BreakReason::Breakpoint { pc: 0x2A6 };
+        assert_eq!(reason.describe(&symbols), "Breakpoint at 0x2a6 <draw_paddle+4>");
+        assert_eq!(reason.to_string(), "Breakpoint at 0x2a6");
+    }
+}
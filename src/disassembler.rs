@@ -0,0 +1,478 @@
+//! Disassembler module for CHIP-8
+//!
+//! Turns opcodes into assembly text. `disassemble` handles a single opcode
+//! (used by the debugger and trace); `Disassembly` handles a whole ROM by
+//! tracing control flow from the entry point, so only bytes that can execute
+//! are listed as instructions and everything else is listed as data. The
+//! listing is valid `assembler` input and reassembles to the same bytes.
+
+use crate::instruction::Instruction;
+use crate::symbols::Symbols;
+use crate::variant::Variant;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Most entries followed in a `Bnnn` jump table (V0 can select 128 two-byte entries)
+const MAX_TABLE_ENTRIES: u16 = 128;
+
+/// Disassembles a single CHIP-8 opcode into assembly text
+/// Every instruction of every variant is recognized; anything else prints as UNKNOWN.
+pub fn disassemble(opcode: u16) -> String {
+    match Instruction::decode(opcode, Variant::XoChip) {
+        Ok(instruction) => instruction.to_string(),
+        Err(_) => format!("UNKNOWN 0x{:04X}", opcode),
+    }
+}
+
+/// What a ROM byte was found to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteKind {
+    /// Never reached by execution
+    Data,
+    /// First byte of an instruction
+    Code,
+    /// Later byte of an instruction
+    Operand,
+}
+
+/// Why an address gets a label; earlier kinds take precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Start,
+    Subroutine,
+    Table,
+    Jump,
+    Data,
+}
+
+/// A ROM split into code and data by following every path execution can take
+pub struct Disassembly {
+    load_address: u16,
+    rom: Vec<u8>,
+    kinds: Vec<ByteKind>,
+    labels: BTreeMap<u16, LabelKind>,
+    /// Names from a symbol file, used in place of the generated ones
+    names: BTreeMap<u16, String>,
+}
+
+impl Disassembly {
+    /// Traces `rom` loaded at `load_address`, starting from its first byte
+    /// Follows jumps, calls, both outcomes of skips and `Bnnn` tables of jumps.
+    pub fn trace(rom: &[u8], load_address: u16) -> Self {
+        let mut disassembly = Disassembly {
+            load_address,
+            rom: rom.to_vec(),
+            kinds: vec![ByteKind::Data; rom.len()],
+            labels: BTreeMap::new(),
+            names: BTreeMap::new(),
+        };
+        disassembly.add_label(load_address, LabelKind::Start);
+
+        let mut pending = vec![load_address];
+        while let Some(addr) = pending.pop() {
+            let Some(instruction) = disassembly.decode_at(addr) else {
+                continue;
+            };
+            if disassembly.kind(addr) == Some(ByteKind::Code) {
+                continue;
+            }
+            let size = instruction.size();
+            let index = disassembly.index(addr).expect("decode_at checked the address");
+            disassembly.kinds[index] = ByteKind::Code;
+            // Where paths disagree about alignment, a byte that starts an instruction stays Code
+            for operand in &mut disassembly.kinds[index + 1..index + size as usize] {
+                if *operand == ByteKind::Data {
+                    *operand = ByteKind::Operand;
+                }
+            }
+
+            let next = addr.wrapping_add(size);
+            match instruction {
+                Instruction::Jump(target) => {
+                    disassembly.add_label(target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Instruction::Call(target) => {
+                    disassembly.add_label(target, LabelKind::Subroutine);
+                    pending.extend([next, target]);
+                }
+                Instruction::JumpOffset(base) => {
+                    disassembly.add_label(base, LabelKind::Table);
+                    pending.extend(disassembly.table_entries(base));
+                }
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::SkipEqByte { .. }
+                | Instruction::SkipNeByte { .. }
+                | Instruction::SkipEqReg { .. }
+                | Instruction::SkipNeReg { .. }
+                | Instruction::SkipKey { .. }
+                | Instruction::SkipNotKey { .. } => {
+                    // A skipped F000 nnnn is skipped whole, as the CPU does
+                    let skipped = disassembly.decode_at(next).map_or(2, |following| following.size());
+                    pending.extend([next, next.wrapping_add(skipped)]);
+                }
+                Instruction::LoadIndex(target) => {
+                    disassembly.add_label(target, LabelKind::Data);
+                    pending.push(next);
+                }
+                Instruction::LoadIndexLong => {
+                    if let Some(target) = disassembly.long_address(addr) {
+                        disassembly.add_label(target, LabelKind::Data);
+                    }
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        // A label inside a listed instruction (code reached at two alignments) could
+        // not be defined in the listing, so operands fall back to the plain address
+        let starts: BTreeSet<u16> = disassembly.lines().into_iter().map(|(addr, _)| addr).collect();
+        disassembly.labels.retain(|addr, _| starts.contains(addr));
+        disassembly
+    }
+
+    /// Address of the first ROM byte
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
+
+    /// How the byte at `addr` is used, or None outside the ROM
+    pub fn kind(&self, addr: u16) -> Option<ByteKind> {
+        self.index(addr).map(|index| self.kinds[index])
+    }
+
+    /// The instruction starting at `addr`, if execution reaches it
+    pub fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        match self.kind(addr) {
+            Some(ByteKind::Code) => self.decode_at(addr),
+            _ => None,
+        }
+    }
+
+    /// Every instruction execution can reach, in address order
+    /// Includes instructions that overlap others (code reached at two alignments).
+    pub fn instructions(&self) -> Vec<(u16, Instruction)> {
+        (0..self.rom.len())
+            .map(|index| self.load_address.wrapping_add(index as u16))
+            .filter_map(|addr| self.instruction_at(addr).map(|instruction| (addr, instruction)))
+            .collect()
+    }
+
+    /// Names listing lines after the symbols at their addresses
+    /// Symbols inside a listed instruction, or whose name another label already
+    /// uses, are skipped so the listing still reassembles.
+    pub fn apply_symbols(&mut self, symbols: &Symbols) {
+        self.names.clear();
+        let lines: Vec<u16> = self.lines().into_iter().map(|(addr, _)| addr).collect();
+        let mut used: BTreeSet<String> = lines
+            .iter()
+            .filter(|&&addr| symbols.name_at(addr).is_none())
+            .filter_map(|&addr| self.label(addr))
+            .collect();
+        for addr in lines {
+            if let Some(name) = symbols.name_at(addr) {
+                if used.insert(name.to_string()) {
+                    self.names.insert(addr, name.to_string());
+                }
+            }
+        }
+    }
+
+    /// Name of the label at `addr`, if any
+    pub fn label(&self, addr: u16) -> Option<String> {
+        if let Some(name) = self.names.get(&addr) {
+            return Some(name.clone());
+        }
+        let kind = self.labels.get(&addr)?;
+        Some(match kind {
+            LabelKind::Start => "start".to_string(),
+            LabelKind::Subroutine => format!("sub_{:04X}", addr),
+            LabelKind::Table => format!("table_{:04X}", addr),
+            LabelKind::Jump => format!("label_{:04X}", addr),
+            LabelKind::Data => format!("data_{:04X}", addr),
+        })
+    }
+
+    /// Number of bytes that are part of instructions
+    pub fn code_bytes(&self) -> usize {
+        self.kinds.iter().filter(|&&kind| kind != ByteKind::Data).count()
+    }
+
+    /// Number of bytes never reached by execution
+    pub fn data_bytes(&self) -> usize {
+        self.rom.len() - self.code_bytes()
+    }
+
+    /// Formats an instruction, naming its address operand by label where one exists
+    pub fn instruction_text(&self, addr: u16, instruction: Instruction) -> String {
+        let named = |target: u16| self.label(target).unwrap_or_else(|| format!("0x{:03X}", target));
+        match instruction {
+            Instruction::Jump(target) => format!("JP {}", named(target)),
+            Instruction::Call(target) => format!("CALL {}", named(target)),
+            Instruction::LoadIndex(target) => format!("LD I, {}", named(target)),
+            Instruction::JumpOffset(target) => format!("JP V0, {}", named(target)),
+            Instruction::LoadIndexLong => match self.long_address(addr) {
+                Some(target) => format!("LD I, LONG {}", self.label(target).unwrap_or_else(|| format!("0x{:04X}", target))),
+                None => instruction.to_string(),
+            },
+            _ => instruction.to_string(),
+        }
+    }
+
+    /// Start address of each listing line, with its instruction (None for a data byte)
+    fn lines(&self) -> Vec<(u16, Option<Instruction>)> {
+        let mut lines = Vec::new();
+        let mut index = 0;
+        while index < self.rom.len() {
+            let addr = self.load_address.wrapping_add(index as u16);
+            let instruction = self.instruction_at(addr);
+            lines.push((addr, instruction));
+            index += instruction.map_or(1, |instruction| instruction.size() as usize);
+        }
+        lines
+    }
+
+    /// Maps an address to a ROM index
+    fn index(&self, addr: u16) -> Option<usize> {
+        let index = (addr as usize).checked_sub(self.load_address as usize)?;
+        (index < self.rom.len()).then_some(index)
+    }
+
+    /// Decodes the instruction at `addr` if all of its bytes are in the ROM
+    /// 0nnn (SYS) ends a path: this emulator, like most, cannot run machine code.
+    fn decode_at(&self, addr: u16) -> Option<Instruction> {
+        let index = self.index(addr)?;
+        let bytes = self.rom.get(index..index + 2)?;
+        let instruction = Instruction::decode(u16::from_be_bytes([bytes[0], bytes[1]]), Variant::XoChip).ok()?;
+        if matches!(instruction, Instruction::Sys(_)) || index + instruction.size() as usize > self.rom.len() {
+            return None;
+        }
+        Some(instruction)
+    }
+
+    /// The address word following F000 at `addr`
+    fn long_address(&self, addr: u16) -> Option<u16> {
+        let index = self.index(addr)?;
+        let bytes = self.rom.get(index + 2..index + 4)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Entry points of a `Bnnn` table: consecutive JP instructions from `base`,
+    /// or just `base` itself if it does not hold a jump
+    fn table_entries(&self, base: u16) -> Vec<u16> {
+        let mut entries = vec![base];
+        for entry in 1..MAX_TABLE_ENTRIES {
+            let addr = base.wrapping_add(entry * 2);
+            match (self.decode_at(base), self.decode_at(addr)) {
+                (Some(Instruction::Jump(_)), Some(Instruction::Jump(_))) => entries.push(addr),
+                _ => break,
+            }
+        }
+        entries
+    }
+
+    /// Labels `addr` if it is inside the ROM; other addresses stay numeric
+    fn add_label(&mut self, addr: u16, kind: LabelKind) {
+        if self.index(addr).is_none() {
+            return;
+        }
+        let entry = self.labels.entry(addr).or_insert(kind);
+        *entry = (*entry).min(kind);
+    }
+}
+
+/// Binary art for a sprite row, e.g. `##..#...` for 0xC8
+pub fn sprite_row(byte: u8) -> String {
+    (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect()
+}
+
+impl fmt::Display for Disassembly {
+    /// Assembler source: one line per instruction or data byte, with label lines
+    /// before labelled addresses and the address, bytes or sprite row as a comment
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, instruction) in self.lines() {
+            let index = self.index(addr).expect("lines are inside the ROM");
+            if let Some(label) = self.label(addr) {
+                writeln!(f, "{}:", label)?;
+            }
+            match instruction {
+                Some(instruction) => {
+                    let size = instruction.size() as usize;
+                    let bytes: String = self.rom[index..index + size].iter().map(|byte| format!("{:02X}", byte)).collect();
+                    let text = self.instruction_text(addr, instruction);
+                    writeln!(f, "    {:<24}; 0x{:04X}  {}", text, addr, bytes)?;
+                }
+                None => {
+                    let byte = self.rom[index];
+                    let text = format!(":byte 0x{:02X}", byte);
+                    writeln!(f, "    {:<24}; 0x{:04X}  {}", text, addr, sprite_row(byte))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_trace_separates_code_from_sprite_data() {
+        // 0x200: LD I, 0x206 / 0x202: DRW V0, V0, 2 / 0x204: JP 0x204 / 0x206: sprite
+        let rom = [0xA2, 0x06, 0xD0, 0x02, 0x12, 0x04, 0xF0, 0x90];
+        let disassembly = Disassembly::trace(&rom, 0x200);
+        assert_eq!(disassembly.kind(0x204), Some(ByteKind::Code));
+        assert_eq!(disassembly.kind(0x205), Some(ByteKind::Operand));
+        assert_eq!(disassembly.kind(0x206), Some(ByteKind::Data));
+        assert_eq!(disassembly.kind(0x208), None);
+        assert_eq!((disassembly.code_bytes(), disassembly.data_bytes()), (6, 2));
+
+        let listing = disassembly.to_string();
+        assert!(listing.contains("    LD I, data_0206         ; 0x0200  A206\n"));
+        assert!(listing.contains("label_0204:\n    JP label_0204           ; 0x0204  1204\n"));
+        assert!(listing.contains("data_0206:\n    :byte 0xF0              ; 0x0206  ####....\n    :byte 0x90"));
+    }
+
+    #[test]
+    fn test_trace_follows_skips_calls_and_odd_jumps() {
+        // 0x200: SE V0, 0 / 0x202: CALL 0x209 / 0x204: JP 0x204 / 0x206: byte 0xFF
+        // 0x207: (unreached) / 0x209: RET, reached through the call at an odd address
+        let rom = [0x30, 0x00, 0x22, 0x09, 0x12, 0x04, 0xFF, 0xFF, 0xFF, 0x00, 0xEE];
+        let disassembly = Disassembly::trace(&rom, 0x200);
+        assert_eq!(disassembly.instruction_at(0x202), Some(Instruction::Call(0x209)));
+        assert_eq!(disassembly.instruction_at(0x209), Some(Instruction::Return));
+        assert_eq!(disassembly.kind(0x206), Some(ByteKind::Data));
+        assert_eq!(disassembly.label(0x209).as_deref(), Some("sub_0209"));
+        assert_eq!(disassembly.label(0x200).as_deref(), Some("start"));
+    }
+
+    #[test]
+    fn test_trace_follows_jump_tables() {
+        // 0x200: JP V0, 0x202 / 0x202: JP 0x206 / 0x204: JP 0x208 / 0x206: EXIT / 0x208: EXIT
+        let rom = [0xB2, 0x02, 0x12, 0x06, 0x12, 0x08, 0x00, 0xFD, 0x00, 0xFD];
+        let disassembly = Disassembly::trace(&rom, 0x200);
+        assert_eq!(disassembly.data_bytes(), 0);
+        assert_eq!(disassembly.label(0x202).as_deref(), Some("table_0202"));
+        assert_eq!(disassembly.instruction_text(0x200, Instruction::JumpOffset(0x202)), "JP V0, table_0202");
+    }
+
+    #[test]
+    fn test_listing_reassembles_every_opcode() {
+        // Each opcode alone, and after a LD I, LONG whose operand it becomes
+        for opcode in 0..=0xFFFFu16 {
+            let [hi, lo] = opcode.to_be_bytes();
+            for rom in [vec![hi, lo], vec![0xF0, 0x00, hi, lo]] {
+                let listing = Disassembly::trace(&rom, 0x200).to_string();
+                let assembly = assemble(&listing, 0x200).unwrap_or_else(|e| panic!("{}\n{}", e, listing));
+                assert_eq!(assembly.rom, rom, "{}", listing);
+            }
+        }
+    }
+
+    #[test]
+    fn test_symbols_name_labels() {
+        // 0x200: CALL 0x206 / 0x202: JP 0x202 / 0x204: (data) / 0x206: RET
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x00, 0xEE];
+        let mut disassembly = Disassembly::trace(&rom, 0x200);
+        let symbols = Symbols::parse("200 main\n201 inside\n204 spare\n206 label_0202\n").unwrap();
+        disassembly.apply_symbols(&symbols);
+        assert_eq!(disassembly.label(0x200).as_deref(), Some("main"));
+        assert_eq!(disassembly.label(0x204).as_deref(), Some("spare"));
+        // Clashes with the generated label for 0x202
+        assert_eq!(disassembly.label(0x206).as_deref(), Some("sub_0206"));
+
+        let listing = disassembly.to_string();
+        assert!(listing.contains("    CALL sub_0206"), "{}", listing);
+        assert!(!listing.contains("inside"), "{}", listing);
+        assert_eq!(assemble(&listing, 0x200).unwrap().rom, rom);
+    }
+
+    #[test]
+    fn test_sprite_row() {
+        assert_eq!(sprite_row(0xC8), "##..#...");
+        assert_eq!(sprite_row(0x00), "........");
+    }
+
+    #[test]
+    fn test_disassemble_cls() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+    }
+
+    #[test]
+    fn test_disassemble_ret() {
+        assert_eq!(disassemble(0x00EE), "RET");
+    }
+
+    #[test]
+    fn test_disassemble_jp() {
+        assert_eq!(disassemble(0x1234), "JP 0x234");
+    }
+
+    #[test]
+    fn test_disassemble_call() {
+        assert_eq!(disassemble(0x2456), "CALL 0x456");
+    }
+
+    #[test]
+    fn test_disassemble_ld_vx_byte() {
+        assert_eq!(disassemble(0x6A42), "LD VA, 0x42");
+    }
+
+    #[test]
+    fn test_disassemble_add_vx_byte() {
+        assert_eq!(disassemble(0x7505), "ADD V5, 0x05");
+    }
+
+    #[test]
+    fn test_disassemble_ld_vx_vy() {
+        assert_eq!(disassemble(0x8AB0), "LD VA, VB");
+    }
+
+    #[test]
+    fn test_disassemble_shifts_name_vy() {
+        assert_eq!(disassemble(0x8126), "SHR V1, V2");
+        assert_eq!(disassemble(0x812E), "SHL V1, V2");
+        assert_eq!(disassemble(0x8128), "UNKNOWN 0x8128");
+    }
+
+    #[test]
+    fn test_disassemble_drw() {
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+    }
+
+    #[test]
+    fn test_disassemble_ld_i() {
+        assert_eq!(disassemble(0xA123), "LD I, 0x123");
+    }
+
+    #[test]
+    fn test_disassemble_ld_dt() {
+        assert_eq!(disassemble(0xF507), "LD V5, DT");
+    }
+
+    #[test]
+    fn test_disassemble_ld_k() {
+        assert_eq!(disassemble(0xF30A), "LD V3, K");
+    }
+
+    #[test]
+    fn test_disassemble_schip() {
+        assert_eq!(disassemble(0x00C4), "SCD 4");
+        assert_eq!(disassemble(0x00FF), "HIGH");
+        assert_eq!(disassemble(0xF230), "LD HF, V2");
+        assert_eq!(disassemble(0xF585), "LD V5, R");
+    }
+
+    #[test]
+    fn test_disassemble_xo_chip() {
+        assert_eq!(disassemble(0x5132), "SAVE V1, V3");
+        assert_eq!(disassemble(0x5133), "LOAD V1, V3");
+        assert_eq!(disassemble(0xF201), "PLANE 2");
+        assert_eq!(disassemble(0x00D4), "SCU 4");
+        assert_eq!(disassemble(0xF002), "AUDIO");
+        assert_eq!(disassemble(0xF43A), "PITCH V4");
+    }
+}
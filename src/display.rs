@@ -1,72 +1,197 @@
 //! Display module for CHIP-8
 //!
 //! CHIP-8 has a 64x32 pixel monochrome display.
+//! Super-CHIP adds a 128x64 high-resolution mode and scrolling.
+//! XO-CHIP adds a second bitplane, giving four colours.
 //! Sprites are XORed onto the screen.
 
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+/// Low-resolution (standard CHIP-8) width
 pub const DISPLAY_WIDTH: usize = 64;
+/// Low-resolution (standard CHIP-8) height
 pub const DISPLAY_HEIGHT: usize = 32;
+/// Super-CHIP high-resolution width
+pub const HIRES_WIDTH: usize = 128;
+/// Super-CHIP high-resolution height
+pub const HIRES_HEIGHT: usize = 64;
+/// Number of XO-CHIP bitplanes
+pub const PLANE_COUNT: usize = 2;
+
+/// Default colours for pixel values 0-3 (off, plane 1, plane 2, both planes)
+pub const DEFAULT_PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
 
-/// The 64x32 monochrome display
+/// The 64x32 (or 128x64 in high-res mode) display with up to two bitplanes
 pub struct Display {
-    /// Pixel buffer: true = white/on, false = black/off
-    pixels: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    /// Pixel buffer: bit 0 = plane 1, bit 1 = plane 2 (0 = off)
+    /// Sized for high-res; only the top-left 64x32 is used in low-res mode
+    pixels: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+    /// Super-CHIP high-resolution mode (128x64)
+    hires: bool,
+    /// XO-CHIP plane mask (Fn01) used by drawing, clearing and scrolling
+    selected_planes: u8,
+    /// Colours for pixel values 0-3
+    palette: [u32; 4],
 }
 
 impl Display {
     /// Creates a new display with all pixels off
     pub fn new() -> Self {
-        Display { pixels: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT] }
+        Display {
+            pixels: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+            hires: false,
+            selected_planes: 0b01,
+            palette: DEFAULT_PALETTE,
+        }
+    }
+
+    /// Current width in pixels (64 or 128)
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    /// Current height in pixels (32 or 64)
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    /// Returns true if the display is in Super-CHIP high-res mode
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64 mode (00FE/00FF).
+    /// The screen (all planes) is cleared on every switch, as modern interpreters do.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+    }
+
+    /// Selects which bitplanes subsequent draws, clears and scrolls affect (Fn01).
+    /// Only the low two bits are used; 0 disables drawing entirely.
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & 0b11;
+    }
+
+    /// Returns the current plane mask
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
     }
 
-    /// Clears the display (all pixels off)
+    /// Replaces the colours used for pixel values 0-3
+    pub fn set_palette(&mut self, palette: [u32; 4]) {
+        self.palette = palette;
+    }
+
+    /// Clears the selected planes (all pixels off for monochrome programs)
     pub fn clear(&mut self) {
-        self.pixels = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        let keep = !self.selected_planes;
+        for row in self.pixels.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= keep;
+            }
+        }
     }
 
-    /// Gets the state of a pixel at (x, y)
+    /// Gets the state of a pixel at (x, y): true if lit on any plane
     pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y][x] != 0
+    }
+
+    /// Gets the colour index (0-3) of a pixel at (x, y)
+    pub fn get_pixel_value(&self, x: usize, y: usize) -> u8 {
         self.pixels[y][x]
     }
 
-    /// Sets the state of a pixel at (x, y)
+    /// Sets the state of a pixel at (x, y) on the selected planes
     pub fn set_pixel(&mut self, x: usize, y: usize, value: bool) {
-        self.pixels[y][x] = value;
+        if value {
+            self.pixels[y][x] |= self.selected_planes;
+        } else {
+            self.pixels[y][x] &= !self.selected_planes;
+        }
     }
 
     /// Draws a sprite at (x, y) with the given sprite data.
     /// Returns true if any pixel was erased (collision).
     /// Sprites are XORed onto the display.
     /// COSMAC VIP quirk: coordinates wrap, but sprites clip at edges (no wrap during draw)
-    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+    /// With `wrap` set (the `wrap_sprites` quirk), pixels past an edge wrap to the opposite side
+    /// XO-CHIP: with both planes selected, the first half of the data is drawn
+    /// to plane 1 and the second half to plane 2.
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8], wrap: bool) -> bool {
+        let rows: Vec<u16> = sprite.iter().map(|&byte| (byte as u16) << 8).collect();
+        self.draw_planes(x, y, &rows, 8, wrap)
+    }
+
+    /// Draws a Super-CHIP 16x16 sprite (Dxy0).
+    /// The sprite data is 32 bytes per selected plane: two bytes (left half, right half) per row.
+    pub fn draw_sprite_16(&mut self, x: u8, y: u8, sprite: &[u8], wrap: bool) -> bool {
+        let rows: Vec<u16> = sprite
+            .chunks(2)
+            .map(|pair| ((pair[0] as u16) << 8) | *pair.get(1).unwrap_or(&0) as u16)
+            .collect();
+        self.draw_planes(x, y, &rows, 16, wrap)
+    }
+
+    /// Splits the sprite rows evenly between the selected planes and draws each part
+    fn draw_planes(&mut self, x: u8, y: u8, rows: &[u16], sprite_width: usize, wrap: bool) -> bool {
+        let planes: Vec<u8> = (0..PLANE_COUNT)
+            .map(|plane| 1u8 << plane)
+            .filter(|bit| self.selected_planes & bit != 0)
+            .collect();
+        if planes.is_empty() {
+            return false;
+        }
+
+        let rows_per_plane = rows.len() / planes.len();
         let mut collision = false;
+        for (index, &plane_bit) in planes.iter().enumerate() {
+            let part = &rows[index * rows_per_plane..(index + 1) * rows_per_plane];
+            collision |= self.draw_rows(x, y, part, sprite_width, plane_bit, wrap);
+        }
+        collision
+    }
+
+    /// XORs rows of up to 16 pixels (MSB = leftmost) onto one plane
+    fn draw_rows(&mut self, x: u8, y: u8, rows: &[u16], sprite_width: usize, plane_bit: u8, wrap: bool) -> bool {
+        let mut collision = false;
+        let width = self.width();
+        let height = self.height();
         
         // Wrap initial coordinates
-        let x_start = (x as usize) % DISPLAY_WIDTH;
-        let y_start = (y as usize) % DISPLAY_HEIGHT;
+        let x_start = (x as usize) % width;
+        let y_start = (y as usize) % height;
         
-        for (row, &sprite_byte) in sprite.iter().enumerate() {
-            let y_pos = y_start + row;
+        for (row, &sprite_row) in rows.iter().enumerate() {
+            let mut y_pos = y_start + row;
             
             // Clip at bottom edge (COSMAC VIP quirk)
-            if y_pos >= DISPLAY_HEIGHT {
-                break;  // Stop drawing if we hit the bottom edge
+            if y_pos >= height {
+                if !wrap {
+                    break;  // Stop drawing if we hit the bottom edge
+                }
+                y_pos %= height;
             }
             
-            for col in 0..8 {  // 8 bits per byte
-                let x_pos = x_start + col;
+            for col in 0..sprite_width {
+                let mut x_pos = x_start + col;
                 
                 // Clip at right edge (COSMAC VIP quirk)
-                if x_pos >= DISPLAY_WIDTH {
-                    break;  // Stop drawing this row if we hit the right edge
+                if x_pos >= width {
+                    if !wrap {
+                        break;  // Stop drawing this row if we hit the right edge
+                    }
+                    x_pos %= width;
                 }
                 
-                let sprite_pixel = (sprite_byte >> (7 - col)) & 1 == 1;
+                let sprite_pixel = (sprite_row >> (15 - col)) & 1 == 1;
                 
                 if sprite_pixel {
-                    if self.pixels[y_pos][x_pos] {
+                    if self.pixels[y_pos][x_pos] & plane_bit != 0 {
                         collision = true;  // Pixel was on, will turn off
                     }
-                    self.pixels[y_pos][x_pos] ^= true;  // XOR
+                    self.pixels[y_pos][x_pos] ^= plane_bit;  // XOR
                 }
             }
         }
@@ -74,19 +199,86 @@ impl Display {
         collision
     }
 
+    /// Scrolls the selected planes down by n pixels (00Cn)
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(0, n as isize);
+    }
+
+    /// Scrolls the selected planes up by n pixels (XO-CHIP 00Dn)
+    pub fn scroll_up(&mut self, n: usize) {
+        self.scroll(0, -(n as isize));
+    }
+
+    /// Scrolls the selected planes right by n pixels (00FB scrolls by 4)
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(n as isize, 0);
+    }
+
+    /// Scrolls the selected planes left by n pixels (00FC scrolls by 4)
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(-(n as isize), 0);
+    }
+
+    /// Moves the selected planes by (dx, dy); pixels shifted in from the edge are off
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mask = self.selected_planes;
+        let source = self.pixels;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let shifted = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    source[src_y as usize][src_x as usize] & mask
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[y as usize][x as usize];
+                *pixel = (*pixel & !mask) | shifted;
+            }
+        }
+    }
+
     /// Converts the display to a buffer suitable for minifb
-    /// Returns a Vec<u32> where each pixel is either white (0xFFFFFF) or black (0x000000)
+    /// Returns a Vec<u32> of width() * height() pixels coloured through the palette
+    /// (white on black for monochrome programs)
     pub fn to_buffer(&self) -> Vec<u32> {
-        self.pixels.iter().flat_map(|row| {
-            row.iter().map(|&pixel| {
-                if pixel {
-                    0xFFFFFF
-                } else {
-                    0x000000
-                }
-            })
+        self.pixels.iter().take(self.height()).flat_map(|row| {
+            row.iter().take(self.width()).map(|&pixel| self.palette[pixel as usize])
         }).collect()
     }
+
+    /// Writes the mode, planes, palette and the full 128x64 pixel buffer
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        writer.u8(self.selected_planes);
+        for &colour in self.palette.iter() {
+            writer.u32(colour);
+        }
+        for row in self.pixels.iter() {
+            writer.bytes(row);
+        }
+    }
+
+    /// Reads a display written by `save_state`
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        let hires = reader.bool()?;
+        let selected_planes = reader.u8()?;
+        if selected_planes > 0b11 {
+            return Err(SaveStateError::Corrupt("plane mask"));
+        }
+        let mut palette = [0; 4];
+        for colour in palette.iter_mut() {
+            *colour = reader.u32()?;
+        }
+        let mut pixels = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        for row in pixels.iter_mut() {
+            *row = reader.array()?;
+            if row.iter().any(|&pixel| pixel > 0b11) {
+                return Err(SaveStateError::Corrupt("pixel"));
+            }
+        }
+        Ok(Display { pixels, hires, selected_planes, palette })
+    }
 }
 
 impl Default for Display {
@@ -96,6 +288,7 @@ impl Default for Display {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -141,7 +334,7 @@ mod tests {
         let mut display = Display::new();
         // Simple 1-byte sprite: 0b11110000 = ████░░░░
         let sprite = [0b11110000];
-        let collision = display.draw_sprite(0, 0, &sprite);
+        let collision = display.draw_sprite(0, 0, &sprite, false);
         
         // Check pixels are set
         assert_eq!(display.get_pixel(0, 0), true);
@@ -160,7 +353,7 @@ mod tests {
         
         // Draw sprite that overlaps
         let sprite = [0b11110000];
-        let collision = display.draw_sprite(0, 0, &sprite);
+        let collision = display.draw_sprite(0, 0, &sprite, false);
         
         // Pixel at (2,0) should be OFF now (XOR: true ^ true = false)
         assert_eq!(display.get_pixel(2, 0), false);
@@ -173,11 +366,11 @@ mod tests {
         let sprite = [0b10000000]; // Single pixel
         
         // Draw once - pixel turns ON
-        display.draw_sprite(5, 5, &sprite);
+        display.draw_sprite(5, 5, &sprite, false);
         assert_eq!(display.get_pixel(5, 5), true);
         
         // Draw again - pixel turns OFF (XOR)
-        let collision = display.draw_sprite(5, 5, &sprite);
+        let collision = display.draw_sprite(5, 5, &sprite, false);
         assert_eq!(display.get_pixel(5, 5), false);
         assert_eq!(collision, true);
     }
@@ -188,7 +381,7 @@ mod tests {
         let sprite = [0b11111111]; // 8 pixels
         
         // Draw at edge - COSMAC VIP CLIPPING quirk: clips instead of wrapping
-        display.draw_sprite(62, 0, &sprite);
+        display.draw_sprite(62, 0, &sprite, false);
         
         // Pixels at edge (only 2 pixels fit)
         assert_eq!(display.get_pixel(62, 0), true);
@@ -198,6 +391,24 @@ mod tests {
         assert_eq!(display.get_pixel(1, 0), false);
     }
 
+    #[test]
+    fn test_draw_sprite_wrap_quirk() {
+        let mut display = Display::new();
+        let sprite = [0b11111111, 0b10000000];
+
+        // Draw at bottom-right corner with wrapping instead of clipping
+        display.draw_sprite(62, 31, &sprite, true);
+
+        assert!(display.get_pixel(62, 31));
+        assert!(display.get_pixel(63, 31));
+        // Wrapped to the left edge
+        assert!(display.get_pixel(0, 31));
+        assert!(display.get_pixel(5, 31));
+        // Second row wrapped to the top
+        assert!(display.get_pixel(62, 0));
+        assert!(!display.get_pixel(63, 0));
+    }
+
     #[test]
     fn test_draw_sprite_multi_row() {
         let mut display = Display::new();
@@ -206,7 +417,7 @@ mod tests {
             0b11110000, // Row 0
             0b00001111, // Row 1
         ];
-        display.draw_sprite(0, 0, &sprite);
+        display.draw_sprite(0, 0, &sprite, false);
         
         // Check row 0
         assert_eq!(display.get_pixel(0, 0), true);
@@ -239,4 +450,123 @@ mod tests {
         // Second pixel should be black
         assert_eq!(buffer[1], 0x000000);
     }
+
+    #[test]
+    fn test_hires_mode_switch() {
+        let mut display = Display::new();
+        assert!(!display.is_hires());
+        assert_eq!((display.width(), display.height()), (64, 32));
+
+        display.set_pixel(1, 1, true);
+        display.set_hires(true);
+        assert!(display.is_hires());
+        assert_eq!((display.width(), display.height()), (128, 64));
+        assert!(!display.get_pixel(1, 1)); // Mode switch clears the screen
+        assert_eq!(display.to_buffer().len(), 128 * 64);
+    }
+
+    #[test]
+    fn test_hires_draw_past_lores_edge() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        display.draw_sprite(100, 50, &[0b10000000], false);
+        assert!(display.get_pixel(100, 50));
+    }
+
+    #[test]
+    fn test_draw_sprite_16() {
+        let mut display = Display::new();
+        let mut sprite = [0u8; 32];
+        sprite[0] = 0xFF;  // Row 0, left half
+        sprite[1] = 0x01;  // Row 0, right half (only last pixel)
+        sprite[31] = 0x01; // Row 15, right half
+        let collision = display.draw_sprite_16(0, 0, &sprite, false);
+
+        assert!(!collision);
+        assert!(display.get_pixel(0, 0));
+        assert!(display.get_pixel(7, 0));
+        assert!(!display.get_pixel(8, 0));
+        assert!(display.get_pixel(15, 0));
+        assert!(display.get_pixel(15, 15));
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut display = Display::new();
+        display.set_pixel(5, 0, true);
+        display.set_pixel(5, 31, true);
+        display.scroll_down(2);
+        assert!(!display.get_pixel(5, 0));
+        assert!(display.get_pixel(5, 2));
+        // Bottom row scrolled off the screen
+        assert!(!display.get_pixel(5, 31));
+    }
+
+    #[test]
+    fn test_scroll_right_and_left() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        display.set_pixel(0, 3, true);
+        display.set_pixel(127, 3, true);
+
+        display.scroll_right(4);
+        assert!(!display.get_pixel(0, 3));
+        assert!(display.get_pixel(4, 3));
+        assert!(!display.get_pixel(127, 3)); // Scrolled off the right edge
+
+        display.scroll_left(4);
+        assert!(display.get_pixel(0, 3));
+        assert!(!display.get_pixel(4, 3));
+    }
+
+    #[test]
+    fn test_two_plane_draw() {
+        let mut display = Display::new();
+        display.select_planes(0b11);
+        // One row for plane 1, one row for plane 2
+        display.draw_sprite(0, 0, &[0b11000000, 0b10100000], false);
+
+        assert_eq!(display.get_pixel_value(0, 0), 3); // Both planes
+        assert_eq!(display.get_pixel_value(1, 0), 1); // Plane 1 only
+        assert_eq!(display.get_pixel_value(2, 0), 2); // Plane 2 only
+        assert_eq!(display.get_pixel_value(3, 0), 0);
+    }
+
+    #[test]
+    fn test_clear_only_selected_plane() {
+        let mut display = Display::new();
+        display.select_planes(0b11);
+        display.set_pixel(4, 4, true);
+        display.select_planes(0b10);
+        display.clear();
+        assert_eq!(display.get_pixel_value(4, 4), 1);
+    }
+
+    #[test]
+    fn test_plane_zero_draws_nothing() {
+        let mut display = Display::new();
+        display.select_planes(0);
+        assert!(!display.draw_sprite(0, 0, &[0xFF], false));
+        assert!(!display.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn test_palette_to_buffer() {
+        let mut display = Display::new();
+        display.set_palette([0x111111, 0x222222, 0x333333, 0x444444]);
+        display.select_planes(0b10);
+        display.set_pixel(1, 0, true);
+        let buffer = display.to_buffer();
+        assert_eq!(buffer[0], 0x111111);
+        assert_eq!(buffer[1], 0x333333);
+    }
+
+    #[test]
+    fn test_scroll_up() {
+        let mut display = Display::new();
+        display.set_pixel(2, 5, true);
+        display.scroll_up(5);
+        assert!(display.get_pixel(2, 0));
+        assert!(!display.get_pixel(2, 5));
+    }
 }
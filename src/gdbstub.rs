@@ -0,0 +1,426 @@
+//! GDB stub module for CHIP-8
+//!
+//! Serves the GDB Remote Serial Protocol over TCP so gdb, lldb or any RSP
+//! frontend can debug a running `Machine`. Execution goes through a
+//! `Debugger`, so breakpoints and stepping (forwards and in reverse) behave
+//! exactly as in the REPL.
+//!
+//! Registers, in `g` packet order: V0-VF (8 bit), I (16 bit), PC (16 bit),
+//! SP, DT, ST (8 bit). Multi-byte values are little-endian. The address
+//! space is the machine's RAM.
+
+use crate::cpu::CpuError;
+use crate::debugger::{BreakReason, Debugger};
+use crate::machine::Machine;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// Number of registers exposed to GDB
+pub const REGISTER_COUNT: usize = 21;
+
+/// Target description sent for `qXfer:features:read:target.xml`
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// A byte from the client: either a complete packet or an out-of-band interrupt (Ctrl-C)
+#[derive(Debug, PartialEq, Eq)]
+enum Incoming {
+    Packet(String),
+    /// A packet whose checksum did not match; the client resends it after a `-`
+    Corrupt,
+    Interrupt,
+}
+
+/// GDB Remote Serial Protocol server for one connection
+pub struct GdbStub {
+    debugger: Debugger,
+}
+
+impl GdbStub {
+    /// Creates a stub with no breakpoints set
+    pub fn new() -> Self {
+        GdbStub { debugger: Debugger::new() }
+    }
+
+    /// Serves one client until it detaches, kills the target or disconnects
+    pub fn serve(&mut self, machine: &mut Machine, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        loop {
+            let packet = match read_incoming(&mut stream)? {
+                Some(Incoming::Packet(packet)) => packet,
+                Some(Incoming::Corrupt) => {
+                    stream.write_all(b"-")?; // No-ack mode is never negotiated
+                    continue;
+                }
+                Some(Incoming::Interrupt) => continue, // Already stopped
+                None => return Ok(()),                 // Disconnected
+            };
+            stream.write_all(b"+")?;
+
+            let reply = match packet.chars().next() {
+                Some('c') => {
+                    let reason = self.debugger.cont_until(machine, || interrupt_pending(&stream));
+                    stop_reply(reason)
+                }
+                Some('s') => stop_reply(self.debugger.step(machine, 1)),
+                Some('b') if packet == "bs" => stop_reply(self.debugger.step_back(machine, 1)),
+                Some('b') if packet == "bc" => stop_reply(self.debugger.reverse_cont(machine)),
+                Some('k') => return Ok(()),
+                Some('D') => {
+                    send_packet(&mut stream, "OK")?;
+                    return Ok(());
+                }
+                _ => self.handle(machine, &packet),
+            };
+            send_packet(&mut stream, &reply)?;
+        }
+    }
+
+    /// Answers every packet that does not resume execution
+    /// Unsupported packets get the empty reply, as the protocol requires.
+    fn handle(&mut self, machine: &mut Machine, packet: &str) -> String {
+        let (command, args) = packet.split_at(1.min(packet.len()));
+        match command {
+            "?" => "S05".to_string(),
+            "g" => read_registers(machine),
+            "G" => match decode_hex(args) {
+                Some(bytes) if bytes.len() == register_bytes() => {
+                    write_registers(machine, &bytes);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTER_COUNT => encode_hex(&register_value(machine, n)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, value)| {
+                    Some((usize::from_str_radix(n, 16).ok()?, decode_hex(value)?))
+                });
+                match parsed {
+                    Some((n, bytes)) if n < REGISTER_COUNT && bytes.len() == register_size(n) => {
+                        set_register(machine, n, &bytes);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_addr_len(args) {
+                Some((addr, len)) => {
+                    let bytes: Vec<u8> = (0..len).map(|i| machine.memory().read(addr.wrapping_add(i))).collect();
+                    encode_hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_addr_len(range)?, decode_hex(data)?)));
+                match parsed {
+                    Some(((addr, len), bytes)) if bytes.len() == len as usize => {
+                        for (i, &byte) in bytes.iter().enumerate() {
+                            machine.memory_mut().write(addr.wrapping_add(i as u16), byte);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "H" => "OK".to_string(),
+            "q" => query(args),
+            _ => String::new(),
+        }
+    }
+
+    /// Inserts or removes a software breakpoint (type 0) or write watchpoint (type 2)
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let addr = fields.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+        let len = fields.next().and_then(|l| u16::from_str_radix(l, 16).ok()).unwrap_or(1);
+        let Some(addr) = addr else {
+            return "E01".to_string();
+        };
+        match (kind, insert) {
+            (Some("0"), true) => self.debugger.add_breakpoint(addr),
+            (Some("2"), true) => (0..len).for_each(|i| self.debugger.add_watchpoint(addr.wrapping_add(i))),
+            (Some("0"), false) => {
+                self.debugger.remove_breakpoint(addr);
+            }
+            (Some("2"), false) => (0..len).for_each(|i| {
+                self.debugger.remove_watchpoint(addr.wrapping_add(i));
+            }),
+            _ => return String::new(), // Hardware breakpoints and read watchpoints are not supported
+        }
+        "OK".to_string()
+    }
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts why execution stopped into a stop-reply packet
+fn stop_reply(reason: BreakReason) -> String {
+    match reason {
+        BreakReason::Exited => "W00".to_string(),
+        BreakReason::Watchpoint { addr, .. } => format!("T05watch:{:x};", addr),
+        BreakReason::HistoryStart => "T05replaylog:begin;".to_string(),
+        BreakReason::Interrupted => "S02".to_string(), // SIGINT
+        BreakReason::Halted(CpuError::UnknownOpcode { .. }) => "S04".to_string(), // SIGILL
+        BreakReason::Halted(_) => "S0b".to_string(), // SIGSEGV
+        _ => "S05".to_string(), // SIGTRAP
+    }
+}
+
+/// Replies to `q` queries
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return "PacketSize=4000;qXfer:features:read+;ReverseStep+;ReverseContinue+".to_string();
+    }
+    if args == "Attached" {
+        return "1".to_string();
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some((offset, len)) = range.split_once(',') else {
+            return "E01".to_string();
+        };
+        let (Ok(offset), Ok(len)) = (usize::from_str_radix(offset, 16), usize::from_str_radix(len, 16)) else {
+            return "E01".to_string();
+        };
+        let xml = TARGET_XML.as_bytes();
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(len).min(xml.len());
+        let marker = if end == xml.len() { 'l' } else { 'm' };
+        return format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]));
+    }
+    String::new()
+}
+
+/// Size in bytes of register `n`
+fn register_size(n: usize) -> usize {
+    match n {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn register_bytes() -> usize {
+    (0..REGISTER_COUNT).map(register_size).sum()
+}
+
+fn register_value(machine: &Machine, n: usize) -> Vec<u8> {
+    let cpu = machine.cpu();
+    match n {
+        0..=15 => vec![cpu.v[n]],
+        16 => cpu.i.to_le_bytes().to_vec(),
+        17 => cpu.pc.to_le_bytes().to_vec(),
+        18 => vec![cpu.sp],
+        19 => vec![cpu.delay_timer],
+        _ => vec![cpu.sound_timer],
+    }
+}
+
+fn set_register(machine: &mut Machine, n: usize, bytes: &[u8]) {
+    let cpu = machine.cpu_mut();
+    let wide = || u16::from_le_bytes([bytes[0], bytes[1]]);
+    match n {
+        0..=15 => cpu.v[n] = bytes[0],
+        16 => cpu.i = wide(),
+        17 => cpu.pc = wide(),
+        18 => cpu.sp = bytes[0].min(16),
+        19 => cpu.delay_timer = bytes[0],
+        _ => cpu.sound_timer = bytes[0],
+    }
+}
+
+fn read_registers(machine: &Machine) -> String {
+    let bytes: Vec<u8> = (0..REGISTER_COUNT).flat_map(|n| register_value(machine, n)).collect();
+    encode_hex(&bytes)
+}
+
+fn write_registers(machine: &mut Machine, bytes: &[u8]) {
+    let mut offset = 0;
+    for n in 0..REGISTER_COUNT {
+        let size = register_size(n);
+        set_register(machine, n, &bytes[offset..offset + size]);
+        offset += size;
+    }
+}
+
+fn parse_addr_len(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Modulo-256 sum of the packet data, sent as two hex digits after `#`
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Sends `$data#checksum`
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    write!(stream, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+    stream.flush()
+}
+
+/// Reads the next packet or interrupt, skipping acknowledgements
+/// Returns None when the client disconnects.
+fn read_incoming(stream: &mut impl Read) -> io::Result<Option<Incoming>> {
+    let mut byte = [0u8; 1];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            0x03 => return Ok(Some(Incoming::Interrupt)),
+            b'$' => break,
+            _ => {} // '+', '-' and noise between packets
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    let mut digits = [0u8; 2];
+    stream.read_exact(&mut digits)?;
+    let sent = std::str::from_utf8(&digits).ok().and_then(|digits| u8::from_str_radix(digits, 16).ok());
+    if sent != Some(checksum(&data)) {
+        return Ok(Some(Incoming::Corrupt));
+    }
+    Ok(Some(Incoming::Packet(String::from_utf8_lossy(&data).into_owned())))
+}
+
+/// Returns true if the client sent Ctrl-C (0x03) while the target was running
+fn interrupt_pending(stream: &TcpStream) -> bool {
+    let mut byte = [0u8; 1];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let interrupted = matches!(stream.peek(&mut byte), Ok(1) if byte[0] == 0x03);
+    if interrupted {
+        let _ = (&*stream).read(&mut byte);
+    }
+    let _ = stream.set_nonblocking(false);
+    interrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(encode_hex(&[0x00, 0xAB, 0x12]), "00ab12");
+        assert_eq!(decode_hex("00ab12"), Some(vec![0x00, 0xAB, 0x12]));
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+
+    #[test]
+    fn test_read_incoming_checks_the_checksum() {
+        let mut input: &[u8] = b"+$m300,2#5e$m300,2#00\x03";
+        assert_eq!(read_incoming(&mut input).unwrap(), Some(Incoming::Packet("m300,2".to_string())));
+        assert_eq!(read_incoming(&mut input).unwrap(), Some(Incoming::Corrupt));
+        assert_eq!(read_incoming(&mut input).unwrap(), Some(Incoming::Interrupt));
+        assert_eq!(read_incoming(&mut input).unwrap(), None);
+
+        let mut input: &[u8] = b"$g#zz";
+        assert_eq!(read_incoming(&mut input).unwrap(), Some(Incoming::Corrupt));
+    }
+
+    #[test]
+    fn test_register_packets() {
+        let mut machine = Machine::default();
+        let mut stub = GdbStub::new();
+        machine.cpu_mut().v[0xF] = 0x7F;
+        machine.cpu_mut().i = 0x0345;
+
+        let registers = stub.handle(&mut machine, "g");
+        assert_eq!(registers.len(), register_bytes() * 2);
+        assert_eq!(&registers[30..32], "7f"); // VF
+        assert_eq!(&registers[32..40], "45030002"); // I, PC (little-endian)
+
+        assert_eq!(stub.handle(&mut machine, "p11"), "0002");
+        assert_eq!(stub.handle(&mut machine, "P11=0403"), "OK");
+        assert_eq!(machine.cpu().pc, 0x304);
+        assert_eq!(stub.handle(&mut machine, "P11=04"), "E01");
+    }
+
+    #[test]
+    fn test_memory_and_breakpoint_packets() {
+        let mut machine = Machine::default();
+        let mut stub = GdbStub::new();
+        assert_eq!(stub.handle(&mut machine, "M300,2:beef"), "OK");
+        assert_eq!(stub.handle(&mut machine, "m300,3"), "beef00");
+        assert_eq!(stub.handle(&mut machine, "Z0,20a,2"), "OK");
+        assert_eq!(stub.debugger.breakpoints().collect::<Vec<_>>(), vec![0x20A]);
+        // A watchpoint at the same address is independent of the breakpoint
+        assert_eq!(stub.handle(&mut machine, "Z2,20a,1"), "OK");
+        assert_eq!(stub.handle(&mut machine, "z2,20a,1"), "OK");
+        assert_eq!(stub.debugger.breakpoints().collect::<Vec<_>>(), vec![0x20A]);
+        assert_eq!(stub.handle(&mut machine, "Z2,20a,1"), "OK");
+        assert_eq!(stub.handle(&mut machine, "z0,20a,2"), "OK");
+        assert_eq!(stub.debugger.breakpoints().count(), 0);
+        assert_eq!(stub.debugger.watchpoints().collect::<Vec<_>>(), vec![0x20A]);
+        assert_eq!(stub.handle(&mut machine, "Z1,20a,2"), ""); // Hardware breakpoints unsupported
+    }
+
+    #[test]
+    fn test_target_xml_query() {
+        let reply = query("Xfer:features:read:target.xml:0,20");
+        assert!(reply.starts_with("m<?xml"));
+        assert!(query("Xfer:features:read:target.xml:0,ffff").starts_with('l'));
+    }
+}
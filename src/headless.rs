@@ -0,0 +1,283 @@
+//! Headless module for CHIP-8
+//!
+//! Runs a `Machine` without a window or audio device and turns the result
+//! into plain files: the framebuffer as ASCII art or PNG and the registers
+//! as JSON. Runs either free-running or driven by a recorded movie. Used by
+//! the `chip8-headless` binary for CI and batch runs.
+
+use crate::cpu::{Cpu, CpuError, StepOutcome};
+use crate::display::Display;
+use crate::machine::{CycleHook, Machine};
+use crate::movie::Movie;
+
+/// Why a headless run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested number of frames ran
+    FrameLimit,
+    /// The program jumped to itself (`JP` to its own address), the usual end-of-test idiom
+    Loop { pc: u16 },
+    /// The program executed 00FD (EXIT)
+    Exited,
+    /// The CPU faulted
+    Halted(CpuError),
+    /// Every frame of a replayed movie ran
+    MovieEnd,
+}
+
+impl StopReason {
+    /// Short lowercase name used in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            StopReason::FrameLimit => "frame-limit",
+            StopReason::Loop { .. } => "loop",
+            StopReason::Exited => "exited",
+            StopReason::Halted(_) => "halted",
+            StopReason::MovieEnd => "movie-end",
+        }
+    }
+}
+
+/// Runs up to `max_frames` frames, stopping early on a self-jump, EXIT or CPU error
+/// Returns the number of frames run and why the run stopped.
+pub fn run(machine: &mut Machine, max_frames: u64) -> (u64, StopReason) {
+    run_with(machine, max_frames, &mut ())
+}
+
+/// Like `run`, calling `hook` around every instruction (e.g. a `Tracer`)
+/// A hook that stops mid-frame is resumed, so only full frames are counted.
+pub fn run_with(machine: &mut Machine, max_frames: u64, hook: &mut impl CycleHook) -> (u64, StopReason) {
+    for frame in 1..=max_frames {
+        match run_whole_frame(machine, hook) {
+            Ok(StepOutcome::Exited) => return (frame, StopReason::Exited),
+            Ok(_) => {}
+            Err(error) => return (frame, StopReason::Halted(error)),
+        }
+        if let Some(pc) = self_jump(machine) {
+            return (frame, StopReason::Loop { pc });
+        }
+    }
+    (max_frames, StopReason::FrameLimit)
+}
+
+/// Replays every frame of a movie on a machine built with `Movie::machine`
+/// Unlike `run`, a halt or self-jump does not end the replay early: the
+/// recording may reset the machine later. The stop reason describes the last frame.
+pub fn replay(machine: &mut Machine, movie: &Movie) -> (u64, StopReason) {
+    replay_with(machine, movie, &mut ())
+}
+
+/// Like `replay`, calling `hook` around every instruction
+pub fn replay_with(machine: &mut Machine, movie: &Movie, hook: &mut impl CycleHook) -> (u64, StopReason) {
+    let mut result = Ok(StepOutcome::Continue);
+    for frame in &movie.frames {
+        frame.apply(machine);
+        result = run_whole_frame(machine, hook);
+    }
+    let reason = match result {
+        Ok(StepOutcome::Exited) => StopReason::Exited,
+        Ok(_) => StopReason::MovieEnd,
+        Err(error) => StopReason::Halted(error),
+    };
+    (movie.frames.len() as u64, reason)
+}
+
+/// Runs one frame to its end, resuming whenever the hook stops mid-frame
+fn run_whole_frame(machine: &mut Machine, hook: &mut impl CycleHook) -> Result<StepOutcome, CpuError> {
+    loop {
+        if let Some(outcome) = machine.run_frame_with(hook)? {
+            return Ok(outcome);
+        }
+    }
+}
+
+/// Returns the PC if the next instruction is `1nnn` with nnn equal to its own address
+/// Above 0xFFF no `1nnn` can reach its own address.
+fn self_jump(machine: &Machine) -> Option<u16> {
+    let pc = machine.cpu().pc;
+    let opcode = machine.memory().read_opcode(pc);
+    (pc <= 0xFFF && opcode == 0x1000 | pc).then_some(pc)
+}
+
+/// Renders the visible screen, one line per row
+/// Off pixels are '.', plane 1 is '#', plane 2 is '+' and both planes are '*'.
+pub fn framebuffer_ascii(display: &Display) -> String {
+    let mut out = String::with_capacity((display.width() + 1) * display.height());
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            out.push(match display.get_pixel_value(x, y) {
+                0 => '.',
+                1 => '#',
+                2 => '+',
+                _ => '*',
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Formats the CPU registers as a JSON object
+pub fn registers_json(cpu: &Cpu) -> String {
+    let join = |values: Vec<String>| values.join(", ");
+    format!(
+        "{{\"pc\": {}, \"i\": {}, \"sp\": {}, \"delay_timer\": {}, \"sound_timer\": {}, \"v\": [{}], \"stack\": [{}]}}",
+        cpu.pc,
+        cpu.i,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer,
+        join(cpu.v.iter().map(u8::to_string).collect()),
+        join(cpu.stack.iter().map(u16::to_string).collect()),
+    )
+}
+
+/// Encodes the visible screen as an RGB PNG, each CHIP-8 pixel scaled to `scale`x`scale`
+pub fn framebuffer_png(display: &Display, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let buffer = display.to_buffer();
+    let (width, height) = (display.width() * scale, display.height() * scale);
+
+    // Raw image data: each row starts with filter type 0 (None)
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for y in 0..height {
+        raw.push(0);
+        for x in 0..width {
+            let color = buffer[(y / scale) * display.width() + x / scale];
+            raw.extend_from_slice(&[(color >> 16) as u8, (color >> 8) as u8, color as u8]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, deflate, no filter, no interlace
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Appends a PNG chunk: length, type, data, CRC of type and data
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineConfig;
+    use crate::variant::Variant;
+
+    #[test]
+    fn test_run_detects_self_jump() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x01, 0x12, 0x02]).unwrap(); // LD V0, 1 / JP 0x202
+        assert_eq!(run(&mut machine, 100), (1, StopReason::Loop { pc: 0x202 }));
+    }
+
+    #[test]
+    fn test_run_ignores_jumps_above_0xfff() {
+        // XO-CHIP: JP 0x200 at 0x1200 is not a jump to itself
+        let mut machine = Machine::new(MachineConfig::for_variant(Variant::XoChip));
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1 / JP 0x200
+        machine.memory_mut().write(0x1200, 0x12);
+        machine.memory_mut().write(0x1201, 0x00);
+        machine.cpu_mut().pc = 0x1200;
+        assert_eq!(run(&mut machine, 3), (3, StopReason::FrameLimit));
+    }
+
+    #[test]
+    fn test_run_frame_limit_and_halt() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1 / JP 0x200
+        assert_eq!(run(&mut machine, 3), (3, StopReason::FrameLimit));
+
+        machine.load_rom(&[0x00, 0xEE]).unwrap(); // RET with empty stack
+        let (frames, reason) = run(&mut machine, 3);
+        assert_eq!(frames, 1);
+        assert!(matches!(reason, StopReason::Halted(CpuError::StackUnderflow { .. })));
+    }
+
+    #[test]
+    fn test_framebuffer_ascii() {
+        let mut display = Display::new();
+        display.set_pixel(1, 0, true);
+        let ascii = framebuffer_ascii(&display);
+        let first_line = ascii.lines().next().unwrap();
+        assert_eq!(first_line.len(), 64);
+        assert!(first_line.starts_with(".#.."));
+        assert_eq!(ascii.lines().count(), 32);
+    }
+
+    #[test]
+    fn test_registers_json() {
+        let mut cpu = Cpu::new();
+        cpu.v[0xF] = 1;
+        cpu.i = 0x300;
+        let json = registers_json(&cpu);
+        assert!(json.starts_with("{\"pc\": 512, \"i\": 768, "));
+        assert!(json.contains("\"v\": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]"));
+    }
+
+    #[test]
+    fn test_framebuffer_png_structure() {
+        let png = framebuffer_png(&Display::new(), 2);
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &128u32.to_be_bytes());
+        assert_eq!(&png[20..24], &64u32.to_be_bytes());
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn test_crc32_known_value() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
@@ -0,0 +1,162 @@
+//! History module for CHIP-8
+//!
+//! Records enough of a debugging session to rebuild the machine at any
+//! earlier instruction boundary: periodic snapshots (checkpoints) and the
+//! bytes produced by `Cxkk`, the one instruction whose result cannot be
+//! recomputed. Keypad presses and other edits made while stopped start a new
+//! checkpoint, so re-executing from a checkpoint with the recorded random
+//! bytes reproduces the original run exactly. Used by `Debugger` for
+//! `step-back` and `reverse-continue`.
+
+use crate::machine::{Machine, MachineConfig};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Instructions between periodic checkpoints (the most a reverse step re-executes)
+pub const CHECKPOINT_INTERVAL: u64 = 2000;
+/// Checkpoints kept before the oldest is dropped
+pub const MAX_CHECKPOINTS: usize = 256;
+
+/// A snapshot at an instruction boundary
+struct Checkpoint {
+    cycle: u64,
+    state: Vec<u8>,
+    frame_cycles: u32,
+    /// The debugger's skip PC at this point, so break checks replay identically
+    skip_pc: Option<u16>,
+}
+
+/// Recorded execution of one machine
+pub struct History {
+    checkpoints: VecDeque<Checkpoint>,
+    /// Value written by each `Cxkk`, keyed by the cycle that executed it
+    random: BTreeMap<u64, u8>,
+    /// Instructions executed on the recorded timeline up to the machine's current state
+    cycle: u64,
+    /// Config and ROM of the recorded machine; a different machine starts a new history
+    origin: Option<(MachineConfig, Vec<u8>)>,
+    /// Machine state when execution last stopped, to detect edits made while stopped
+    parked: Option<(Vec<u8>, u32)>,
+    /// Register the current instruction fills with a random byte
+    pending_random: Option<usize>,
+}
+
+impl History {
+    /// Creates an empty history
+    pub fn new() -> Self {
+        History {
+            checkpoints: VecDeque::new(),
+            random: BTreeMap::new(),
+            cycle: 0,
+            origin: None,
+            parked: None,
+            pending_random: None,
+        }
+    }
+
+    /// Instructions executed since recording started
+    pub fn position(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Earliest cycle that can still be reached, if anything was recorded
+    pub fn oldest(&self) -> Option<u64> {
+        self.checkpoints.front().map(|checkpoint| checkpoint.cycle)
+    }
+
+    /// Number of snapshots held
+    pub fn checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Forgets everything recorded
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+
+    /// Called when execution resumes at `skip_pc`
+    /// Starts a new checkpoint if the machine changed since it stopped; anything
+    /// recorded after this point then no longer applies and is dropped.
+    pub(crate) fn resume(&mut self, machine: &Machine, skip_pc: Option<u16>) {
+        let same_machine = matches!(&self.origin, Some((config, rom)) if config == machine.config() && rom == machine.rom());
+        if !same_machine {
+            self.clear();
+            self.origin = Some((*machine.config(), machine.rom().to_vec()));
+        }
+
+        let state = machine.save_state();
+        let frame_cycles = machine.frame_cycles();
+        if self.parked.as_ref().is_some_and(|(parked, frame)| *parked == state && *frame == frame_cycles) {
+            return;
+        }
+        self.checkpoints.retain(|checkpoint| checkpoint.cycle < self.cycle);
+        self.random.split_off(&self.cycle);
+        self.push(Checkpoint { cycle: self.cycle, state, frame_cycles, skip_pc });
+    }
+
+    /// Called when execution stops
+    pub(crate) fn park(&mut self, machine: &Machine) {
+        self.parked = Some((machine.save_state(), machine.frame_cycles()));
+    }
+
+    /// Called before every instruction
+    pub(crate) fn before_cycle(&mut self, machine: &Machine, skip_pc: Option<u16>) {
+        let due = self.checkpoints.back().is_some_and(|last| self.cycle >= last.cycle + CHECKPOINT_INTERVAL);
+        if due {
+            self.push(Checkpoint {
+                cycle: self.cycle,
+                state: machine.save_state(),
+                frame_cycles: machine.frame_cycles(),
+                skip_pc,
+            });
+        }
+
+        let cpu = machine.cpu();
+        let opcode = machine.memory().read_opcode(cpu.pc);
+        self.pending_random = (opcode & 0xF000 == 0xC000).then_some(((opcode >> 8) & 0xF) as usize);
+    }
+
+    /// Called after every instruction that executed
+    /// Records a `Cxkk` result, or replays the recorded one over the fresh random byte.
+    pub(crate) fn after_cycle(&mut self, machine: &mut Machine) {
+        if let Some(x) = self.pending_random.take() {
+            match self.random.get(&self.cycle) {
+                Some(&value) => machine.cpu_mut().v[x] = value,
+                None => {
+                    self.random.insert(self.cycle, machine.cpu().v[x]);
+                }
+            }
+        }
+        self.cycle += 1;
+    }
+
+    /// Loads the latest checkpoint at or before `target`
+    /// Returns its cycle and skip PC, or None if `target` is older than the history.
+    pub(crate) fn restore(&mut self, machine: &mut Machine, target: u64) -> Option<(u64, Option<u16>)> {
+        let checkpoint = self.checkpoints.iter().rev().find(|checkpoint| checkpoint.cycle <= target)?;
+        machine.load_state(&checkpoint.state).ok()?;
+        machine.set_frame_cycles(checkpoint.frame_cycles);
+        self.cycle = checkpoint.cycle;
+        Some((checkpoint.cycle, checkpoint.skip_pc))
+    }
+
+    /// Cycle of the latest checkpoint strictly before `cycle`
+    pub(crate) fn checkpoint_before(&self, cycle: u64) -> Option<u64> {
+        self.checkpoints.iter().rev().map(|checkpoint| checkpoint.cycle).find(|&start| start < cycle)
+    }
+
+    fn push(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.push_back(checkpoint);
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            self.checkpoints.pop_front();
+            if let Some(oldest) = self.oldest() {
+                self.random = self.random.split_off(&oldest);
+            }
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
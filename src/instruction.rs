@@ -0,0 +1,439 @@
+//! Instruction module for CHIP-8
+//!
+//! Decodes opcodes into typed `Instruction` values. The CPU executes these
+//! and the disassembler prints them through `Display`, so both always agree
+//! on what an opcode means.
+
+use crate::variant::Variant;
+use std::fmt;
+
+/// Why an opcode could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No CHIP-8 family member defines the opcode
+    Unknown(u16),
+    /// The opcode belongs to an extension the requested variant lacks
+    Unsupported { opcode: u16, required: Variant },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::Unknown(opcode) => write!(f, "Unknown opcode {:#06x}", opcode),
+            DecodeError::Unsupported { opcode, required } => {
+                write!(f, "Opcode {:#06x} requires {:?}", opcode, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A decoded instruction. `x` and `y` are register numbers (0x0-0xF).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00Cn - SCD n: Scroll down n pixels (Super-CHIP)
+    ScrollDown(u8),
+    /// 00Dn - SCU n: Scroll up n pixels (XO-CHIP)
+    ScrollUp(u8),
+    /// 00E0 - CLS
+    Clear,
+    /// 00EE - RET
+    Return,
+    /// 00FB - SCR: Scroll right 4 pixels (Super-CHIP)
+    ScrollRight,
+    /// 00FC - SCL: Scroll left 4 pixels (Super-CHIP)
+    ScrollLeft,
+    /// 00FD - EXIT (Super-CHIP)
+    Exit,
+    /// 00FE - LOW: 64x32 mode (Super-CHIP)
+    LowRes,
+    /// 00FF - HIGH: 128x64 mode (Super-CHIP)
+    HighRes,
+    /// 0nnn - SYS addr: Call a machine code routine on the original hardware
+    Sys(u16),
+    /// 1nnn - JP addr
+    Jump(u16),
+    /// 2nnn - CALL addr
+    Call(u16),
+    /// 3xkk - SE Vx, byte
+    SkipEqByte { x: u8, kk: u8 },
+    /// 4xkk - SNE Vx, byte
+    SkipNeByte { x: u8, kk: u8 },
+    /// 5xy0 - SE Vx, Vy
+    SkipEqReg { x: u8, y: u8 },
+    /// 5xy2 - SAVE Vx, Vy: Store Vx through Vy at I (XO-CHIP)
+    SaveRange { x: u8, y: u8 },
+    /// 5xy3 - LOAD Vx, Vy: Read Vx through Vy from I (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6xkk - LD Vx, byte
+    LoadByte { x: u8, kk: u8 },
+    /// 7xkk - ADD Vx, byte
+    AddByte { x: u8, kk: u8 },
+    /// 8xy0 - LD Vx, Vy
+    Move { x: u8, y: u8 },
+    /// 8xy1 - OR Vx, Vy
+    Or { x: u8, y: u8 },
+    /// 8xy2 - AND Vx, Vy
+    And { x: u8, y: u8 },
+    /// 8xy3 - XOR Vx, Vy
+    Xor { x: u8, y: u8 },
+    /// 8xy4 - ADD Vx, Vy
+    Add { x: u8, y: u8 },
+    /// 8xy5 - SUB Vx, Vy
+    Sub { x: u8, y: u8 },
+    /// 8xy6 - SHR Vx, Vy (Vy is ignored under the `shift_vx` quirk)
+    ShiftRight { x: u8, y: u8 },
+    /// 8xy7 - SUBN Vx, Vy
+    SubN { x: u8, y: u8 },
+    /// 8xyE - SHL Vx, Vy (Vy is ignored under the `shift_vx` quirk)
+    ShiftLeft { x: u8, y: u8 },
+    /// 9xy0 - SNE Vx, Vy
+    SkipNeReg { x: u8, y: u8 },
+    /// Annn - LD I, addr
+    LoadIndex(u16),
+    /// Bnnn - JP V0, addr (Bxnn jumps to xnn + Vx under the `jump_vx` quirk)
+    JumpOffset(u16),
+    /// Cxkk - RND Vx, byte
+    Random { x: u8, kk: u8 },
+    /// Dxyn - DRW Vx, Vy, n (n = 0 draws a 16x16 sprite)
+    Draw { x: u8, y: u8, n: u8 },
+    /// Ex9E - SKP Vx
+    SkipKey { x: u8 },
+    /// ExA1 - SKNP Vx
+    SkipNotKey { x: u8 },
+    /// F000 nnnn - LD I, long: The address is the following word (XO-CHIP)
+    LoadIndexLong,
+    /// Fn01 - PLANE n: Select bitplanes (XO-CHIP)
+    Plane(u8),
+    /// F002 - AUDIO: Load the audio pattern from I (XO-CHIP)
+    Audio,
+    /// Fx07 - LD Vx, DT
+    LoadDelay { x: u8 },
+    /// Fx0A - LD Vx, K
+    WaitKey { x: u8 },
+    /// Fx15 - LD DT, Vx
+    SetDelay { x: u8 },
+    /// Fx18 - LD ST, Vx
+    SetSound { x: u8 },
+    /// Fx1E - ADD I, Vx
+    AddIndex { x: u8 },
+    /// Fx29 - LD F, Vx
+    LoadFont { x: u8 },
+    /// Fx30 - LD HF, Vx (Super-CHIP)
+    LoadBigFont { x: u8 },
+    /// Fx33 - LD B, Vx
+    StoreBcd { x: u8 },
+    /// Fx3A - PITCH Vx (XO-CHIP)
+    Pitch { x: u8 },
+    /// Fx55 - LD [I], Vx
+    StoreRegisters { x: u8 },
+    /// Fx65 - LD Vx, [I]
+    LoadRegisters { x: u8 },
+    /// Fx75 - LD R, Vx (Super-CHIP)
+    StoreFlags { x: u8 },
+    /// Fx85 - LD Vx, R (Super-CHIP)
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    /// Decodes `opcode`, accepting only instructions `variant` supports
+    pub fn decode(opcode: u16, variant: Variant) -> Result<Self, DecodeError> {
+        let instruction = Self::decode_any(opcode)?;
+        let required = instruction.required_variant();
+        if required > variant {
+            return Err(DecodeError::Unsupported { opcode, required });
+        }
+        Ok(instruction)
+    }
+
+    /// Decodes `opcode` as any CHIP-8 family member would
+    fn decode_any(opcode: u16) -> Result<Self, DecodeError> {
+        let nnn = opcode & 0x0FFF;
+        let n = (opcode & 0x000F) as u8;
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let kk = (opcode & 0x00FF) as u8;
+
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00C0..=0x00CF => Instruction::ScrollDown(n),
+                0x00D0..=0x00DF => Instruction::ScrollUp(n),
+                0x00E0 => Instruction::Clear,
+                0x00EE => Instruction::Return,
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowRes,
+                0x00FF => Instruction::HighRes,
+                _ => Instruction::Sys(nnn),
+            },
+            0x1000 => Instruction::Jump(nnn),
+            0x2000 => Instruction::Call(nnn),
+            0x3000 => Instruction::SkipEqByte { x, kk },
+            0x4000 => Instruction::SkipNeByte { x, kk },
+            0x5000 => match n {
+                0x0 => Instruction::SkipEqReg { x, y },
+                0x2 => Instruction::SaveRange { x, y },
+                0x3 => Instruction::LoadRange { x, y },
+                _ => return Err(DecodeError::Unknown(opcode)),
+            },
+            0x6000 => Instruction::LoadByte { x, kk },
+            0x7000 => Instruction::AddByte { x, kk },
+            0x8000 => match n {
+                0x0 => Instruction::Move { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::Add { x, y },
+                0x5 => Instruction::Sub { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubN { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _ => return Err(DecodeError::Unknown(opcode)),
+            },
+            0x9000 if n == 0 => Instruction::SkipNeReg { x, y },
+            0xA000 => Instruction::LoadIndex(nnn),
+            0xB000 => Instruction::JumpOffset(nnn),
+            0xC000 => Instruction::Random { x, kk },
+            0xD000 => Instruction::Draw { x, y, n },
+            0xE000 => match kk {
+                0x9E => Instruction::SkipKey { x },
+                0xA1 => Instruction::SkipNotKey { x },
+                _ => return Err(DecodeError::Unknown(opcode)),
+            },
+            0xF000 => match kk {
+                0x00 if x == 0 => Instruction::LoadIndexLong,
+                0x01 => Instruction::Plane(x),
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::LoadDelay { x },
+                0x0A => Instruction::WaitKey { x },
+                0x15 => Instruction::SetDelay { x },
+                0x18 => Instruction::SetSound { x },
+                0x1E => Instruction::AddIndex { x },
+                0x29 => Instruction::LoadFont { x },
+                0x30 => Instruction::LoadBigFont { x },
+                0x33 => Instruction::StoreBcd { x },
+                0x3A => Instruction::Pitch { x },
+                0x55 => Instruction::StoreRegisters { x },
+                0x65 => Instruction::LoadRegisters { x },
+                0x75 => Instruction::StoreFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _ => return Err(DecodeError::Unknown(opcode)),
+            },
+            _ => return Err(DecodeError::Unknown(opcode)),
+        };
+        Ok(instruction)
+    }
+
+    /// The earliest variant that defines this instruction
+    pub fn required_variant(&self) -> Variant {
+        match self {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::LoadBigFont { .. }
+            | Instruction::StoreFlags { .. }
+            | Instruction::LoadFlags { .. }
+            | Instruction::Draw { n: 0, .. } => Variant::SuperChip,
+            Instruction::ScrollUp(_)
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadIndexLong
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch { .. } => Variant::XoChip,
+            _ => Variant::Chip8,
+        }
+    }
+
+    /// The opcode pattern this instruction matches, as reference tables write it (e.g. `8xy6`)
+    /// A 16x16 sprite draw is `Dxy0`, since only Super-CHIP and later define it.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::ScrollDown(_) => "00Cn",
+            Instruction::ScrollUp(_) => "00Dn",
+            Instruction::Clear => "00E0",
+            Instruction::Return => "00EE",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::Exit => "00FD",
+            Instruction::LowRes => "00FE",
+            Instruction::HighRes => "00FF",
+            Instruction::Sys(_) => "0nnn",
+            Instruction::Jump(_) => "1nnn",
+            Instruction::Call(_) => "2nnn",
+            Instruction::SkipEqByte { .. } => "3xkk",
+            Instruction::SkipNeByte { .. } => "4xkk",
+            Instruction::SkipEqReg { .. } => "5xy0",
+            Instruction::SaveRange { .. } => "5xy2",
+            Instruction::LoadRange { .. } => "5xy3",
+            Instruction::LoadByte { .. } => "6xkk",
+            Instruction::AddByte { .. } => "7xkk",
+            Instruction::Move { .. } => "8xy0",
+            Instruction::Or { .. } => "8xy1",
+            Instruction::And { .. } => "8xy2",
+            Instruction::Xor { .. } => "8xy3",
+            Instruction::Add { .. } => "8xy4",
+            Instruction::Sub { .. } => "8xy5",
+            Instruction::ShiftRight { .. } => "8xy6",
+            Instruction::SubN { .. } => "8xy7",
+            Instruction::ShiftLeft { .. } => "8xyE",
+            Instruction::SkipNeReg { .. } => "9xy0",
+            Instruction::LoadIndex(_) => "Annn",
+            Instruction::JumpOffset(_) => "Bnnn",
+            Instruction::Random { .. } => "Cxkk",
+            Instruction::Draw { n: 0, .. } => "Dxy0",
+            Instruction::Draw { .. } => "Dxyn",
+            Instruction::SkipKey { .. } => "Ex9E",
+            Instruction::SkipNotKey { .. } => "ExA1",
+            Instruction::LoadIndexLong => "F000",
+            Instruction::Plane(_) => "Fn01",
+            Instruction::Audio => "F002",
+            Instruction::LoadDelay { .. } => "Fx07",
+            Instruction::WaitKey { .. } => "Fx0A",
+            Instruction::SetDelay { .. } => "Fx15",
+            Instruction::SetSound { .. } => "Fx18",
+            Instruction::AddIndex { .. } => "Fx1E",
+            Instruction::LoadFont { .. } => "Fx29",
+            Instruction::LoadBigFont { .. } => "Fx30",
+            Instruction::StoreBcd { .. } => "Fx33",
+            Instruction::Pitch { .. } => "Fx3A",
+            Instruction::StoreRegisters { .. } => "Fx55",
+            Instruction::LoadRegisters { .. } => "Fx65",
+            Instruction::StoreFlags { .. } => "Fx75",
+            Instruction::LoadFlags { .. } => "Fx85",
+        }
+    }
+
+    /// Size in bytes, including the address word that follows F000
+    pub fn size(&self) -> u16 {
+        if *self == Instruction::LoadIndexLong {
+            4
+        } else {
+            2
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqByte { x, kk } => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SkipNeByte { x, kk } => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadByte { x, kk } => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            Instruction::AddByte { x, kk } => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Instruction::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpOffset(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Random { x, kk } => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadIndexLong => write!(f, "LD I, LONG"),
+            Instruction::Plane(n) => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreRegisters { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegisters { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_fields() {
+        assert_eq!(Instruction::decode(0xD125, Variant::Chip8), Ok(Instruction::Draw { x: 1, y: 2, n: 5 }));
+        assert_eq!(Instruction::decode(0x8AB6, Variant::Chip8), Ok(Instruction::ShiftRight { x: 0xA, y: 0xB }));
+        assert_eq!(Instruction::decode(0xB234, Variant::Chip8), Ok(Instruction::JumpOffset(0x234)));
+        assert_eq!(Instruction::decode(0x0123, Variant::Chip8), Ok(Instruction::Sys(0x123)));
+    }
+
+    #[test]
+    fn test_decode_checks_variant() {
+        assert_eq!(
+            Instruction::decode(0x00FF, Variant::Chip8),
+            Err(DecodeError::Unsupported { opcode: 0x00FF, required: Variant::SuperChip })
+        );
+        assert_eq!(Instruction::decode(0x00FF, Variant::SuperChip), Ok(Instruction::HighRes));
+        assert_eq!(
+            Instruction::decode(0xF000, Variant::SuperChip),
+            Err(DecodeError::Unsupported { opcode: 0xF000, required: Variant::XoChip })
+        );
+        assert_eq!(Instruction::decode(0xF000, Variant::XoChip), Ok(Instruction::LoadIndexLong));
+        assert_eq!(Instruction::LoadIndexLong.size(), 4);
+        // Dxy0 draws nothing on the COSMAC VIP; only Super-CHIP gives it a 16x16 sprite
+        assert_eq!(
+            Instruction::decode(0xD120, Variant::Chip8),
+            Err(DecodeError::Unsupported { opcode: 0xD120, required: Variant::SuperChip })
+        );
+        assert_eq!(Instruction::decode(0xD121, Variant::Chip8), Ok(Instruction::Draw { x: 1, y: 2, n: 1 }));
+    }
+
+    #[test]
+    fn test_decode_unknown() {
+        for opcode in [0x5121, 0x8128, 0x9121, 0xE1FF, 0xF1FF, 0xF100] {
+            assert_eq!(Instruction::decode(opcode, Variant::XoChip), Err(DecodeError::Unknown(opcode)));
+        }
+    }
+
+    #[test]
+    fn test_pattern_matches_opcode() {
+        for opcode in 0..=0xFFFFu16 {
+            let Ok(instruction) = Instruction::decode(opcode, Variant::XoChip) else {
+                continue;
+            };
+            let hex = format!("{:04X}", opcode);
+            let pattern = instruction.pattern();
+            // Upper-case hex digits are fixed nibbles; lower-case letters are operands
+            let matches = pattern.chars().zip(hex.chars()).all(|(p, h)| p.is_lowercase() || p == h);
+            assert!(matches, "{} does not match {}", pattern, hex);
+        }
+    }
+
+    #[test]
+    fn test_display_shows_both_shift_registers() {
+        assert_eq!(Instruction::ShiftRight { x: 1, y: 2 }.to_string(), "SHR V1, V2");
+        assert_eq!(Instruction::ShiftLeft { x: 0xA, y: 0xF }.to_string(), "SHL VA, VF");
+    }
+}
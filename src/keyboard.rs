@@ -2,6 +2,8 @@
 //!
 //! CHIP-8 uses a 16-key hexadecimal keypad (0-F).
 
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
 /// The 16-key CHIP-8 keyboard
 pub struct Keyboard {
     /// State of each key: true = pressed, false = released
@@ -33,6 +35,30 @@ impl Keyboard {
         }
         None
     }
+
+    /// The pressed keys as a 16-bit mask (bit n = key n)
+    pub fn mask(&self) -> u16 {
+        self.keys.iter().enumerate().fold(0u16, |mask, (i, &pressed)| mask | ((pressed as u16) << i))
+    }
+
+    /// Sets every key from a 16-bit mask (bit n = key n)
+    pub fn set_mask(&mut self, mask: u16) {
+        for (i, key) in self.keys.iter_mut().enumerate() {
+            *key = mask & (1 << i) != 0;
+        }
+    }
+
+    /// Writes the pressed keys as a 16-bit mask
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.u16(self.mask());
+    }
+
+    /// Reads a keyboard written by `save_state`
+    pub(crate) fn load_state(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut keyboard = Keyboard::new();
+        keyboard.set_mask(reader.u16()?);
+        Ok(keyboard)
+    }
 }
 
 impl Default for Keyboard {
@@ -42,6 +68,7 @@ impl Default for Keyboard {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
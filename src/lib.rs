@@ -1,14 +1,55 @@
 //! CHIP-8 Emulator Library
 //!
 //! This crate provides the core components for a CHIP-8 emulator:
+//! - Analyzer (static scan for the variant and quirks a ROM needs)
+//! - Assembler (mnemonic and Octo source to ROM)
 //! - CPU (fetch, decode, execute)
-//! - Memory (4KB RAM)
-//! - Display (64x32 pixels)
+//! - Debugger (breakpoints, watchpoints, stepping)
+//! - History (recorded execution for reverse stepping)
+//! - Disassembler (opcode to assembly text)
+//! - Instruction (typed opcode decoding shared by the CPU and disassembler)
+//! - Memory (4KB RAM, 64KB for XO-CHIP)
+//! - Display (64x32 pixels, 128x64 high-res, two XO-CHIP bitplanes)
+//! - GDB stub (remote serial protocol server)
+//! - JSON (reader for symbol maps and the ROM database)
 //! - Keyboard (16 keys)
-//! - Sound (beep tone)
+//! - Sound (beep tone, requires the `frontend` feature)
+//! - Machine (owns all components and runs frames)
+//! - Movie (recorded input for deterministic replay)
+//! - Headless (run without a window, dump the result)
+//! - Quirks (interpreter-specific behavior profiles)
+//! - Rewind (ring buffer of compressed per-frame snapshots)
+//! - ROM database (title, author and settings by SHA-1)
+//! - RNG (seedable random sources for Cxkk)
+//! - Save states (versioned binary snapshots of the machine)
+//! - SHA-1 (ROM identification)
+//! - Symbols (label names for addresses, from symbol files)
+//! - Trace (per-instruction execution log)
+//! - Variant (CHIP-8, Super-CHIP, XO-CHIP)
 
+pub mod analyzer;
+pub mod assembler;
 pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
 pub mod display;
+pub mod gdbstub;
+pub mod headless;
+pub mod history;
+pub mod instruction;
+pub mod json;
 pub mod keyboard;
+pub mod machine;
 pub mod memory;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod romdb;
+pub mod savestate;
+pub mod sha1;
+#[cfg(feature = "frontend")]
 pub mod sound;
+pub mod symbols;
+pub mod trace;
+pub mod variant;
@@ -0,0 +1,495 @@
+//! Machine module for CHIP-8
+//!
+//! Owns the CPU, memory, display and keyboard and runs them together, so
+//! every frontend (window, tests, headless runners) shares the same reset
+//! logic and 60 Hz frame sequencing.
+
+use crate::cpu::{Cpu, CpuError, StepOutcome};
+use crate::display::{Display, DEFAULT_PALETTE};
+use crate::keyboard::Keyboard;
+use crate::memory::{AddressPolicy, Memory, RomError, PROGRAM_START};
+use crate::quirks::Quirks;
+use crate::rng::RngKind;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::variant::Variant;
+
+/// Instructions per frame - high value for quirks test (DRW breaks early anyway)
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 200;
+
+/// Everything needed to build a machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineConfig {
+    /// Instruction set family (decides memory size)
+    pub variant: Variant,
+    /// Interpreter behavior switches
+    pub quirks: Quirks,
+    /// How out-of-range memory accesses are handled
+    pub address_policy: AddressPolicy,
+    /// Where the ROM is loaded and execution starts
+    pub load_address: u16,
+    /// Maximum instructions executed per 60 Hz frame
+    pub cycles_per_frame: u32,
+    /// Generator used by Cxkk
+    pub rng: RngKind,
+    /// Seed for the generator; None seeds it from OS entropy on every reset
+    pub seed: Option<u64>,
+    /// Colours for pixel values 0-3
+    pub palette: [u32; 4],
+}
+
+impl MachineConfig {
+    /// Default configuration for a variant, using its usual quirk profile
+    pub fn for_variant(variant: Variant) -> Self {
+        MachineConfig {
+            variant,
+            quirks: variant.default_quirks(),
+            address_policy: AddressPolicy::default(),
+            load_address: PROGRAM_START,
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            rng: RngKind::default(),
+            seed: None,
+            palette: DEFAULT_PALETTE,
+        }
+    }
+
+    /// Configuration for a quirk preset name, with the variant it implies (see `quirks::PRESET_NAMES`)
+    pub fn for_profile(name: &str) -> Option<Self> {
+        let quirks = Quirks::from_name(name)?;
+        let variant = Variant::from_profile_name(name).unwrap_or_default();
+        Some(MachineConfig { quirks, ..Self::for_variant(variant) })
+    }
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self::for_variant(Variant::Chip8)
+    }
+}
+
+/// Observes and steers execution one instruction at a time (debuggers, tracers)
+pub trait CycleHook {
+    /// Called before each instruction; returning false stops before it executes
+    fn before_cycle(&mut self, _machine: &mut Machine) -> bool {
+        true
+    }
+
+    /// Called after each instruction that executed without error; returning false stops after it
+    fn after_cycle(&mut self, _machine: &mut Machine) -> bool {
+        true
+    }
+}
+
+/// The no-op hook used by `run_frame`
+impl CycleHook for () {}
+
+/// A complete CHIP-8 system
+pub struct Machine {
+    config: MachineConfig,
+    /// The loaded ROM, kept so `reset` can reload it
+    rom: Vec<u8>,
+    cpu: Cpu,
+    memory: Memory,
+    display: Display,
+    keyboard: Keyboard,
+    /// The error that stopped the CPU, if any; cleared by `reset`
+    halted: Option<CpuError>,
+    /// Instructions already run in the current frame (non-zero only when a hook stopped mid-frame)
+    frame_cycles: u32,
+}
+
+impl Machine {
+    /// Creates a powered-on machine with no ROM loaded
+    pub fn new(config: MachineConfig) -> Self {
+        let mut memory = Memory::for_variant(config.variant);
+        memory.set_policy(config.address_policy);
+        let mut cpu = Cpu::with_entry_point(config.quirks, config.load_address);
+        cpu.set_rng(config.rng.build(config.seed));
+        cpu.set_variant(config.variant);
+        let mut display = Display::new();
+        display.set_palette(config.palette);
+        Machine {
+            config,
+            rom: Vec::new(),
+            cpu,
+            memory,
+            display,
+            keyboard: Keyboard::new(),
+            halted: None,
+            frame_cycles: 0,
+        }
+    }
+
+    /// Resets the machine and loads a ROM at the configured load address
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomError> {
+        let mut memory = Memory::for_variant(self.config.variant);
+        memory.set_policy(self.config.address_policy);
+        memory.load_rom_at(data, self.config.load_address)?;
+
+        self.rom = data.to_vec();
+        self.reset();
+        Ok(())
+    }
+
+    /// Returns the machine to its power-on state with the current ROM reloaded
+    /// The decode cache setting is kept.
+    pub fn reset(&mut self) {
+        let config = self.config;
+        let rom = std::mem::take(&mut self.rom);
+        let decode_cache = self.cpu.decode_cache;
+        *self = Machine::new(config);
+        self.memory
+            .load_rom_at(&rom, config.load_address)
+            .expect("ROM was validated when it was loaded");
+        self.rom = rom;
+        self.cpu.set_decode_cache(decode_cache);
+    }
+
+    /// Executes a single instruction
+    /// Once the CPU has faulted, the same error is returned until `reset`.
+    pub fn step(&mut self) -> Result<StepOutcome, CpuError> {
+        if let Some(error) = self.halted {
+            return Err(error);
+        }
+        let result = self.cpu.cycle(&mut self.memory, &mut self.display, &self.keyboard);
+        if let Err(error) = result {
+            self.halted = Some(error);
+        }
+        result
+    }
+
+    /// Runs one 60 Hz frame:
+    /// - Run CPU cycles until DRW (DISP.WAIT), EXIT or cycles_per_frame reached
+    /// - Timer decrements ONCE per frame (AFTER CPU cycles)
+    ///
+    /// Returns the outcome that ended the frame (`Continue` if the cycle budget ran out).
+    /// Timers do not tick on a frame that faults.
+    pub fn run_frame(&mut self) -> Result<StepOutcome, CpuError> {
+        self.run_frame_with(&mut ())
+            .map(|outcome| outcome.unwrap_or(StepOutcome::Continue))
+    }
+
+    /// Runs the rest of the current frame, calling `hook` around every instruction
+    /// Returns `Ok(None)` if the hook stopped before the frame ended; the next call
+    /// resumes the same frame with the remaining cycle budget.
+    pub fn run_frame_with(&mut self, hook: &mut impl CycleHook) -> Result<Option<StepOutcome>, CpuError> {
+        while self.frame_cycles < self.config.cycles_per_frame {
+            if !hook.before_cycle(self) {
+                return Ok(None);
+            }
+            self.frame_cycles += 1;
+            let outcome = self.step()?;
+            let resume = hook.after_cycle(self);
+            if outcome != StepOutcome::Continue {
+                return Ok(Some(self.end_frame(outcome)));
+            }
+            if !resume {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.end_frame(StepOutcome::Continue)))
+    }
+
+    /// Returns true if a hook stopped execution part way through a frame
+    pub fn is_mid_frame(&self) -> bool {
+        self.frame_cycles > 0
+    }
+
+    /// Instructions already run in the current frame
+    pub(crate) fn frame_cycles(&self) -> u32 {
+        self.frame_cycles
+    }
+
+    /// Restores the position within the frame after `load_state` (which starts a new frame)
+    pub(crate) fn set_frame_cycles(&mut self, cycles: u32) {
+        self.frame_cycles = cycles;
+    }
+
+    fn end_frame(&mut self, outcome: StepOutcome) -> StepOutcome {
+        self.frame_cycles = 0;
+        // Timer decrements at END of frame (after CPU cycles)
+        self.cpu.tick_timers();
+        outcome
+    }
+
+    /// Snapshots the CPU, memory, display and keyboard in the versioned save state format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.config.variant);
+        self.cpu.save_state(&mut writer);
+        self.memory.save_state(&mut writer);
+        self.display.save_state(&mut writer);
+        self.keyboard.save_state(&mut writer);
+        writer.finish()
+    }
+
+    /// Restores a snapshot from `save_state`
+    /// The machine is left untouched if the state is invalid. A halted CPU resumes.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data, self.config.variant)?;
+        let mut cpu = Cpu::load_state(&mut reader)?;
+        let memory = Memory::load_state(&mut reader, self.config.variant)?;
+        let display = Display::load_state(&mut reader)?;
+        let keyboard = Keyboard::load_state(&mut reader)?;
+        reader.finish()?;
+
+        // The generator is not part of the state; keep the running one
+        std::mem::swap(&mut cpu.rng, &mut self.cpu.rng);
+        cpu.decode_cache = self.cpu.decode_cache;
+        cpu.variant = self.cpu.variant;
+        self.cpu = cpu;
+        self.memory = memory;
+        self.display = display;
+        self.keyboard = keyboard;
+        self.halted = None;
+        self.frame_cycles = 0;
+        Ok(())
+    }
+
+    /// The configuration this machine was built with
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /// Changes the instruction budget per frame (speed control)
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.config.cycles_per_frame = cycles;
+    }
+
+    /// The error that halted the CPU, if any
+    pub fn halted(&self) -> Option<CpuError> {
+        self.halted
+    }
+
+    /// The loaded ROM
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn display_mut(&mut self) -> &mut Display {
+        &mut self.display
+    }
+
+    pub fn keyboard(&self) -> &Keyboard {
+        &self.keyboard
+    }
+
+    pub fn keyboard_mut(&mut self) -> &mut Keyboard {
+        &mut self.keyboard
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new(MachineConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_new_uses_config() {
+        let config = MachineConfig {
+            load_address: 0x600,
+            ..MachineConfig::for_variant(Variant::XoChip)
+        };
+        let machine = Machine::new(config);
+        assert_eq!(machine.cpu().pc, 0x600);
+        assert_eq!(machine.memory().size(), 0x10000);
+        assert_eq!(machine.cpu().quirks, Quirks::xo_chip());
+    }
+
+    #[test]
+    fn test_machine_config_for_profile() {
+        let config = MachineConfig::for_profile("schip-legacy").unwrap();
+        assert_eq!(config.variant, Variant::SuperChip);
+        assert_eq!(config.quirks, Quirks::schip_legacy());
+        assert_eq!(MachineConfig::for_profile("chip-9000"), None);
+    }
+
+    #[test]
+    fn test_machine_load_rom_too_large_keeps_state() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x01]).unwrap();
+        let result = machine.load_rom(&vec![0; 4000]);
+        assert!(matches!(result, Err(RomError::TooLarge { .. })));
+        assert_eq!(machine.rom(), &[0x60, 0x01]);
+    }
+
+    #[test]
+    fn test_machine_reset_reloads_rom() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x2A]).unwrap(); // LD V0, 42
+        machine.step().unwrap();
+        machine.memory_mut().write(0x200, 0x00);
+        assert_eq!(machine.cpu().v[0], 42);
+
+        machine.reset();
+        assert_eq!(machine.cpu().v[0], 0);
+        assert_eq!(machine.cpu().pc, 0x200);
+        assert_eq!(machine.memory().read(0x200), 0x60);
+    }
+
+    #[test]
+    fn test_machine_reset_keeps_decode_cache_setting() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x2A]).unwrap();
+        machine.cpu_mut().set_decode_cache(false);
+        machine.reset();
+        machine.step().unwrap();
+        assert!(machine.memory().decoded(0x200).is_none());
+    }
+
+    #[test]
+    fn test_machine_seed_makes_rnd_reproducible() {
+        let config = MachineConfig { seed: Some(1234), ..MachineConfig::default() };
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF]; // RND V0-V2, 0xFF
+        let run = |machine: &mut Machine| {
+            for _ in 0..3 {
+                machine.step().unwrap();
+            }
+            machine.cpu().v
+        };
+
+        let mut machine = Machine::new(config);
+        machine.load_rom(&rom).unwrap();
+        let first = run(&mut machine);
+        let mut other = Machine::new(config);
+        other.load_rom(&rom).unwrap();
+        assert_eq!(run(&mut other), first);
+
+        // A reset re-seeds; loading a state keeps the running generator
+        machine.reset();
+        let state = machine.save_state();
+        assert_eq!(run(&mut machine), first);
+        machine.load_state(&state).unwrap();
+        assert_ne!(run(&mut machine), first);
+    }
+
+    #[test]
+    fn test_machine_run_frame_stops_at_draw() {
+        let mut machine = Machine::default();
+        // LD V0, 5 / LD DT, V0 / DRW V0, V0, 1 / JP 0x206
+        machine.load_rom(&[0x60, 0x05, 0xF0, 0x15, 0xD0, 0x01, 0x12, 0x06]).unwrap();
+
+        assert_eq!(machine.run_frame(), Ok(StepOutcome::WaitForVblank));
+        assert_eq!(machine.cpu().pc, 0x206);
+        assert_eq!(machine.cpu().delay_timer, 4); // Ticked once at end of frame
+    }
+
+    #[test]
+    fn test_machine_save_state_round_trip() {
+        // LD V0, 3 / LD ST, V0 / LD V1, K
+        let rom = [0x60, 0x03, 0xF0, 0x18, 0xF1, 0x0A];
+        let mut machine = Machine::new(MachineConfig::for_variant(Variant::XoChip));
+        machine.load_rom(&rom).unwrap();
+        machine.display_mut().set_pixel(5, 5, true);
+        machine.keyboard_mut().set_key(0xA, true);
+        for _ in 0..3 {
+            machine.step().unwrap();
+        }
+        let state = machine.save_state();
+
+        let mut restored = Machine::new(MachineConfig::for_variant(Variant::XoChip));
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.cpu().pc, 0x204);
+        assert_eq!(restored.cpu().sound_timer, 3);
+        assert!(restored.display().get_pixel(5, 5));
+        assert!(restored.keyboard().is_key_pressed(0xA));
+
+        // The private FX0A wait survives: releasing the key completes LD V1, K
+        restored.keyboard_mut().set_key(0xA, false);
+        restored.step().unwrap();
+        assert_eq!(restored.cpu().v[1], 0xA);
+        assert_eq!(restored.cpu().pc, 0x206);
+    }
+
+    #[test]
+    fn test_machine_load_state_rejects_invalid() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x2A]).unwrap();
+        let state = machine.save_state();
+
+        machine.step().unwrap();
+        assert_eq!(machine.load_state(&state[..state.len() - 1]), Err(SaveStateError::Truncated));
+        assert_eq!(machine.cpu().v[0], 42); // Untouched
+
+        let mut xo = Machine::new(MachineConfig::for_variant(Variant::XoChip));
+        assert!(matches!(xo.load_state(&state), Err(SaveStateError::VariantMismatch { .. })));
+    }
+
+    struct StopAfter(u32);
+
+    impl CycleHook for StopAfter {
+        fn after_cycle(&mut self, _machine: &mut Machine) -> bool {
+            self.0 -= 1;
+            self.0 > 0
+        }
+    }
+
+    #[test]
+    fn test_machine_run_frame_with_hook_resumes() {
+        let mut machine = Machine::new(MachineConfig { cycles_per_frame: 10, ..MachineConfig::default() });
+        // LD V0, 2 / LD DT, V0 / ADD V1, 1 / JP 0x204
+        machine.load_rom(&[0x60, 0x02, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04]).unwrap();
+
+        assert_eq!(machine.run_frame_with(&mut StopAfter(4)), Ok(None));
+        assert!(machine.is_mid_frame());
+        assert_eq!(machine.cpu().delay_timer, 2); // Not ticked yet
+
+        assert_eq!(machine.run_frame_with(&mut ()), Ok(Some(StepOutcome::Continue)));
+        assert!(!machine.is_mid_frame());
+        assert_eq!(machine.cpu().v[1], 4); // 10 cycles: 2 setup, then 4 x (ADD, JP)
+        assert_eq!(machine.cpu().delay_timer, 1);
+    }
+
+    #[test]
+    fn test_machine_halts_on_error() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x00, 0xEE]).unwrap(); // RET with empty stack
+        let error = machine.run_frame().unwrap_err();
+        assert_eq!(machine.halted(), Some(error));
+        assert_eq!(machine.step(), Err(error));
+
+        machine.reset();
+        assert_eq!(machine.halted(), None);
+    }
+
+    #[test]
+    fn test_machine_decodes_with_configured_variant() {
+        let mut machine = Machine::new(MachineConfig::for_variant(Variant::Chip8));
+        machine.load_rom(&[0x00, 0xFF]).unwrap(); // Super-CHIP HIGH
+        let state = machine.save_state();
+        assert_eq!(machine.step(), Err(CpuError::UnknownOpcode { pc: 0x200, opcode: 0x00FF }));
+
+        // The restriction survives loading a state
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.step(), Err(CpuError::UnknownOpcode { pc: 0x200, opcode: 0x00FF }));
+
+        let mut machine = Machine::new(MachineConfig::for_variant(Variant::SuperChip));
+        machine.load_rom(&[0x00, 0xFF]).unwrap();
+        assert!(machine.step().is_ok());
+        assert!(machine.display().is_hires());
+    }
+}
@@ -2,31 +2,176 @@
 //!
 //! A CHIP-8 emulator written in Rust.
 
-use chip8_emulator::cpu::Cpu;
-use chip8_emulator::display::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_emulator::analyzer::analyze;
 use chip8_emulator::keyboard::Keyboard;
-use chip8_emulator::memory::Memory;
+use chip8_emulator::machine::{Machine, MachineConfig, DEFAULT_CYCLES_PER_FRAME};
+use chip8_emulator::memory::{AddressPolicy, POLICY_NAMES, PROGRAM_START};
+use chip8_emulator::movie::Movie;
+use chip8_emulator::quirks::{Quirks, PRESET_NAMES};
+use chip8_emulator::rewind::{RewindBuffer, DEFAULT_REWIND_SECONDS};
+use chip8_emulator::rng::{RngKind, RNG_NAMES};
+use chip8_emulator::romdb::{RomDatabase, RomInfo};
 use chip8_emulator::sound::Sound;
+use chip8_emulator::symbols::Symbols;
+use chip8_emulator::trace::{self, Tracer};
+use chip8_emulator::variant::Variant;
 use minifb::{Key, Window, WindowOptions};
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::BufWriter;
 use std::time::{Duration, Instant};
 
 const WINDOW_WIDTH: usize = 640;
 const WINDOW_HEIGHT: usize = 320;
-const CYCLES_PER_FRAME: u32 = 200;  // Instructions per frame - high value for quirks test (DRW breaks early anyway)
+const SAVE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+const LOAD_SLOT_KEYS: [Key; 4] = [Key::F5, Key::F6, Key::F7, Key::F8];
+/// Host keys for the game actions named by the ROM database's keymaps
+const ACTION_KEYS: [(&str, Key); 12] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("a", Key::Space),
+    ("b", Key::Enter),
+    ("player2Up", Key::I),
+    ("player2Down", Key::K),
+    ("player2Left", Key::J),
+    ("player2Right", Key::L),
+    ("player2A", Key::U),
+    ("player2B", Key::O),
+];
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 2 {
+
+    // Parse command line: <rom_file> [--quirks <profile>] [--memory-policy <policy>] [--load-address <addr>]
+    //                     [--rewind-seconds <n>] [--trace <file>] [--trace-range <start>-<end>]
+    //                     [--symbols <file>] [--seed <n>] [--rng <generator>] [--record <file>]
+    //                     [--cycles <n>] [--rom-db <file>] [--no-rom-db]
+    let mut rom_path: Option<&String> = None;
+    let mut quirk_name: Option<&str> = None;
+    let mut address_policy = AddressPolicy::default();
+    let mut load_address = PROGRAM_START;
+    let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+    let mut trace_path: Option<&String> = None;
+    let mut trace_range = None;
+    let mut symbols_path: Option<&String> = None;
+    let mut seed: Option<u64> = None;
+    let mut rng = RngKind::default();
+    let mut record_path: Option<&String> = None;
+    let mut cycles_per_frame: Option<u32> = None;
+    let mut rom_db_path: Option<&String> = None;
+    let mut use_rom_db = true;
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        if arg == "--quirks" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            if Quirks::from_name(name).is_none() {
+                eprintln!("Unknown quirk profile '{}'. Available: {}", name, PRESET_NAMES.join(", "));
+                return;
+            }
+            quirk_name = Some(name);
+        } else if arg == "--memory-policy" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            address_policy = match AddressPolicy::from_name(name) {
+                Some(policy) => policy,
+                None => {
+                    eprintln!("Unknown memory policy '{}'. Available: {}", name, POLICY_NAMES.join(", "));
+                    return;
+                }
+            };
+        } else if arg == "--load-address" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            load_address = match u16::from_str_radix(text.trim_start_matches("0x"), 16) {
+                Ok(addr) => addr,
+                Err(_) => {
+                    eprintln!("Invalid load address '{}'. Expected hex, e.g. 0x600", text);
+                    return;
+                }
+            };
+        } else if arg == "--rewind-seconds" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            rewind_seconds = match text.parse() {
+                Ok(seconds) => seconds,
+                Err(_) => {
+                    eprintln!("Invalid rewind depth '{}'. Expected whole seconds, e.g. 30", text);
+                    return;
+                }
+            };
+        } else if arg == "--seed" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            seed = match text.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    eprintln!("Invalid seed '{}'. Expected a whole number", text);
+                    return;
+                }
+            };
+        } else if arg == "--rng" {
+            let name = arg_iter.next().map(String::as_str).unwrap_or("");
+            rng = match RngKind::from_name(name) {
+                Some(kind) => kind,
+                None => {
+                    eprintln!("Unknown random generator '{}'. Available: {}", name, RNG_NAMES.join(", "));
+                    return;
+                }
+            };
+        } else if arg == "--record" {
+            record_path = arg_iter.next();
+        } else if arg == "--trace" {
+            trace_path = arg_iter.next();
+        } else if arg == "--trace-range" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            trace_range = match trace::parse_pc_range(text) {
+                Some(range) => Some(range),
+                None => {
+                    eprintln!("Invalid trace range '{}'. Expected hex start-end, e.g. 200-2FF", text);
+                    return;
+                }
+            };
+        } else if arg == "--symbols" {
+            symbols_path = arg_iter.next();
+        } else if arg == "--cycles" {
+            let text = arg_iter.next().map(String::as_str).unwrap_or("");
+            cycles_per_frame = match text.parse() {
+                Ok(cycles) if cycles > 0 => Some(cycles),
+                _ => {
+                    eprintln!("Invalid cycle count '{}'. Expected instructions per frame, e.g. 30", text);
+                    return;
+                }
+            };
+        } else if arg == "--rom-db" {
+            rom_db_path = arg_iter.next();
+        } else if arg == "--no-rom-db" {
+            use_rom_db = false;
+        } else {
+            rom_path = Some(arg);
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
         println!("CHIP-8 Emulator");
         println!("===============");
         println!();
-        println!("Usage: {} <rom_file>", args[0]);
+        println!("Usage: {} <rom_file> [options]", args[0]);
         println!();
         println!("Example: {} roms/pong.ch8", args[0]);
         println!();
+        println!("Options:");
+        println!("  --quirks <profile>         Interpreter behavior: {} (default: ROM database, else detected)", PRESET_NAMES.join(", "));
+        println!("  --cycles <n>               Instructions per frame (default: ROM database, else {})", DEFAULT_CYCLES_PER_FRAME);
+        println!("  --rom-db <file>            Extra ROM database (community programs.json) checked before the bundled one");
+        println!("  --no-rom-db                Ignore the ROM database: no automatic quirks, speed, keys or colours");
+        println!("  --memory-policy <policy>   Out-of-range addresses: {} (default: wrap)", POLICY_NAMES.join(", "));
+        println!("  --load-address <addr>      Load address and entry point in hex (default: 0x200, ETI-660: 0x600)");
+        println!("  --rewind-seconds <n>       Rewind depth at 60 fps, 0 disables (default: {})", DEFAULT_REWIND_SECONDS);
+        println!("  --seed <n>                 Seed the RND generator for a reproducible run");
+        println!("  --rng <generator>          RND generator: {} (default: std)", RNG_NAMES.join(", "));
+        println!("  --record <file>            Record keypad input to a movie for chip8-headless --replay");
+        println!("  --trace <file>             Write a per-instruction trace to a file");
+        println!("  --trace-range <start-end>  Only trace instructions with PC in this hex range");
+        println!("  --symbols <file>           Symbol map (addr name lines or Octo JSON) to label trace lines");
+        println!();
         println!("Controls:");
         println!("  P           - Pause/Resume");
         println!("  R           - Reset");
@@ -34,12 +179,13 @@ fn main() {
         println!("  [/-         - CPU speed down");
         println!("  Page Up     - Timer speed up");
         println!("  Page Down   - Timer speed down");
+        println!("  F1-F4       - Save state to slot 1-4");
+        println!("  F5-F8       - Load state from slot 1-4");
+        println!("  Backspace   - Rewind (hold)");
         println!("  ESC         - Quit");
         return;
-    }
+    };
 
-    let rom_path = &args[1];
-    
     // Load ROM
     let rom_data = match fs::read(rom_path) {
         Ok(data) => data,
@@ -50,6 +196,64 @@ fn main() {
     };
 
     println!("Loaded ROM: {} ({} bytes)", rom_path, rom_data.len());
+
+    // Known ROMs get the settings the database recommends; command line flags override them
+    let rom_database = match (use_rom_db, rom_db_path) {
+        (false, _) => RomDatabase::new(),
+        (true, None) => RomDatabase::bundled(),
+        (true, Some(path)) => match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| {
+            RomDatabase::parse(&text).map_err(|e| e.to_string())
+        }) {
+            Ok(database) => {
+                let mut combined = RomDatabase::bundled();
+                combined.extend(database);
+                combined
+            }
+            Err(e) => {
+                eprintln!("Error loading ROM database '{}': {}", path, e);
+                return;
+            }
+        },
+    };
+    let rom_info = rom_database.lookup(&rom_data);
+    if let Some(info) = rom_info {
+        println!("ROM database: {} by {} ({})", info.title, info.author(), info.platform);
+    }
+
+    // The database's settings for this ROM, then the command line's overrides
+    let mut config = MachineConfig { address_policy, load_address, rng, seed, ..MachineConfig::default() };
+    if let Some(info) = rom_info {
+        info.apply(&mut config);
+    }
+    match (quirk_name, rom_info) {
+        (Some(name), _) => {
+            // The profile also decides the instruction set family (memory size)
+            config.quirks = Quirks::from_name(name).expect("profile names are checked when parsed");
+            config.variant = Variant::from_profile_name(name).unwrap_or_default();
+            println!("Quirk profile: {}", name);
+        }
+        (None, Some(info)) => println!("Quirk profile: ROM database ({})", info.platform),
+        // Unknown ROM: use the profile for the variant its instructions need
+        (None, None) => {
+            let analysis = analyze(&rom_data, load_address);
+            println!("Detected {} program", analysis.variant.name());
+            let name = analysis.profile();
+            config.quirks = Quirks::from_name(name).expect("analysis picks a preset");
+            config.variant = analysis.variant;
+            println!("Quirk profile: {}", name);
+        }
+    }
+    if let Some(cycles) = cycles_per_frame {
+        config.cycles_per_frame = cycles;
+    }
+    if config.cycles_per_frame != DEFAULT_CYCLES_PER_FRAME {
+        println!("Cycles per frame: {}", config.cycles_per_frame);
+    }
+    if let Some(seed) = seed {
+        println!("Random seed: {}", seed);
+    }
+    let action_keys = rom_info.map(action_keys).unwrap_or_default();
+
     println!();
     println!("Controls:");
     println!("  P           - Pause/Resume");
@@ -58,20 +262,62 @@ fn main() {
     println!("  [/-         - CPU speed down");
     println!("  Page Up     - Timer speed up (current: 1.0x)");
     println!("  Page Down   - Timer speed down");
+    println!("  F1-F4       - Save state to slot 1-4");
+    println!("  F5-F8       - Load state from slot 1-4");
+    println!("  Backspace   - Rewind (hold, up to {} seconds)", rewind_seconds);
     println!("  ESC         - Quit");
+    for (key, chip8_key) in action_keys.iter() {
+        println!("  {:<11} - CHIP-8 key {:X}", format!("{:?}", key), chip8_key);
+    }
 
     // Initialize components
-    let mut cpu = Cpu::new();
-    let mut memory = Memory::new();
-    let mut display = Display::new();
-    let mut keyboard = Keyboard::new();
-    let sound = Sound::new().unwrap_or_else(|| {
+    // Movie recording: the movie picks a seed if none was given, so replays get the same RND bytes
+    let mut recording = record_path.map(|path| (path, Movie::new(config, &rom_data)));
+    if let Some((path, movie)) = &recording {
+        config = movie.config;
+        println!("Recording movie to {} (seed {})", path, config.seed.unwrap_or(0));
+    }
+    let mut movie_reset = false;
+
+    let mut machine = Machine::new(config);
+    let sound = Sound::for_variant(config.variant).unwrap_or_else(|| {
         eprintln!("Warning: Could not initialize audio system");
         Sound::default()
     });
 
     // Load ROM into memory
-    memory.load_rom(&rom_data);
+    if let Err(e) = machine.load_rom(&rom_data) {
+        eprintln!("Error loading ROM '{}': {}", rom_path, e);
+        return;
+    }
+    let symbols = match symbols_path {
+        Some(path) => match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| {
+            Symbols::parse(&text).map_err(|e| e.to_string())
+        }) {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                eprintln!("Error loading symbols '{}': {}", path, e);
+                return;
+            }
+        },
+        None => Symbols::new(),
+    };
+
+    // Optional per-instruction trace
+    let mut tracer = match trace_path {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(Tracer::new(BufWriter::new(file), trace_range).with_symbols(symbols)),
+            Err(e) => {
+                eprintln!("Error creating trace '{}': {}", path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    // Per-frame snapshots for stepping back
+    let mut rewind = RewindBuffer::with_seconds(rewind_seconds);
+    rewind.push(&machine);
 
     // Debug timing variables (commented out)
     // let mut debug_timer = Instant::now();
@@ -90,6 +336,9 @@ fn main() {
     let mut last_minus_key = false;
     let last_pgup_key = false;
     let last_pgdn_key = false;
+    let mut last_save_keys = [false; 4];
+    let mut last_load_keys = [false; 4];
+    let mut last_rewind_key = false;
 
     // Create window
     let mut window = Window::new(
@@ -105,8 +354,6 @@ fn main() {
     // Don't use minifb's rate limiting - we'll do our own precise timing
     window.set_target_fps(0);
 
-    let mut cycles_per_frame = CYCLES_PER_FRAME;
-    
     // Precise frame timing for DISP.WAIT
     let frame_duration = Duration::from_nanos(1_000_000_000 / 60); // Exactly 60 Hz
     let mut last_frame_time = Instant::now();
@@ -139,11 +386,11 @@ fn main() {
 
         // Reset emulator (detect rising edge)
         if r_pressed && !last_r_key {
-            cpu = Cpu::new();
-            memory = Memory::new();
-            display = Display::new();
-            keyboard = Keyboard::new();
-            memory.load_rom(&rom_data);
+            machine.reset();
+            movie_reset = true;
+            rewind.clear();
+            rewind.push(&machine);
+            window.set_title(&format!("CHIP-8 Emulator - CPU:{:.2}x Timer:{:.2}x", speed_multiplier, timer_multiplier));
             last_frame_time = Instant::now();
             println!("Reset emulator");
         }
@@ -152,7 +399,8 @@ fn main() {
         // CPU Speed up (detect rising edge) - increases cycles per frame
         if plus_pressed && !last_plus_key {
             speed_multiplier = (speed_multiplier * 2.0).min(4.0);
-            cycles_per_frame = (CYCLES_PER_FRAME as f32 * speed_multiplier) as u32;
+            let cycles_per_frame = (config.cycles_per_frame as f32 * speed_multiplier).max(1.0) as u32;
+            machine.set_cycles_per_frame(cycles_per_frame);
             let status = if is_paused { "PAUSED" } else { "" };
             let title = format!("CHIP-8 Emulator - CPU:{:.2}x Timer:{:.2}x {}", speed_multiplier, timer_multiplier, status);
             window.set_title(&title);
@@ -163,7 +411,8 @@ fn main() {
         // CPU Speed down (detect rising edge)
         if minus_pressed && !last_minus_key {
             speed_multiplier = (speed_multiplier / 2.0).max(0.25);
-            cycles_per_frame = (CYCLES_PER_FRAME as f32 * speed_multiplier) as u32;
+            let cycles_per_frame = (config.cycles_per_frame as f32 * speed_multiplier).max(1.0) as u32;
+            machine.set_cycles_per_frame(cycles_per_frame);
             let status = if is_paused { "PAUSED" } else { "" };
             let title = format!("CHIP-8 Emulator - CPU:{:.2}x Timer:{:.2}x {}", speed_multiplier, timer_multiplier, status);
             window.set_title(&title);
@@ -171,6 +420,38 @@ fn main() {
         }
         last_minus_key = minus_pressed;
 
+        // Save/load state slots (detect rising edge)
+        for slot in 0..SAVE_SLOT_KEYS.len() {
+            let save_pressed = window.is_key_down(SAVE_SLOT_KEYS[slot]);
+            if save_pressed && !last_save_keys[slot] {
+                let path = state_path(rom_path, slot);
+                match fs::write(&path, machine.save_state()) {
+                    Ok(()) => println!("Saved state to slot {} ({})", slot + 1, path),
+                    Err(e) => eprintln!("Error saving state '{}': {}", path, e),
+                }
+            }
+            last_save_keys[slot] = save_pressed;
+
+            let load_pressed = window.is_key_down(LOAD_SLOT_KEYS[slot]);
+            if load_pressed && !last_load_keys[slot] {
+                stop_recording(&mut recording, &machine);
+                let path = state_path(rom_path, slot);
+                let result = fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| machine.load_state(&data).map_err(|e| e.to_string()));
+                match result {
+                    Ok(()) => {
+                        rewind.clear();
+                        rewind.push(&machine);
+                        window.set_title(&format!("CHIP-8 Emulator - CPU:{:.2}x Timer:{:.2}x", speed_multiplier, timer_multiplier));
+                        println!("Loaded state from slot {}", slot + 1);
+                    }
+                    Err(e) => eprintln!("Error loading state '{}': {}", path, e),
+                }
+            }
+            last_load_keys[slot] = load_pressed;
+        }
+
         // Timer Speed controls disabled for now - proper DISP.WAIT requires exactly 60 Hz
         // Keeping the key checks to avoid warnings
         let _ = (pgup_pressed, last_pgup_key, pgdn_pressed, last_pgdn_key, timer_multiplier);
@@ -190,65 +471,115 @@ fn main() {
         //     debug_timer = Instant::now();
         // }
 
-        // Skip execution if paused
-        if !is_paused {
+        // Rewind one frame per 60 Hz tick while Backspace is held (also works when halted)
+        let rewind_pressed = window.is_key_down(Key::Backspace);
+        if !rewind_pressed && last_rewind_key {
+            let status = if is_paused { "PAUSED" } else { "" };
+            window.set_title(&format!("CHIP-8 Emulator - CPU:{:.2}x Timer:{:.2}x {}", speed_multiplier, timer_multiplier, status));
+        }
+        last_rewind_key = rewind_pressed;
+
+        if rewind_pressed {
+            stop_recording(&mut recording, &machine);
+            wait_for_frame(&mut last_frame_time, frame_duration);
+            if rewind.rewind(&mut machine) {
+                window.set_title(&format!("CHIP-8 Emulator - REWIND ({} frames left)", rewind.len()));
+            }
+            sound.stop();
+        } else if !is_paused && machine.halted().is_none() {
+            // Skip execution if paused or halted by a CPU error
             // Handle keyboard input
-            update_keyboard(&window, &mut keyboard);
+            update_keyboard(&window, machine.keyboard_mut(), &action_keys);
+            if let Some((_, movie)) = recording.as_mut() {
+                movie.record_frame(&machine, std::mem::take(&mut movie_reset));
+            }
 
             // FRAME-BASED EXECUTION with precise timing:
             // - Wait for next frame boundary (60 Hz)
-            // - Run CPU cycles until DRW or cycles_per_frame reached
-            // - Timer decrements ONCE per frame (AFTER CPU cycles)
-            
-            // Precise frame timing: spin-wait until frame boundary
-            let now = Instant::now();
-            let elapsed = now.duration_since(last_frame_time);
-            if elapsed < frame_duration {
-                // Sleep for most of the remaining time (minus 1ms for spin accuracy)
-                let remaining = frame_duration - elapsed;
-                if remaining > Duration::from_millis(1) {
-                    std::thread::sleep(remaining - Duration::from_millis(1));
-                }
-                // Spin-wait for the exact frame boundary
-                while Instant::now().duration_since(last_frame_time) < frame_duration {
-                    std::hint::spin_loop();
-                }
-            }
-            last_frame_time += frame_duration; // Use addition to prevent drift
-            
+            // - Machine::run_frame runs the CPU and ticks the timers once
+            wait_for_frame(&mut last_frame_time, frame_duration);
+
             // Run CPU cycles for this frame
-            // DISP.WAIT: If DRW executes, break loop early
-            for _ in 0..cycles_per_frame {
-                let wait_for_vblank = cpu.cycle(&mut memory, &mut display, &keyboard);
-                if wait_for_vblank {
-                    break;
-                }
+            // DISP.WAIT: If DRW executes (and the quirk is enabled), the frame ends early
+            // A tracer never stops mid-frame, so the frame always completes
+            let result = match tracer.as_mut() {
+                Some(tracer) => machine.run_frame_with(tracer).map(|_| ()),
+                None => machine.run_frame().map(|_| ()),
+            };
+            if let Err(error) = result {
+                // Keep the window open showing the last frame; R resets
+                eprintln!("CPU halted: {}", error);
+                window.set_title(&format!("CHIP-8 Emulator - HALTED: {} (R to reset, Backspace to rewind)", error));
             }
-
-            // Timer decrements at END of frame (after CPU cycles)
-            cpu.tick_timers();
+            rewind.push(&machine);
 
             // Handle sound based on sound_timer
+            // XO-CHIP: feed the current pattern buffer and pitch to the player
+            let cpu = machine.cpu();
+            sound.set_pattern(cpu.audio_pattern, cpu.pitch);
             if cpu.sound_timer > 0 {
                 sound.play();
             } else {
                 sound.stop();
             }
         } else {
-            // When paused, still stop sound
+            // When paused or halted, still stop sound
             sound.stop();
         }
 
         // Update display (runs at window refresh rate, ~60 Hz)
+        // minifb scales the 64x32 or 128x64 buffer to the window size
+        let display = machine.display();
         let buffer = display.to_buffer();
         window
-            .update_with_buffer(&buffer, DISPLAY_WIDTH, DISPLAY_HEIGHT)
+            .update_with_buffer(&buffer, display.width(), display.height())
             .unwrap();
     }
 
+    stop_recording(&mut recording, &machine);
+    if let Some(tracer) = tracer {
+        if let Err(e) = tracer.finish() {
+            eprintln!("Error writing trace: {}", e);
+        }
+    }
+
     println!("Emulator stopped.");
 }
 
+/// Precise frame timing: sleeps, then spin-waits until the next frame boundary
+fn wait_for_frame(last_frame_time: &mut Instant, frame_duration: Duration) {
+    let elapsed = Instant::now().duration_since(*last_frame_time);
+    if elapsed < frame_duration {
+        // Sleep for most of the remaining time (minus 1ms for spin accuracy)
+        let remaining = frame_duration - elapsed;
+        if remaining > Duration::from_millis(1) {
+            std::thread::sleep(remaining - Duration::from_millis(1));
+        }
+        // Spin-wait for the exact frame boundary
+        while Instant::now().duration_since(*last_frame_time) < frame_duration {
+            std::hint::spin_loop();
+        }
+    }
+    *last_frame_time += frame_duration; // Use addition to prevent drift
+}
+
+/// Finishes a movie recording and writes it out
+/// Loading a state or rewinding cannot be replayed, so they end the recording too.
+fn stop_recording(recording: &mut Option<(&String, Movie)>, machine: &Machine) {
+    if let Some((path, mut movie)) = recording.take() {
+        movie.finish(machine);
+        match fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("Saved movie ({} frames) to {}", movie.frames.len(), path),
+            Err(e) => eprintln!("Error saving movie '{}': {}", path, e),
+        }
+    }
+}
+
+/// File used for a save state slot (0-based): `<rom>.state1` to `<rom>.state4`
+fn state_path(rom_path: &str, slot: usize) -> String {
+    format!("{}.state{}", rom_path, slot + 1)
+}
+
 /// Maps keyboard input to CHIP-8 keys
 /// 
 /// CHIP-8 keypad:     Modern keyboard:
@@ -256,7 +587,9 @@ fn main() {
 /// 4 5 6 D            Q W E R
 /// 7 8 9 E            A S D F
 /// A 0 B F            Z X C V
-fn update_keyboard(window: &Window, keyboard: &mut Keyboard) {
+///
+/// `action_keys` adds the arrow and other game keys of a ROM database keymap.
+fn update_keyboard(window: &Window, keyboard: &mut Keyboard, action_keys: &[(Key, u8)]) {
     // Release all keys first
     for i in 0..16 {
         keyboard.set_key(i, false);
@@ -282,9 +615,20 @@ fn update_keyboard(window: &Window, keyboard: &mut Keyboard) {
         (Key::V, 0xF),
     ];
 
-    for (key, chip8_key) in key_map.iter() {
+    for (key, chip8_key) in key_map.iter().chain(action_keys) {
         if window.is_key_down(*key) {
             keyboard.set_key(*chip8_key, true);
         }
     }
 }
+
+/// Host keys for the game actions in a ROM's database keymap (see `ACTION_KEYS`)
+fn action_keys(info: &RomInfo) -> Vec<(Key, u8)> {
+    info.keys
+        .iter()
+        .filter_map(|(action, chip8_key)| {
+            let (_, key) = ACTION_KEYS.iter().find(|(name, _)| name == action)?;
+            Some((*key, *chip8_key))
+        })
+        .collect()
+}
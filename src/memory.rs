@@ -3,8 +3,25 @@
 //! CHIP-8 has 4KB (4096 bytes) of RAM.
 //! - 0x000-0x1FF: Reserved for interpreter (font data stored here)
 //! - 0x200-0xFFF: Program and data space
+//!
+//! XO-CHIP extends the address space to 64KB (0x0000-0xFFFF).
+//!
+//! Accesses past the end of RAM are resolved by an `AddressPolicy`.
+
+use crate::instruction::Instruction;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::variant::Variant;
+use std::fmt;
+
+/// Where programs are loaded and start executing on most interpreters
+pub const PROGRAM_START: u16 = 0x200;
+/// Load address and entry point used by the ETI-660
+pub const ETI660_PROGRAM_START: u16 = 0x600;
 
-/// The 4KB memory of the CHIP-8 system
+/// Address of the 5-byte small font (Fx29)
+pub const FONT_ADDR: u16 = 0x000;
+/// Address of the 10-byte Super-CHIP big font (Fx30)
+pub const BIG_FONT_ADDR: u16 = 0x050;
 
 const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -25,8 +42,101 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// Super-CHIP 8x10 digits (0-9 from SCHIP 1.1, A-F as added by Octo/XO-CHIP)
+const BIG_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// What happens when an address lies outside RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressPolicy {
+    /// Wrap around at the end of RAM (4KB, or 64KB for XO-CHIP)
+    #[default]
+    Wrap,
+    /// Keep only the low 12 bits, as the COSMAC VIP address bus did
+    Mask,
+    /// Report a `MemoryError` (surfaced by the CPU as `CpuError::MemoryOutOfBounds`)
+    Strict,
+    /// Reads return 0 and writes are ignored
+    OpenBus,
+}
+
+/// Names accepted by `AddressPolicy::from_name`
+pub const POLICY_NAMES: [&str; 4] = ["wrap", "mask", "strict", "open-bus"];
+
+impl AddressPolicy {
+    /// Looks up a policy by name (see `POLICY_NAMES`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(AddressPolicy::Wrap),
+            "mask" => Some(AddressPolicy::Mask),
+            "strict" => Some(AddressPolicy::Strict),
+            "open-bus" => Some(AddressPolicy::OpenBus),
+            _ => None,
+        }
+    }
+}
+
+/// An access outside RAM under the `Strict` policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryError {
+    /// The address as requested, before any policy was applied
+    pub addr: u16,
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Memory access out of bounds at {:#06x}", self.addr)
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+/// Why a ROM could not be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomError {
+    /// The ROM does not fit between the load address and the end of RAM
+    TooLarge { size: usize, max_size: usize, load_address: u16 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RomError::TooLarge { size, max_size, load_address } => write!(
+                f,
+                "ROM too large for variant: {} bytes, but only {} bytes fit when loading at {:#05x}",
+                size, max_size, load_address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// The RAM of the CHIP-8 system (4KB, or 64KB for XO-CHIP)
 pub struct Memory {
-    ram: [u8; 4096],
+    ram: Vec<u8>,
+    /// How out-of-range addresses are handled
+    policy: AddressPolicy,
+    /// Addresses written since the last `take_writes`, when tracking is on (watchpoints)
+    write_log: Option<Vec<u16>>,
+    /// Opcodes the CPU has decoded, by address; empty until the first is cached
+    decoded: Vec<Option<(u16, Instruction)>>,
 }
 
 impl Default for Memory {
@@ -36,32 +146,166 @@ impl Default for Memory {
 }
 
 impl Memory {
-    /// Creates a new Memory instance with font data loaded
+    /// Creates a new 4KB Memory instance with font data loaded
     pub fn new() -> Self {
-        let mut mem = Memory { ram: [0; 4096] };
+        Self::for_variant(Variant::Chip8)
+    }
+
+    /// Creates a Memory instance sized for the given variant, with font data loaded
+    pub fn for_variant(variant: Variant) -> Self {
+        let mut mem = Memory {
+            ram: vec![0; variant.memory_size()],
+            policy: AddressPolicy::default(),
+            write_log: None,
+            decoded: Vec::new(),
+        };
         // Load font set into memory starting at 0x000
         for (i, &byte) in FONT_SET.iter().enumerate() {
-            mem.ram[i] = byte;
+            mem.ram[FONT_ADDR as usize + i] = byte;
+        }
+        // Load Super-CHIP big font right after it
+        for (i, &byte) in BIG_FONT_SET.iter().enumerate() {
+            mem.ram[BIG_FONT_ADDR as usize + i] = byte;
         }
         mem
     }
 
+    /// Returns the size of the address space in bytes
+    pub fn size(&self) -> usize {
+        self.ram.len()
+    }
+
+    /// Returns the out-of-range address policy
+    pub fn policy(&self) -> AddressPolicy {
+        self.policy
+    }
+
+    /// Sets the out-of-range address policy
+    pub fn set_policy(&mut self, policy: AddressPolicy) {
+        self.policy = policy;
+    }
+
+    /// Starts or stops recording the address of every write
+    pub fn set_write_tracking(&mut self, enabled: bool) {
+        self.write_log = if enabled { Some(self.write_log.take().unwrap_or_default()) } else { None };
+    }
+
+    /// Returns true if writes are being recorded
+    pub fn is_tracking_writes(&self) -> bool {
+        self.write_log.is_some()
+    }
+
+    /// Returns the RAM addresses written since the last call (after the address policy)
+    pub fn take_writes(&mut self) -> Vec<u16> {
+        self.write_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Maps an address to a RAM index according to the policy.
+    /// Returns Ok(None) for an open-bus access.
+    fn resolve(&self, addr: u16) -> Result<Option<usize>, MemoryError> {
+        let index = addr as usize;
+        if index < self.ram.len() {
+            return Ok(Some(index));
+        }
+        match self.policy {
+            AddressPolicy::Wrap => Ok(Some(index % self.ram.len())),
+            AddressPolicy::Mask => Ok(Some(index & 0x0FFF)),
+            AddressPolicy::Strict => Err(MemoryError { addr }),
+            AddressPolicy::OpenBus => Ok(None),
+        }
+    }
+
+    /// Reads a byte, reporting out-of-range addresses under the `Strict` policy
+    pub fn try_read(&self, addr: u16) -> Result<u8, MemoryError> {
+        Ok(self.resolve(addr)?.map_or(0, |index| self.ram[index]))
+    }
+
+    /// Writes a byte, reporting out-of-range addresses under the `Strict` policy
+    pub fn try_write(&mut self, addr: u16, value: u8) -> Result<(), MemoryError> {
+        if let Some(index) = self.resolve(addr)? {
+            self.ram[index] = value;
+            if let Some(log) = &mut self.write_log {
+                log.push(index as u16);
+            }
+            // Self-modifying code: drop the opcodes starting at this byte and the one before
+            if !self.decoded.is_empty() {
+                self.decoded[index] = None;
+                if index > 0 {
+                    self.decoded[index - 1] = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Reads a byte from the given address
+    /// Out-of-range reads follow the policy; under `Strict` they return 0
     pub fn read(&self, addr: u16) -> u8 {
-        self.ram[addr as usize]
+        self.try_read(addr).unwrap_or(0)
+    }
+
+    /// Reads the big-endian 16-bit opcode at the given address (for inspection; the CPU uses `try_read`)
+    pub fn read_opcode(&self, addr: u16) -> u16 {
+        ((self.read(addr) as u16) << 8) | self.read(addr.wrapping_add(1)) as u16
     }
 
     /// Writes a byte to the given address
+    /// Out-of-range writes follow the policy; under `Strict` they are dropped
     pub fn write(&mut self, addr: u16, value: u8) {
-        self.ram[addr as usize] = value;
+        let _ = self.try_write(addr, value);
     }
 
     /// Loads a ROM into memory starting at 0x200
-    pub fn load_rom(&mut self, data: &[u8]) {
-        let start = 0x200;
-        for (i, &byte) in data.iter().enumerate() {
-            self.ram[start + i] = byte;
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomError> {
+        self.load_rom_at(data, PROGRAM_START)
+    }
+
+    /// Loads a ROM into memory starting at the given address (e.g. 0x600 for ETI-660).
+    /// Fails without touching memory if the ROM would run past the end of RAM.
+    pub fn load_rom_at(&mut self, data: &[u8], load_address: u16) -> Result<(), RomError> {
+        let start = load_address as usize;
+        let max_size = self.ram.len().saturating_sub(start);
+        if data.len() > max_size {
+            return Err(RomError::TooLarge { size: data.len(), max_size, load_address });
+        }
+        self.ram[start..start + data.len()].copy_from_slice(data);
+        self.decoded.clear();
+        Ok(())
+    }
+
+    /// The opcode and instruction the CPU cached for `addr`, if still valid
+    pub(crate) fn decoded(&self, addr: u16) -> Option<(u16, Instruction)> {
+        self.decoded.get(addr as usize).copied().flatten()
+    }
+
+    /// Caches the decoding of the opcode at `addr` until either of its bytes is written
+    /// Opcodes that straddle the end of RAM are not cached, since their second byte depends on the policy.
+    pub(crate) fn cache_decoded(&mut self, addr: u16, opcode: u16, instruction: Instruction) {
+        let index = addr as usize;
+        if index + 1 >= self.ram.len() {
+            return;
+        }
+        if self.decoded.is_empty() {
+            self.decoded = vec![None; self.ram.len()];
+        }
+        self.decoded[index] = Some((opcode, instruction));
+    }
+
+    /// Writes the address policy and all of RAM
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.policy(self.policy);
+        writer.u32(self.ram.len() as u32);
+        writer.bytes(&self.ram);
+    }
+
+    /// Reads memory written by `save_state`; RAM must be the variant's size
+    pub(crate) fn load_state(reader: &mut StateReader, variant: Variant) -> Result<Self, SaveStateError> {
+        let policy = reader.policy()?;
+        let size = reader.u32()? as usize;
+        if size != variant.memory_size() {
+            return Err(SaveStateError::Corrupt("memory size"));
         }
+        Ok(Memory { ram: reader.bytes(size)?.to_vec(), policy, write_log: None, decoded: Vec::new() })
     }
 }
 
@@ -106,17 +350,117 @@ fn test_font_data_loaded() {
     assert_eq!(mem.read(0x005), 0x20);
 }
 
+    #[test]
+    fn test_big_font_data_loaded() {
+        let mem = Memory::new();
+        // Big "0" starts at 0x050, big "1" 10 bytes later
+        assert_eq!(mem.read(BIG_FONT_ADDR), 0x3C);
+        assert_eq!(mem.read(BIG_FONT_ADDR + 10), 0x18);
+        // Big font must not overlap the program area
+        assert!(BIG_FONT_ADDR as usize + BIG_FONT_SET.len() <= 0x200);
+    }
+
     #[test]
     fn test_load_rom() {
         let mut mem = Memory::new();
         let rom_data = [0xDE, 0xAD, 0xBE, 0xEF];
-        mem.load_rom(&rom_data);
+        mem.load_rom(&rom_data).unwrap();
         assert_eq!(mem.read(0x200), 0xDE);
         assert_eq!(mem.read(0x201), 0xAD);
         assert_eq!(mem.read(0x202), 0xBE);
         assert_eq!(mem.read(0x203), 0xEF);
     }
 
+    #[test]
+    fn test_memory_for_variant_xo_chip() {
+        let mut mem = Memory::for_variant(Variant::XoChip);
+        assert_eq!(mem.size(), 0x10000);
+        mem.write(0xFFFF, 0x42);
+        assert_eq!(mem.read(0xFFFF), 0x42);
+        // Fonts are loaded regardless of size
+        assert_eq!(mem.read(0x000), 0xF0);
+    }
+
+    #[test]
+    fn test_policy_wrap() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.policy(), AddressPolicy::Wrap);
+        mem.write(0x1002, 0x42);
+        assert_eq!(mem.read(0x002), 0x42);
+        assert_eq!(mem.read(0x1002), 0x42);
+    }
+
+    #[test]
+    fn test_policy_mask_xo_chip() {
+        // Mask keeps 12 bits even when RAM is larger
+        let mut mem = Memory::for_variant(Variant::XoChip);
+        mem.set_policy(AddressPolicy::Mask);
+        mem.write(0x1234, 0x42);
+        assert_eq!(mem.read(0x1234), 0x42);
+        assert_eq!(mem.read(0x0234), 0x00); // In range, so not masked
+    }
+
+    #[test]
+    fn test_policy_strict() {
+        let mut mem = Memory::new();
+        mem.set_policy(AddressPolicy::Strict);
+        assert_eq!(mem.try_read(0x1000), Err(MemoryError { addr: 0x1000 }));
+        assert_eq!(mem.try_write(0xFFFF, 1), Err(MemoryError { addr: 0xFFFF }));
+        assert_eq!(mem.try_read(0xFFF), Ok(0));
+    }
+
+    #[test]
+    fn test_policy_open_bus() {
+        let mut mem = Memory::new();
+        mem.set_policy(AddressPolicy::OpenBus);
+        mem.write(0x1000, 0x42);
+        assert_eq!(mem.read(0x1000), 0);
+        assert_eq!(mem.read(0x000), 0xF0); // Wrapped location untouched
+    }
+
+    #[test]
+    fn test_policy_from_name() {
+        for name in POLICY_NAMES.iter() {
+            assert!(AddressPolicy::from_name(name).is_some());
+        }
+        assert_eq!(AddressPolicy::from_name("open-bus"), Some(AddressPolicy::OpenBus));
+    }
+
+    #[test]
+    fn test_load_rom_at_eti660_address() {
+        let mut mem = Memory::new();
+        mem.load_rom_at(&[0x12, 0x34], ETI660_PROGRAM_START).unwrap();
+        assert_eq!(mem.read(0x600), 0x12);
+        assert_eq!(mem.read(0x601), 0x34);
+        assert_eq!(mem.read(0x200), 0x00);
+    }
+
+    #[test]
+    fn test_load_rom_largest_fit() {
+        let mut mem = Memory::new();
+        let rom = vec![0xAB; 3584]; // 0x200-0xFFF exactly
+        assert_eq!(mem.load_rom(&rom), Ok(()));
+        assert_eq!(mem.read(0xFFF), 0xAB);
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut mem = Memory::new();
+        let rom = vec![0xAB; 3585];
+        let error = mem.load_rom(&rom).unwrap_err();
+        assert_eq!(error, RomError::TooLarge { size: 3585, max_size: 3584, load_address: 0x200 });
+        assert!(error.to_string().starts_with("ROM too large for variant"));
+        // Nothing was written
+        assert_eq!(mem.read(0x200), 0x00);
+    }
+
+    #[test]
+    fn test_load_rom_xo_chip_allows_large_roms() {
+        let mut mem = Memory::for_variant(Variant::XoChip);
+        let rom = vec![0xAB; 0x8000];
+        assert_eq!(mem.load_rom(&rom), Ok(()));
+    }
+
     #[test]
     fn test_memory_default() {
         // Test that Default::default() works the same as new()
@@ -126,4 +470,34 @@ fn test_font_data_loaded() {
         // Program area should be zeroed
         assert_eq!(mem.read(0x200), 0);
     }
+
+    #[test]
+    fn test_write_tracking() {
+        let mut memory = Memory::new();
+        memory.write(0x300, 1);
+        assert!(memory.take_writes().is_empty());
+
+        memory.set_write_tracking(true);
+        memory.write(0x300, 1);
+        memory.write(0x1301, 2); // Wraps to 0x301
+        assert_eq!(memory.take_writes(), vec![0x300, 0x301]);
+        assert!(memory.take_writes().is_empty());
+    }
+
+    #[test]
+    fn test_decoded_cache_invalidation() {
+        let mut memory = Memory::new();
+        memory.cache_decoded(0x300, 0x00E0, Instruction::Clear);
+        memory.cache_decoded(0x302, 0x00E0, Instruction::Clear);
+        memory.cache_decoded(0xFFF, 0x00E0, Instruction::Clear); // Straddles the end of RAM
+        assert_eq!(memory.decoded(0x300), Some((0x00E0, Instruction::Clear)));
+        assert_eq!(memory.decoded(0xFFF), None);
+
+        memory.write(0x301, 0xEE); // Second byte of the opcode at 0x300
+        assert_eq!(memory.decoded(0x300), None);
+        assert!(memory.decoded(0x302).is_some());
+
+        memory.load_rom(&[0x00, 0xE0]).unwrap();
+        assert_eq!(memory.decoded(0x302), None);
+    }
 }
@@ -0,0 +1,302 @@
+//! Movie module for CHIP-8
+//!
+//! A movie records a session from power-on so it can be replayed exactly:
+//! the machine configuration (including the RND seed), the ROM's SHA-1, and
+//! the keypad state and cycle budget of every frame that ran. Replaying the
+//! frames on a machine built from the same config and ROM (see
+//! `headless::replay`) reproduces the run bit for bit, so a bug report
+//! becomes a regression test.
+//!
+//! ```text
+//! "C8MV"  magic
+//! u16     format version (little-endian, like every multi-byte field)
+//! u8      variant, quirks, u8 address policy, u16 load address,
+//! u32     cycles per frame, u8 generator, u64 seed, u32 x4 palette
+//! [u8;20] ROM SHA-1
+//! u8      1 if a final state hash follows, then [u8;20] SHA-1 of the final save state
+//! u32     frame count, then per frame: u8 flags (bit 0 = reset first), u16 keys, u32 cycles
+//! ```
+
+use crate::machine::{Machine, MachineConfig};
+use crate::rng::RngKind;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::sha1::sha1;
+use std::fmt;
+
+/// Identifies a movie file
+pub const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+/// Current format version; movies from newer versions are rejected
+pub const MOVIE_VERSION: u16 = 1;
+
+const FLAG_RESET: u8 = 1;
+
+/// Why a movie could not be loaded or played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start with `MOVIE_MAGIC`
+    BadMagic,
+    /// The movie was written by a format version this build cannot read
+    UnsupportedVersion(u16),
+    /// The data ended before the movie was complete
+    Truncated,
+    /// A field holds a value no valid movie can contain
+    Corrupt(&'static str),
+    /// The ROM's SHA-1 differs from the one recorded
+    RomMismatch,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "Not a CHIP-8 movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported movie version {} (this build reads up to {})", version, MOVIE_VERSION)
+            }
+            MovieError::Truncated => write!(f, "Movie is truncated"),
+            MovieError::Corrupt(field) => write!(f, "Movie has an invalid {}", field),
+            MovieError::RomMismatch => write!(f, "Movie was recorded with a different ROM"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        match error {
+            SaveStateError::BadMagic => MovieError::BadMagic,
+            SaveStateError::UnsupportedVersion(version) => MovieError::UnsupportedVersion(version),
+            SaveStateError::Truncated => MovieError::Truncated,
+            SaveStateError::Corrupt(field) => MovieError::Corrupt(field),
+            SaveStateError::VariantMismatch { .. } => MovieError::Corrupt("variant"),
+        }
+    }
+}
+
+/// Input for one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// Pressed keys, bit n = key n
+    pub keys: u16,
+    /// Instruction budget the frame ran with
+    pub cycles_per_frame: u32,
+    /// The machine was reset before this frame
+    pub reset: bool,
+}
+
+impl MovieFrame {
+    /// Puts the machine in the state the frame was recorded in (reset, speed, keys)
+    pub fn apply(&self, machine: &mut Machine) {
+        if self.reset {
+            machine.reset();
+        }
+        machine.set_cycles_per_frame(self.cycles_per_frame);
+        machine.keyboard_mut().set_mask(self.keys);
+    }
+}
+
+/// A recorded session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// The configuration the machine was built with; `seed` is always set
+    pub config: MachineConfig,
+    pub rom_sha1: [u8; 20],
+    pub frames: Vec<MovieFrame>,
+    /// SHA-1 of the machine's save state after the last frame, if recorded
+    pub final_state: Option<[u8; 20]>,
+}
+
+impl Movie {
+    /// Starts recording a machine built from `config` running `rom`
+    /// A config without a seed gets a random one, which the caller must build the machine with.
+    pub fn new(config: MachineConfig, rom: &[u8]) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        Movie {
+            config: MachineConfig { seed: Some(seed), ..config },
+            rom_sha1: sha1(rom),
+            frames: Vec::new(),
+            final_state: None,
+        }
+    }
+
+    /// Records the input for the frame about to run (call after updating the keypad)
+    pub fn record_frame(&mut self, machine: &Machine, reset: bool) {
+        self.frames.push(MovieFrame {
+            keys: machine.keyboard().mask(),
+            cycles_per_frame: machine.config().cycles_per_frame,
+            reset,
+        });
+    }
+
+    /// Records the hash of the final state so replays can verify themselves
+    pub fn finish(&mut self, machine: &Machine) {
+        self.final_state = Some(sha1(&machine.save_state()));
+    }
+
+    /// Checks that `rom` is the ROM the movie was recorded with
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), MovieError> {
+        if sha1(rom) == self.rom_sha1 {
+            Ok(())
+        } else {
+            Err(MovieError::RomMismatch)
+        }
+    }
+
+    /// Returns whether the machine ended in the recorded final state (None if none was recorded)
+    pub fn matches_final_state(&self, machine: &Machine) -> Option<bool> {
+        self.final_state.map(|hash| hash == sha1(&machine.save_state()))
+    }
+
+    /// Builds a fresh machine for replay and loads `rom` into it
+    pub fn machine(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        self.check_rom(rom)?;
+        let mut machine = Machine::new(self.config);
+        machine.load_rom(rom).map_err(|_| MovieError::Corrupt("load address"))?;
+        Ok(machine)
+    }
+
+    /// Encodes the movie in the binary format described in the module docs
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = &self.config;
+        let mut writer = StateWriter::with_header(MOVIE_MAGIC, MOVIE_VERSION);
+        writer.variant(config.variant);
+        writer.quirks(&config.quirks);
+        writer.policy(config.address_policy);
+        writer.u16(config.load_address);
+        writer.u32(config.cycles_per_frame);
+        writer.u8(match config.rng {
+            RngKind::Std => 0,
+            RngKind::Vip => 1,
+        });
+        writer.u64(config.seed.unwrap_or(0));
+        for &colour in config.palette.iter() {
+            writer.u32(colour);
+        }
+        writer.bytes(&self.rom_sha1);
+        writer.bool(self.final_state.is_some());
+        if let Some(hash) = &self.final_state {
+            writer.bytes(hash);
+        }
+        writer.u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            writer.u8(if frame.reset { FLAG_RESET } else { 0 });
+            writer.u16(frame.keys);
+            writer.u32(frame.cycles_per_frame);
+        }
+        writer.finish()
+    }
+
+    /// Decodes a movie written by `to_bytes`
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::with_header(data, MOVIE_MAGIC, MOVIE_VERSION)?;
+        let config = MachineConfig {
+            variant: reader.variant()?,
+            quirks: reader.quirks()?,
+            address_policy: reader.policy()?,
+            load_address: reader.u16()?,
+            cycles_per_frame: reader.u32()?,
+            rng: match reader.u8()? {
+                0 => RngKind::Std,
+                1 => RngKind::Vip,
+                _ => return Err(MovieError::Corrupt("generator")),
+            },
+            seed: Some(reader.u64()?),
+            palette: [reader.u32()?, reader.u32()?, reader.u32()?, reader.u32()?],
+        };
+        let rom_sha1 = reader.array()?;
+        let final_state = if reader.bool()? { Some(reader.array()?) } else { None };
+
+        let count = reader.u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let flags = reader.u8()?;
+            if flags & !FLAG_RESET != 0 {
+                return Err(MovieError::Corrupt("frame flags"));
+            }
+            frames.push(MovieFrame { reset: flags & FLAG_RESET != 0, keys: reader.u16()?, cycles_per_frame: reader.u32()? });
+        }
+        reader.finish()?;
+        Ok(Movie { config, rom_sha1, frames, final_state })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless::{self, StopReason};
+
+    // 0x200: RND V0, 0xFF / 0x202: SKP V1 / 0x204: JP 0x200 / 0x206: ADD V2, 1 / 0x208: JP 0x200
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00];
+
+    fn record() -> (Movie, Machine) {
+        let mut movie = Movie::new(MachineConfig::default(), &ROM);
+        let mut machine = Machine::new(movie.config);
+        machine.load_rom(&ROM).unwrap();
+        for frame in 0..20u16 {
+            // Reset once, and hold key 0 (V1 = 0) on every third frame
+            let reset = frame == 10;
+            if reset {
+                machine.reset();
+            }
+            machine.keyboard_mut().set_key(0, frame % 3 == 0);
+            movie.record_frame(&machine, reset);
+            machine.run_frame().unwrap();
+        }
+        movie.finish(&machine);
+        (movie, machine)
+    }
+
+    #[test]
+    fn test_movie_records_a_seed() {
+        let movie = Movie::new(MachineConfig::default(), &ROM);
+        assert!(movie.config.seed.is_some());
+        assert_eq!(Movie::new(MachineConfig { seed: Some(9), ..MachineConfig::default() }, &ROM).config.seed, Some(9));
+    }
+
+    #[test]
+    fn test_movie_replay_is_bit_identical() {
+        let (movie, recorded) = record();
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.frames.len(), 20);
+        assert!(movie.frames[10].reset);
+
+        let mut machine = movie.machine(&ROM).unwrap();
+        assert_eq!(headless::replay(&mut machine, &movie), (20, StopReason::MovieEnd));
+        assert_eq!(machine.save_state(), recorded.save_state());
+        assert_eq!(movie.matches_final_state(&machine), Some(true));
+    }
+
+    #[test]
+    fn test_movie_replays_the_palette() {
+        // The palette is part of the save state, so a replay must build the machine with it
+        let config = MachineConfig { palette: [0x102030, 0xF0E0D0, 0x808080, 0x404040], ..MachineConfig::default() };
+        let mut movie = Movie::new(config, &ROM);
+        let mut machine = Machine::new(movie.config);
+        machine.load_rom(&ROM).unwrap();
+        for reset in [false, true, false] {
+            if reset {
+                machine.reset();
+            }
+            movie.record_frame(&machine, reset);
+            machine.run_frame().unwrap();
+        }
+        movie.finish(&machine);
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.config.palette, config.palette);
+        let mut replayed = movie.machine(&ROM).unwrap();
+        headless::replay(&mut replayed, &movie);
+        assert_eq!(movie.matches_final_state(&replayed), Some(true));
+    }
+
+    #[test]
+    fn test_movie_rejects_bad_input() {
+        let (movie, _) = record();
+        assert_eq!(movie.machine(&[0x00, 0xE0]).err(), Some(MovieError::RomMismatch));
+
+        let data = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&data[..data.len() - 1]), Err(MovieError::Truncated));
+        assert_eq!(Movie::from_bytes(b"C8ST\x01\x00"), Err(MovieError::BadMagic));
+        assert_eq!(Movie::from_bytes(b"C8MV\x02\x00"), Err(MovieError::UnsupportedVersion(2)));
+    }
+}
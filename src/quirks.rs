@@ -0,0 +1,160 @@
+//! Quirks module for CHIP-8
+//!
+//! Different CHIP-8 interpreters disagree on the behavior of a handful of
+//! instructions. A `Quirks` value describes which behavior the CPU and
+//! display should use, and the named presets match the common platforms
+//! (see the Timendus quirks test for the reference table).
+
+/// How Fx55/Fx65 leave the I register after storing/loading registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryQuirk {
+    /// I = I + x + 1 (COSMAC VIP)
+    IncrementXPlusOne,
+    /// I = I + x (CHIP-48, SCHIP 1.0)
+    IncrementX,
+    /// I is left unchanged (SCHIP 1.1 and modern interpreters)
+    Unchanged,
+}
+
+/// Behavior switches for instructions that differ between interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy1/8xy2/8xy3 reset VF to 0 after the logic operation
+    pub vf_reset: bool,
+    /// 8xy6/8xyE shift Vx in place instead of copying Vy into Vx first
+    pub shift_vx: bool,
+    /// Effect of Fx55/Fx65 on the I register
+    pub memory: MemoryQuirk,
+    /// Bnnn is read as Bxnn and jumps to nnn + Vx instead of nnn + V0
+    pub jump_vx: bool,
+    /// Dxyn waits for the next vertical blank before drawing
+    pub display_wait: bool,
+    /// Sprites wrap around the screen edges instead of clipping
+    pub wrap_sprites: bool,
+}
+
+/// Names accepted by `Quirks::from_name`, in the order they are listed to users
+pub const PRESET_NAMES: [&str; 5] = ["vip", "chip48", "schip-legacy", "schip-modern", "xo-chip"];
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter (1977)
+    pub fn vip() -> Self {
+        Quirks {
+            vf_reset: true,
+            shift_vx: false,
+            memory: MemoryQuirk::IncrementXPlusOne,
+            jump_vx: false,
+            display_wait: true,
+            wrap_sprites: false,
+        }
+    }
+
+    /// CHIP-48 on the HP48 calculators (1990)
+    pub fn chip48() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vx: true,
+            memory: MemoryQuirk::IncrementX,
+            jump_vx: true,
+            display_wait: false,
+            wrap_sprites: false,
+        }
+    }
+
+    /// Super-CHIP 1.1 as it behaved on the HP48 (draws wait for the display interrupt)
+    pub fn schip_legacy() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vx: true,
+            memory: MemoryQuirk::Unchanged,
+            jump_vx: true,
+            display_wait: true,
+            wrap_sprites: false,
+        }
+    }
+
+    /// Super-CHIP as implemented by modern interpreters (Octo, Cadmium)
+    pub fn schip_modern() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vx: true,
+            memory: MemoryQuirk::Unchanged,
+            jump_vx: true,
+            display_wait: false,
+            wrap_sprites: false,
+        }
+    }
+
+    /// XO-CHIP as defined by Octo (2014)
+    pub fn xo_chip() -> Self {
+        Quirks {
+            vf_reset: false,
+            shift_vx: false,
+            memory: MemoryQuirk::IncrementXPlusOne,
+            jump_vx: false,
+            display_wait: false,
+            wrap_sprites: true,
+        }
+    }
+
+    /// Looks up a preset by name (see `PRESET_NAMES`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vip" => Some(Self::vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip-legacy" => Some(Self::schip_legacy()),
+            "schip-modern" => Some(Self::schip_modern()),
+            "xo-chip" => Some(Self::xo_chip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::vip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirks_default_is_vip() {
+        assert_eq!(Quirks::default(), Quirks::vip());
+    }
+
+    #[test]
+    fn test_quirks_from_name_all_presets() {
+        for name in PRESET_NAMES.iter() {
+            assert!(Quirks::from_name(name).is_some(), "Preset '{}' should exist", name);
+        }
+    }
+
+    #[test]
+    fn test_quirks_from_name_unknown() {
+        assert_eq!(Quirks::from_name("chip-9000"), None);
+    }
+
+    #[test]
+    fn test_quirks_vip_values() {
+        let quirks = Quirks::vip();
+        assert!(quirks.vf_reset);
+        assert!(!quirks.shift_vx);
+        assert_eq!(quirks.memory, MemoryQuirk::IncrementXPlusOne);
+        assert!(!quirks.jump_vx);
+        assert!(quirks.display_wait);
+        assert!(!quirks.wrap_sprites);
+    }
+
+    #[test]
+    fn test_quirks_chip48_values() {
+        let quirks = Quirks::from_name("chip48").unwrap();
+        assert!(!quirks.vf_reset);
+        assert!(quirks.shift_vx);
+        assert_eq!(quirks.memory, MemoryQuirk::IncrementX);
+        assert!(quirks.jump_vx);
+        assert!(!quirks.display_wait);
+    }
+}
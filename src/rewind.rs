@@ -0,0 +1,220 @@
+//! Rewind module for CHIP-8
+//!
+//! Keeps the last few seconds of a running machine so the user can step
+//! back frame by frame. Each frame is captured as a save state, but only the
+//! newest state is kept whole: older frames are stored as the run-length
+//! encoded XOR difference to the frame after them. Between two frames only a
+//! few bytes of RAM and pixels change, so most entries are a handful of bytes.
+
+use crate::machine::Machine;
+use std::collections::VecDeque;
+
+/// Default rewind depth in seconds
+pub const DEFAULT_REWIND_SECONDS: usize = 30;
+/// Frames captured per second of rewind depth
+pub const FRAMES_PER_SECOND: usize = 60;
+
+/// Ring buffer of per-frame machine snapshots
+pub struct RewindBuffer {
+    /// Maximum number of frames that can be stepped back
+    capacity: usize,
+    /// The most recently captured state, uncompressed
+    latest: Option<Vec<u8>>,
+    /// Deltas from oldest to newest; applying the newest to `latest` gives the frame before it
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer that can step back up to `capacity` frames
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer { capacity, latest: None, deltas: VecDeque::new() }
+    }
+
+    /// Creates a buffer holding `seconds` of 60 fps frames
+    pub fn with_seconds(seconds: usize) -> Self {
+        Self::new(seconds * FRAMES_PER_SECOND)
+    }
+
+    /// Number of frames that can currently be stepped back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Returns true if there is nothing to rewind to
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Maximum number of frames that can be stepped back
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Approximate bytes held by the snapshots
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    /// Forgets every captured frame (e.g. after a reset)
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Captures the machine's current frame
+    pub fn push(&mut self, machine: &Machine) {
+        if self.capacity == 0 {
+            return;
+        }
+        let state = machine.save_state();
+        match self.latest.take() {
+            Some(previous) if previous.len() == state.len() => {
+                if self.deltas.len() == self.capacity {
+                    self.deltas.pop_front();
+                }
+                self.deltas.push_back(encode_delta(&state, &previous));
+            }
+            // First frame, or the state layout changed: start over
+            _ => self.deltas.clear(),
+        }
+        self.latest = Some(state);
+    }
+
+    /// Restores the frame before the newest captured one
+    /// Returns false (leaving the machine untouched) if there is nothing to rewind to.
+    pub fn rewind(&mut self, machine: &mut Machine) -> bool {
+        let (Some(latest), Some(delta)) = (self.latest.as_mut(), self.deltas.back()) else {
+            return false;
+        };
+        let mut previous = latest.clone();
+        apply_delta(&mut previous, delta);
+        if machine.load_state(&previous).is_err() {
+            self.clear();
+            return false;
+        }
+        self.deltas.pop_back();
+        *latest = previous;
+        true
+    }
+}
+
+/// Encodes `new XOR old` as pairs of (unchanged run, changed run + bytes)
+/// Run lengths are LEB128 varints.
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < new.len() {
+        let same = new[pos..].iter().zip(&old[pos..]).take_while(|(a, b)| a == b).count();
+        pos += same;
+        let changed = new[pos..].iter().zip(&old[pos..]).take_while(|(a, b)| a != b).count();
+        write_varint(&mut out, same);
+        write_varint(&mut out, changed);
+        out.extend(new[pos..pos + changed].iter().zip(&old[pos..]).map(|(a, b)| a ^ b));
+        pos += changed;
+    }
+    out
+}
+
+/// XORs a delta from `encode_delta` into `state`
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut input = delta;
+    while !input.is_empty() {
+        pos += read_varint(&mut input);
+        let changed = read_varint(&mut input);
+        for (byte, diff) in state[pos..pos + changed].iter_mut().zip(&input[..changed]) {
+            *byte ^= diff;
+        }
+        input = &input[changed..];
+        pos += changed;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = input.split_first() {
+        *input = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counting_machine() -> Machine {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // ADD V0, 1 / JP 0x200
+        machine
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let old = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let new = vec![1, 9, 3, 4, 5, 0, 0, 8];
+        let delta = encode_delta(&new, &old);
+        let mut state = new.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, old);
+    }
+
+    #[test]
+    fn test_rewind_steps_back_one_frame() {
+        let mut machine = counting_machine();
+        let mut rewind = RewindBuffer::new(10);
+        rewind.push(&machine);
+        let mut history = vec![machine.save_state()];
+        for _ in 0..5 {
+            machine.run_frame().unwrap();
+            rewind.push(&machine);
+            history.push(machine.save_state());
+        }
+        assert_eq!(rewind.len(), 5);
+
+        history.pop();
+        while let Some(expected) = history.pop() {
+            assert!(rewind.rewind(&mut machine));
+            assert_eq!(machine.save_state(), expected);
+        }
+        assert!(!rewind.rewind(&mut machine));
+    }
+
+    #[test]
+    fn test_rewind_capacity_drops_oldest() {
+        let mut machine = counting_machine();
+        let mut rewind = RewindBuffer::new(3);
+        for _ in 0..10 {
+            machine.run_frame().unwrap();
+            rewind.push(&machine);
+        }
+        assert_eq!(rewind.len(), 3);
+        while rewind.rewind(&mut machine) {}
+        assert_eq!(machine.cpu().v[0], (7 * 100 % 256) as u8); // 100 ADDs per 200-cycle frame
+    }
+
+    #[test]
+    fn test_rewind_deltas_are_small() {
+        let mut machine = counting_machine();
+        let mut rewind = RewindBuffer::with_seconds(1);
+        for _ in 0..FRAMES_PER_SECOND {
+            machine.run_frame().unwrap();
+            rewind.push(&machine);
+        }
+        let full = machine.save_state().len();
+        assert!(rewind.memory_usage() < full * 2, "{} bytes for {} frames", rewind.memory_usage(), rewind.len());
+    }
+}
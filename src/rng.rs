@@ -0,0 +1,185 @@
+//! RNG module for CHIP-8
+//!
+//! Random number sources for the `Cxkk` (RND) instruction. The CPU owns a
+//! boxed `RandomSource`, so runs can be made reproducible with a seed or
+//! driven by a custom generator in tests.
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Names accepted by `RngKind::from_name`
+pub const RNG_NAMES: [&str; 2] = ["std", "vip"];
+
+/// Produces the bytes `Cxkk` masks with kk
+pub trait RandomSource: Send {
+    /// Returns the next random byte
+    fn next_byte(&mut self) -> u8;
+
+    /// Called once per 60 Hz timer tick, for generators the VIP's interrupt advances
+    fn tick(&mut self) {}
+}
+
+/// `rand`'s standard generator, seeded for reproducible runs or from OS entropy
+pub struct SeededRandom {
+    rng: StdRng,
+}
+
+impl SeededRandom {
+    /// A generator that always produces the same sequence for the same seed
+    pub fn new(seed: u64) -> Self {
+        SeededRandom { rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// A generator seeded from the operating system (different every run)
+    pub fn from_entropy() -> Self {
+        SeededRandom { rng: StdRng::from_entropy() }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.rng.next_u32() as u8
+    }
+}
+
+/// The COSMAC VIP interpreter's page at 0x0100, which RND indexes as its table
+/// (from Laurence Scotford's disassembly of the interpreter)
+pub const VIP_INTERPRETER_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC,
+    0x22, 0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A,
+    0xF4, 0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA,
+    0x0A, 0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A,
+    0x0E, 0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F,
+    0x56, 0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA,
+    0x0F, 0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88,
+    0xD4, 0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88,
+    0xD4, 0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2,
+    0xFC, 0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A,
+    0xC4, 0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2,
+    0x56, 0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE,
+    0xF4, 0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F,
+    0xBA, 0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00, 0x4B,
+];
+
+/// The COSMAC VIP interpreter's generator
+///
+/// The VIP keeps its state in register R9, which its 60 Hz interrupt also
+/// increments. Each RND increments R9, adds the interpreter byte its low byte
+/// indexes to its high byte, then adds the 9-bit sum shifted right by one to
+/// the sum's low byte. The result is both the random byte and R9's new high
+/// byte.
+pub struct VipRandom {
+    r9: u16,
+    page: [u8; 256],
+}
+
+impl VipRandom {
+    /// Starts from R9 = `seed`, indexing the VIP interpreter's page
+    pub fn new(seed: u16) -> Self {
+        Self::with_page(seed, VIP_INTERPRETER_PAGE)
+    }
+
+    /// Starts from R9 = `seed`, indexing the given 256-byte interpreter page
+    pub fn with_page(seed: u16, page: [u8; 256]) -> Self {
+        VipRandom { r9: seed, page }
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let sum = (self.r9 >> 8) + self.page[(self.r9 & 0xFF) as usize] as u16;
+        // SHRC shifts the carry of the addition into bit 7
+        let high = ((sum >> 1) as u8).wrapping_add(sum as u8);
+        self.r9 = ((high as u16) << 8) | (self.r9 & 0xFF);
+        high
+    }
+
+    fn tick(&mut self) {
+        self.r9 = self.r9.wrapping_add(1);
+    }
+}
+
+/// Which generator a machine builds for `Cxkk`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RngKind {
+    /// `SeededRandom`
+    #[default]
+    Std,
+    /// `VipRandom`
+    Vip,
+}
+
+impl RngKind {
+    /// Looks up a generator by name (see `RNG_NAMES`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "std" => Some(RngKind::Std),
+            "vip" => Some(RngKind::Vip),
+            _ => None,
+        }
+    }
+
+    /// Creates the generator; without a seed it starts from OS entropy
+    pub fn build(self, seed: Option<u64>) -> Box<dyn RandomSource> {
+        match (self, seed) {
+            (RngKind::Std, Some(seed)) => Box::new(SeededRandom::new(seed)),
+            (RngKind::Std, None) => Box::new(SeededRandom::from_entropy()),
+            (RngKind::Vip, Some(seed)) => Box::new(VipRandom::new(seed as u16)),
+            (RngKind::Vip, None) => Box::new(VipRandom::new(rand::random())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(source: &mut dyn RandomSource, count: usize) -> Vec<u8> {
+        (0..count).map(|_| source.next_byte()).collect()
+    }
+
+    #[test]
+    fn test_seeded_random_is_reproducible() {
+        let first = bytes(&mut SeededRandom::new(42), 32);
+        assert_eq!(first, bytes(&mut SeededRandom::new(42), 32));
+        assert_ne!(first, bytes(&mut SeededRandom::new(43), 32));
+    }
+
+    #[test]
+    fn test_vip_random_algorithm() {
+        let mut page = [0; 256];
+        page[1] = 0x10;
+        page[2] = 0x05;
+        page[3] = 0xF0;
+        let mut vip = VipRandom::with_page(0x2000, page);
+        // sum = high + page[low]; result = sum + (sum >> 1), the carry shifted into bit 7
+        assert_eq!(bytes(&mut vip, 3), vec![0x48, 0x73, 0x14]);
+        assert_eq!(vip.r9, 0x1403);
+        vip.tick();
+        assert_eq!(vip.r9, 0x1404);
+    }
+
+    #[test]
+    fn test_vip_random_matches_the_interpreter() {
+        // R9 = 0 after reset, with no interrupts between the RNDs
+        let mut vip = VipRandom::new(0);
+        assert_eq!(bytes(&mut vip, 8), vec![0x00, 0x00, 0x00, 0x00, 0x67, 0x8F, 0xBA, 0x98]);
+        // The table is the interpreter's RND routine itself, at 0x01D9
+        assert_eq!(VIP_INTERPRETER_PAGE[0xD9..0xEB], [
+            0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE, 0xF4, 0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4,
+        ]);
+    }
+
+    #[test]
+    fn test_rng_kind_from_name() {
+        assert_eq!(RngKind::from_name("VIP"), Some(RngKind::Vip));
+        assert_eq!(RngKind::from_name("std"), Some(RngKind::Std));
+        assert_eq!(RngKind::from_name("xorshift"), None);
+        let mut a = RngKind::Vip.build(Some(7));
+        let mut b = RngKind::Vip.build(Some(7));
+        assert_eq!(bytes(a.as_mut(), 8), bytes(b.as_mut(), 8));
+    }
+}
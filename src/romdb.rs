@@ -0,0 +1,341 @@
+//! ROM database module for CHIP-8
+//!
+//! Looks ROMs up by SHA-1 to find their title, author, target platform and
+//! the settings they need to play correctly: instructions per frame, quirks,
+//! keymap and colours. The file format is `programs.json` from the community
+//! CHIP-8 database (github.com/chip-8/chip-8-database): an array of programs,
+//! each with a `roms` object keyed by the SHA-1 of the ROM file.
+//!
+//! A copy is bundled at `data/programs.json`; `RomDatabase::parse` reads
+//! another one at runtime.
+
+use crate::display::DEFAULT_PALETTE;
+use crate::json::{Json, JsonError};
+use crate::machine::MachineConfig;
+use crate::quirks::{MemoryQuirk, Quirks};
+use crate::sha1::{from_hex, sha1};
+use crate::variant::Variant;
+use std::collections::HashMap;
+
+/// The database compiled into the emulator
+const BUNDLED: &str = include_str!("../data/programs.json");
+
+/// Database platform ids this emulator can run, in the order they are listed to users
+pub const PLATFORM_IDS: [&str; 7] =
+    ["originalChip8", "hybridVIP", "modernChip8", "chip48", "superchip1", "superchip", "xochip"];
+
+/// What the database records about one ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// Database id of the platform the ROM is run as (see `PLATFORM_IDS`)
+    pub platform: String,
+    pub variant: Variant,
+    /// The platform's quirks with the ROM's own overrides applied
+    pub quirks: Quirks,
+    /// Recommended instructions per frame (the database's `tickrate`)
+    pub cycles_per_frame: Option<u32>,
+    /// Game actions (`up`, `a`, `player2Left`, ...) and the CHIP-8 key each one presses
+    pub keys: Vec<(String, u8)>,
+    /// Colours for pixel values 0-3, when the ROM asks for its own
+    pub palette: Option<[u32; 4]>,
+}
+
+impl RomInfo {
+    /// The authors joined for display, or "unknown author"
+    pub fn author(&self) -> String {
+        if self.authors.is_empty() {
+            "unknown author".to_string()
+        } else {
+            self.authors.join(", ")
+        }
+    }
+
+    /// Sets the variant, quirks and (when recommended) speed and palette of `config`
+    pub fn apply(&self, config: &mut MachineConfig) {
+        config.variant = self.variant;
+        config.quirks = self.quirks;
+        if let Some(cycles) = self.cycles_per_frame {
+            config.cycles_per_frame = cycles;
+        }
+        if let Some(palette) = self.palette {
+            config.palette = palette;
+        }
+    }
+}
+
+/// ROM settings by SHA-1 digest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomDatabase {
+    roms: HashMap<[u8; 20], RomInfo>,
+}
+
+impl RomDatabase {
+    /// Creates an empty database
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The database bundled with the emulator
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("the bundled ROM database is valid")
+    }
+
+    /// Parses a community `programs.json`
+    /// ROMs for platforms this emulator cannot run are skipped.
+    pub fn parse(text: &str) -> Result<Self, JsonError> {
+        let json = Json::parse(text)?;
+        // JSON values carry no line numbers, so errors past parsing point at line 1
+        let error = |message: String| JsonError { line: 1, message };
+        let programs = json.as_array().ok_or_else(|| error("Expected an array of programs".to_string()))?;
+        let mut database = RomDatabase::new();
+        for program in programs {
+            let title = program.get("title").and_then(Json::as_str).unwrap_or("Untitled");
+            let authors: Vec<String> = match program.get("authors").and_then(Json::as_array) {
+                Some(authors) => authors.iter().filter_map(Json::as_str).map(str::to_string).collect(),
+                None => Vec::new(),
+            };
+            let Some(roms) = program.get("roms").and_then(Json::as_object) else {
+                return Err(error(format!("Expected a roms object for '{}'", title)));
+            };
+            for (hash, rom) in roms {
+                let digest = from_hex(&hash.to_ascii_lowercase())
+                    .ok_or_else(|| error(format!("Invalid SHA-1 '{}' for '{}'", hash, title)))?;
+                let info = rom_info(title, &authors, rom).map_err(|message| error(format!("{} for '{}'", message, title)))?;
+                if let Some(info) = info {
+                    database.roms.insert(digest, info);
+                }
+            }
+        }
+        Ok(database)
+    }
+
+    /// Adds every ROM of `other`, replacing entries for the same ROM
+    pub fn extend(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    /// The entry for a ROM's SHA-1 digest
+    pub fn get(&self, digest: &[u8; 20]) -> Option<&RomInfo> {
+        self.roms.get(digest)
+    }
+
+    /// The entry for a ROM image
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1(rom))
+    }
+}
+
+/// Reads one entry of a program's `roms` object
+/// Returns None when none of the ROM's platforms can be run here.
+fn rom_info(title: &str, authors: &[String], rom: &Json) -> Result<Option<RomInfo>, String> {
+    let platforms = rom.get("platforms").and_then(Json::as_array).ok_or("Expected a platforms array")?;
+    let Some((platform, variant, mut quirks)) = platforms.iter().filter_map(Json::as_str).find_map(platform_settings) else {
+        return Ok(None);
+    };
+    if let Some(overrides) = rom.get("quirkyPlatforms").and_then(|quirky| quirky.get(platform)) {
+        apply_quirk_overrides(&mut quirks, overrides)?;
+    }
+
+    let cycles_per_frame = match rom.get("tickrate") {
+        Some(tickrate) => Some(
+            tickrate.as_u64().and_then(|rate| u32::try_from(rate).ok()).filter(|&rate| rate > 0).ok_or("Invalid tickrate")?,
+        ),
+        None => None,
+    };
+
+    let mut keys = Vec::new();
+    if let Some(members) = rom.get("keys").and_then(Json::as_object) {
+        for (action, key) in members {
+            match key.as_u64() {
+                Some(key @ 0..=0xF) => keys.push((action.clone(), key as u8)),
+                _ => return Err(format!("Invalid key for '{}'", action)),
+            }
+        }
+    }
+
+    let palette = match rom.get("colors").and_then(|colors| colors.get("pixels")).and_then(Json::as_array) {
+        Some(pixels) if pixels.len() > 4 => return Err("Expected at most 4 pixel colours".to_string()),
+        Some(pixels) => {
+            let mut palette = DEFAULT_PALETTE;
+            for (colour, value) in palette.iter_mut().zip(pixels) {
+                *colour = value.as_str().and_then(parse_colour).ok_or("Invalid pixel colour")?;
+            }
+            Some(palette)
+        }
+        None => None,
+    };
+
+    Ok(Some(RomInfo {
+        title: title.to_string(),
+        authors: authors.to_vec(),
+        platform: platform.to_string(),
+        variant,
+        quirks,
+        cycles_per_frame,
+        keys,
+        palette,
+    }))
+}
+
+/// The variant and quirks of a database platform id
+fn platform_settings(platform: &str) -> Option<(&str, Variant, Quirks)> {
+    let (variant, quirks) = match platform {
+        "originalChip8" | "hybridVIP" => (Variant::Chip8, Quirks::vip()),
+        "modernChip8" => (
+            Variant::Chip8,
+            Quirks { vf_reset: false, display_wait: false, ..Quirks::vip() },
+        ),
+        "chip48" => (Variant::Chip8, Quirks::chip48()),
+        "superchip1" => (Variant::SuperChip, Quirks::chip48()),
+        "superchip" => (Variant::SuperChip, Quirks::schip_modern()),
+        "xochip" => (Variant::XoChip, Quirks::xo_chip()),
+        _ => return None,
+    };
+    Some((platform, variant, quirks))
+}
+
+/// Applies a `quirkyPlatforms` entry: database quirk names to booleans
+fn apply_quirk_overrides(quirks: &mut Quirks, overrides: &Json) -> Result<(), String> {
+    let members = overrides.as_object().ok_or("Expected an object of quirks")?;
+    let mut increment_by_x = quirks.memory == MemoryQuirk::IncrementX;
+    let mut leave_i_unchanged = quirks.memory == MemoryQuirk::Unchanged;
+    for (name, value) in members {
+        let value = value.as_bool().ok_or_else(|| format!("Invalid value for quirk '{}'", name))?;
+        match name.as_str() {
+            "logic" => quirks.vf_reset = value,
+            "shift" => quirks.shift_vx = value,
+            "jump" => quirks.jump_vx = value,
+            "vblank" => quirks.display_wait = value,
+            "wrap" => quirks.wrap_sprites = value,
+            "memoryIncrementByX" => increment_by_x = value,
+            "memoryLeaveIUnchanged" => leave_i_unchanged = value,
+            _ => return Err(format!("Unknown quirk '{}'", name)),
+        }
+    }
+    quirks.memory = match (leave_i_unchanged, increment_by_x) {
+        (true, _) => MemoryQuirk::Unchanged,
+        (false, true) => MemoryQuirk::IncrementX,
+        (false, false) => MemoryQuirk::IncrementXPlusOne,
+    };
+    Ok(())
+}
+
+/// Parses a `#RRGGBB` colour
+fn parse_colour(text: &str) -> Option<u32> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha1::sha1_hex;
+
+    /// PONG as listed in roms/Pong_Commented.md
+    const PONG: [u8; 246] = [
+        0x6A, 0x02, 0x6B, 0x0C, 0x6C, 0x3F, 0x6D, 0x0C, 0xA2, 0xEA, 0xDA, 0xB6, 0xDC, 0xD6, 0x6E, 0x00,
+        0x22, 0xD4, 0x66, 0x03, 0x68, 0x02, 0x60, 0x60, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x1A,
+        0xC7, 0x17, 0x77, 0x08, 0x69, 0xFF, 0xA2, 0xF0, 0xD6, 0x71, 0xA2, 0xEA, 0xDA, 0xB6, 0xDC, 0xD6,
+        0x60, 0x01, 0xE0, 0xA1, 0x7B, 0xFE, 0x60, 0x04, 0xE0, 0xA1, 0x7B, 0x02, 0x60, 0x1F, 0x8B, 0x02,
+        0xDA, 0xB6, 0x8D, 0x70, 0xC0, 0x0A, 0x7D, 0xFE, 0x40, 0x00, 0x7D, 0x02, 0x60, 0x00, 0x60, 0x1F,
+        0x8D, 0x02, 0xDC, 0xD6, 0xA2, 0xF0, 0xD6, 0x71, 0x86, 0x84, 0x87, 0x94, 0x60, 0x3F, 0x86, 0x02,
+        0x61, 0x1F, 0x87, 0x12, 0x46, 0x02, 0x12, 0x78, 0x46, 0x3F, 0x12, 0x82, 0x47, 0x1F, 0x69, 0xFF,
+        0x47, 0x00, 0x69, 0x01, 0xD6, 0x71, 0x12, 0x2A, 0x68, 0x02, 0x63, 0x01, 0x80, 0x70, 0x80, 0xB5,
+        0x12, 0x8A, 0x68, 0xFE, 0x63, 0x0A, 0x80, 0x70, 0x80, 0xD5, 0x3F, 0x01, 0x12, 0xA2, 0x61, 0x02,
+        0x80, 0x15, 0x3F, 0x01, 0x12, 0xBA, 0x80, 0x15, 0x3F, 0x01, 0x12, 0xC8, 0x80, 0x15, 0x3F, 0x01,
+        0x12, 0xC2, 0x60, 0x20, 0xF0, 0x18, 0x22, 0xD4, 0x8E, 0x34, 0x22, 0xD4, 0x66, 0x3E, 0x33, 0x01,
+        0x66, 0x03, 0x68, 0xFE, 0x33, 0x01, 0x68, 0x02, 0x12, 0x16, 0x79, 0xFF, 0x49, 0xFE, 0x69, 0xFF,
+        0x12, 0xC8, 0x79, 0x01, 0x49, 0x02, 0x69, 0x01, 0x60, 0x04, 0xF0, 0x18, 0x76, 0x01, 0x46, 0x40,
+        0x76, 0xFE, 0x12, 0x6C, 0xA2, 0xF2, 0xFE, 0x33, 0xF2, 0x65, 0xF1, 0x29, 0x64, 0x14, 0x65, 0x00,
+        0xD4, 0x55, 0x74, 0x15, 0xF2, 0x29, 0xD4, 0x55, 0x00, 0xEE, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+        0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    fn database_for(rom: &[u8], entry: &str) -> RomDatabase {
+        let text = format!(r#"[{{"title": "Test", "authors": ["A", "B"], "roms": {{"{}": {}}}}}]"#, sha1_hex(rom), entry);
+        RomDatabase::parse(&text).unwrap()
+    }
+
+    #[test]
+    fn test_bundled_database_knows_pong() {
+        let database = RomDatabase::bundled();
+        assert_eq!(sha1_hex(&PONG), "607c4f7f4e4dce9f99d96b3182bfe7e88bb090ee");
+        let info = database.lookup(&PONG).unwrap();
+        assert_eq!(info.title, "Pong (1 player)");
+        assert_eq!(info.variant, Variant::Chip8);
+        assert_eq!(info.quirks, Quirks::vip());
+        assert_eq!(info.keys, vec![("up".to_string(), 1), ("down".to_string(), 4)]);
+    }
+
+    #[test]
+    fn test_lookup_by_rom_contents() {
+        let rom = [0x00, 0xE0, 0x12, 0x00];
+        let entry = r##"{"platforms": ["megachip8", "superchip"], "tickrate": 30,
+            "keys": {"up": 5, "a": 6}, "colors": {"pixels": ["#102030", "#ffffff"], "buzzer": "#990000"}}"##;
+        let database = database_for(&rom, entry);
+        assert_eq!(database.len(), 1);
+        assert!(database.lookup(&[0x00, 0xE0]).is_none());
+
+        let info = database.lookup(&rom).unwrap();
+        assert_eq!(info.title, "Test");
+        assert_eq!(info.author(), "A, B");
+        assert_eq!(info.platform, "superchip");
+        assert_eq!(info.variant, Variant::SuperChip);
+        assert_eq!(info.quirks, Quirks::schip_modern());
+        assert_eq!(info.keys, vec![("up".to_string(), 5), ("a".to_string(), 6)]);
+        assert_eq!(info.palette, Some([0x102030, 0xFFFFFF, DEFAULT_PALETTE[2], DEFAULT_PALETTE[3]]));
+
+        let mut config = MachineConfig::default();
+        info.apply(&mut config);
+        assert_eq!(config.variant, Variant::SuperChip);
+        assert_eq!(config.quirks, Quirks::schip_modern());
+        assert_eq!(config.cycles_per_frame, 30);
+        assert_eq!(config.palette, info.palette.unwrap());
+    }
+
+    #[test]
+    fn test_quirky_platform_overrides() {
+        let rom = [0x12, 0x00];
+        let entry = r#"{"platforms": ["originalChip8"],
+            "quirkyPlatforms": {"originalChip8": {"shift": true, "memoryLeaveIUnchanged": true, "vblank": false}}}"#;
+        let info = database_for(&rom, entry).lookup(&rom).cloned().unwrap();
+        let expected = Quirks { shift_vx: true, memory: MemoryQuirk::Unchanged, display_wait: false, ..Quirks::vip() };
+        assert_eq!(info.quirks, expected);
+        assert_eq!(info.cycles_per_frame, None);
+
+        // Only platforms this emulator runs are kept
+        assert!(database_for(&rom, r#"{"platforms": ["chip8x"]}"#).is_empty());
+        for id in PLATFORM_IDS {
+            assert!(platform_settings(id).is_some(), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_rejects_malformed_entries() {
+        assert!(RomDatabase::parse(r#"{"title": "Not an array"}"#).is_err());
+        assert!(RomDatabase::parse(r#"[{"title": "T", "roms": {"abc": {"platforms": []}}}]"#).is_err());
+        let hash = sha1_hex(&[]);
+        for entry in [
+            r#"{"platforms": ["xochip"], "tickrate": -1}"#,
+            r#"{"platforms": ["xochip"], "keys": {"up": 16}}"#,
+            r##"{"platforms": ["xochip"], "colors": {"pixels": ["#12345"]}}"##,
+            r#"{"platforms": ["xochip"], "quirkyPlatforms": {"xochip": {"teleport": true}}}"#,
+        ] {
+            let text = format!(r#"[{{"title": "T", "roms": {{"{}": {}}}}}]"#, hash, entry);
+            assert!(RomDatabase::parse(&text).is_err(), "{}", entry);
+        }
+    }
+}
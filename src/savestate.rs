@@ -0,0 +1,292 @@
+//! Save state module for CHIP-8
+//!
+//! A save state is a snapshot of the whole machine in a small binary format:
+//!
+//! ```text
+//! "C8ST"  magic
+//! u16     format version (little-endian, like every multi-byte field)
+//! u8      variant (0 = CHIP-8, 1 = Super-CHIP, 2 = XO-CHIP)
+//! ...     CPU, memory, display and keyboard sections, in that order
+//! ```
+//!
+//! Each component writes its own section (`save_state`/`load_state` in its
+//! module). Bump `SAVE_STATE_VERSION` whenever a section changes and teach
+//! the loaders to read the older layout.
+
+use crate::memory::AddressPolicy;
+use crate::quirks::{MemoryQuirk, Quirks};
+use crate::variant::Variant;
+use std::fmt;
+
+/// Identifies a save state file
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"C8ST";
+/// Current format version; states from newer versions are rejected
+pub const SAVE_STATE_VERSION: u16 = 1;
+
+/// Why a save state could not be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data does not start with `SAVE_STATE_MAGIC`
+    BadMagic,
+    /// The state was written by a format version this build cannot read
+    UnsupportedVersion(u16),
+    /// The state was saved on a different variant than the running machine
+    VariantMismatch { expected: Variant, found: Variant },
+    /// The data ended before the state was complete
+    Truncated,
+    /// A field holds a value no valid state can contain
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "Not a CHIP-8 save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported save state version {} (this build reads up to {})",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::VariantMismatch { expected, found } => {
+                write!(f, "Save state is for {:?}, but the machine is {:?}", found, expected)
+            }
+            SaveStateError::Truncated => write!(f, "Save state is truncated"),
+            SaveStateError::Corrupt(field) => write!(f, "Save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// Appends little-endian fields to a save state
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a state with the header for the given variant
+    pub(crate) fn new(variant: Variant) -> Self {
+        let mut writer = Self::with_header(SAVE_STATE_MAGIC, SAVE_STATE_VERSION);
+        writer.variant(variant);
+        writer
+    }
+
+    /// Starts another file format that shares these field encodings (e.g. movies)
+    pub(crate) fn with_header(magic: [u8; 4], version: u16) -> Self {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.bytes(&magic);
+        writer.u16(version);
+        writer
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn variant(&mut self, variant: Variant) {
+        self.u8(match variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        });
+    }
+
+    pub(crate) fn policy(&mut self, policy: AddressPolicy) {
+        self.u8(match policy {
+            AddressPolicy::Wrap => 0,
+            AddressPolicy::Mask => 1,
+            AddressPolicy::Strict => 2,
+            AddressPolicy::OpenBus => 3,
+        });
+    }
+
+    pub(crate) fn quirks(&mut self, quirks: &Quirks) {
+        self.bool(quirks.vf_reset);
+        self.bool(quirks.shift_vx);
+        self.u8(match quirks.memory {
+            MemoryQuirk::IncrementXPlusOne => 0,
+            MemoryQuirk::IncrementX => 1,
+            MemoryQuirk::Unchanged => 2,
+        });
+        self.bool(quirks.jump_vx);
+        self.bool(quirks.display_wait);
+        self.bool(quirks.wrap_sprites);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads little-endian fields from a save state
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// Format version from the header, for loaders that must read older layouts
+    pub(crate) version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header and returns a reader positioned at the first section
+    pub(crate) fn new(data: &'a [u8], expected: Variant) -> Result<Self, SaveStateError> {
+        let mut reader = Self::with_header(data, SAVE_STATE_MAGIC, SAVE_STATE_VERSION)?;
+        let found = reader.variant()?;
+        if found != expected {
+            return Err(SaveStateError::VariantMismatch { expected, found });
+        }
+        Ok(reader)
+    }
+
+    /// Checks the magic and version of another format written with `StateWriter::with_header`
+    pub(crate) fn with_header(data: &'a [u8], magic: [u8; 4], max_version: u16) -> Result<Self, SaveStateError> {
+        let mut reader = StateReader { data, pos: 0, version: 0 };
+        if reader.bytes(magic.len()).ok() != Some(&magic[..]) {
+            return Err(SaveStateError::BadMagic);
+        }
+        reader.version = reader.u16()?;
+        if reader.version == 0 || reader.version > max_version {
+            return Err(SaveStateError::UnsupportedVersion(reader.version));
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Corrupt("flag")),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.pos.checked_add(len).ok_or(SaveStateError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(SaveStateError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn quirks(&mut self) -> Result<Quirks, SaveStateError> {
+        Ok(Quirks {
+            vf_reset: self.bool()?,
+            shift_vx: self.bool()?,
+            memory: match self.u8()? {
+                0 => MemoryQuirk::IncrementXPlusOne,
+                1 => MemoryQuirk::IncrementX,
+                2 => MemoryQuirk::Unchanged,
+                _ => return Err(SaveStateError::Corrupt("memory quirk")),
+            },
+            jump_vx: self.bool()?,
+            display_wait: self.bool()?,
+            wrap_sprites: self.bool()?,
+        })
+    }
+
+    pub(crate) fn variant(&mut self) -> Result<Variant, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(Variant::Chip8),
+            1 => Ok(Variant::SuperChip),
+            2 => Ok(Variant::XoChip),
+            _ => Err(SaveStateError::Corrupt("variant")),
+        }
+    }
+
+    pub(crate) fn policy(&mut self) -> Result<AddressPolicy, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(AddressPolicy::Wrap),
+            1 => Ok(AddressPolicy::Mask),
+            2 => Ok(AddressPolicy::Strict),
+            3 => Ok(AddressPolicy::OpenBus),
+            _ => Err(SaveStateError::Corrupt("address policy")),
+        }
+    }
+
+    /// Fails unless every byte of the state was consumed
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        if self.pos == self.data.len() {
+            Ok(())
+        } else {
+            Err(SaveStateError::Corrupt("length"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let data = StateWriter::new(Variant::XoChip).finish();
+        assert_eq!(&data[..4], b"C8ST");
+        let reader = StateReader::new(&data, Variant::XoChip).unwrap();
+        assert_eq!(reader.version, SAVE_STATE_VERSION);
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn test_header_rejects_bad_input() {
+        assert_eq!(StateReader::new(b"NOPE\x01\x00\x00", Variant::Chip8).err(), Some(SaveStateError::BadMagic));
+        assert_eq!(StateReader::new(b"C8ST\x01", Variant::Chip8).err(), Some(SaveStateError::Truncated));
+        assert_eq!(
+            StateReader::new(b"C8ST\x63\x00\x00", Variant::Chip8).err(),
+            Some(SaveStateError::UnsupportedVersion(99))
+        );
+        assert_eq!(
+            StateReader::new(b"C8ST\x01\x00\x02", Variant::Chip8).err(),
+            Some(SaveStateError::VariantMismatch { expected: Variant::Chip8, found: Variant::XoChip })
+        );
+    }
+
+    #[test]
+    fn test_quirks_round_trip() {
+        let mut writer = StateWriter::new(Variant::Chip8);
+        writer.quirks(&Quirks::chip48());
+        let data = writer.finish();
+        let mut reader = StateReader::new(&data, Variant::Chip8).unwrap();
+        assert_eq!(reader.quirks().unwrap(), Quirks::chip48());
+    }
+}
@@ -1,10 +1,16 @@
 //! Sound module for CHIP-8
 //!
 //! Generates a simple beep tone when the sound timer is active.
+//! XO-CHIP programs instead play a 128-bit pattern at a programmable pitch.
 
+use crate::cpu::{DEFAULT_AUDIO_PATTERN, DEFAULT_PITCH};
+use crate::variant::Variant;
 use rodio::{OutputStream, Sink, Source};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+const SAMPLE_RATE: u32 = 48000;
+
 /// Generates a square wave audio source
 struct SquareWave {
     frequency: f32,
@@ -16,7 +22,7 @@ impl SquareWave {
     fn new(frequency: f32) -> Self {
         SquareWave {
             frequency,
-            sample_rate: 48000,
+            sample_rate: SAMPLE_RATE,
             num_sample: 0,
         }
     }
@@ -54,15 +60,91 @@ impl Source for SquareWave {
     }
 }
 
+/// XO-CHIP audio state shared between the emulator and the audio thread
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PatternState {
+    /// 128 1-bit samples, most significant bit of byte 0 first
+    pattern: [u8; 16],
+    /// Pitch register value (Fx3A)
+    pitch: u8,
+}
+
+/// Returns the XO-CHIP playback rate in bits per second for a pitch value:
+/// 4000 * 2^((pitch - 64) / 48)
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// Plays the XO-CHIP 1-bit audio pattern in a loop, sample by sample
+struct PatternWave {
+    state: Arc<Mutex<PatternState>>,
+    sample_rate: u32,
+    /// Position in the pattern, in bits (0.0..128.0)
+    position: f64,
+}
+
+impl PatternWave {
+    fn new(state: Arc<Mutex<PatternState>>) -> Self {
+        PatternWave {
+            state,
+            sample_rate: SAMPLE_RATE,
+            position: 0.0,
+        }
+    }
+}
+
+impl Iterator for PatternWave {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let state = *self.state.lock().unwrap();
+
+        let bit_index = self.position as usize % 128;
+        let bit = (state.pattern[bit_index / 8] >> (7 - bit_index % 8)) & 1;
+
+        // Advance by the number of pattern bits that fit in one output sample
+        self.position = (self.position + playback_rate(state.pitch) as f64 / self.sample_rate as f64) % 128.0;
+
+        // Same low volume as the square wave
+        Some(if bit == 1 { 0.1 } else { -0.1 })
+    }
+}
+
+impl Source for PatternWave {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
 /// Sound system that can play a beep tone
 pub struct Sound {
     _stream: OutputStream,
     sink: Sink,
+    /// Present when playing XO-CHIP patterns instead of the fixed beep
+    pattern: Option<Arc<Mutex<PatternState>>>,
 }
 
 impl Sound {
     /// Creates a new sound system
     pub fn new() -> Option<Self> {
+        Self::for_variant(Variant::Chip8)
+    }
+
+    /// Creates a sound system for the given variant.
+    /// XO-CHIP uses the programmable pattern player; other variants use the 440 Hz beep.
+    pub fn for_variant(variant: Variant) -> Option<Self> {
         // Try to create audio output
         let (_stream, stream_handle) = match OutputStream::try_default() {
             Ok(output) => output,
@@ -74,12 +156,30 @@ impl Sound {
             Err(_) => return None,
         };
 
-        // Pre-load the square wave source so it's ready to play
-        let source = SquareWave::new(440.0);
-        sink.append(source);
+        // Pre-load the source so it's ready to play
+        let pattern = if variant == Variant::XoChip {
+            let state = Arc::new(Mutex::new(PatternState {
+                pattern: DEFAULT_AUDIO_PATTERN,
+                pitch: DEFAULT_PITCH,
+            }));
+            sink.append(PatternWave::new(Arc::clone(&state)));
+            Some(state)
+        } else {
+            sink.append(SquareWave::new(440.0));
+            None
+        };
         sink.pause(); // Start paused
 
-        Some(Sound { _stream, sink })
+        Some(Sound { _stream, sink, pattern })
+    }
+
+    /// Updates the XO-CHIP pattern and pitch (no effect for the fixed beep)
+    pub fn set_pattern(&self, pattern: [u8; 16], pitch: u8) {
+        if let Some(state) = &self.pattern {
+            let mut state = state.lock().unwrap();
+            state.pattern = pattern;
+            state.pitch = pitch;
+        }
     }
 
     /// Starts playing the beep sound if not already playing
@@ -112,7 +212,7 @@ impl Default for Sound {
             let source = SquareWave::new(440.0);
             sink.append(source);
             sink.pause();
-            Sound { _stream, sink }
+            Sound { _stream, sink, pattern: None }
         })
     }
 }
@@ -146,4 +246,45 @@ mod tests {
         // This may fail if audio is not available, which is ok
         let _sound = Sound::new();
     }
+
+    #[test]
+    fn test_playback_rate() {
+        assert_eq!(playback_rate(64), 4000.0);
+        assert!((playback_rate(112) - 8000.0).abs() < 0.01); // One octave up
+        assert!((playback_rate(16) - 2000.0).abs() < 0.01); // One octave down
+    }
+
+    #[test]
+    fn test_pattern_wave_follows_bits() {
+        let mut pattern = [0u8; 16];
+        pattern[0] = 0b1000_0000; // Only the first bit is set
+        let state = Arc::new(Mutex::new(PatternState { pattern, pitch: 64 }));
+        let mut wave = PatternWave::new(Arc::clone(&state));
+
+        // 4000 bits/s at 48000 Hz = 12 samples per bit
+        // (the sample right on a bit boundary may land on either side)
+        for _ in 0..11 {
+            assert_eq!(wave.next(), Some(0.1));
+        }
+        wave.next();
+        for _ in 0..11 {
+            assert_eq!(wave.next(), Some(-0.1));
+        }
+
+        // Pattern loops after 128 bits
+        for _ in 23..(128 * 12 + 1) {
+            wave.next();
+        }
+        assert_eq!(wave.next(), Some(0.1));
+    }
+
+    #[test]
+    fn test_pattern_wave_picks_up_changes() {
+        let state = Arc::new(Mutex::new(PatternState { pattern: [0; 16], pitch: 64 }));
+        let mut wave = PatternWave::new(Arc::clone(&state));
+        assert_eq!(wave.next(), Some(-0.1));
+
+        state.lock().unwrap().pattern = [0xFF; 16];
+        assert_eq!(wave.next(), Some(0.1));
+    }
 }
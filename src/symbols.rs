@@ -0,0 +1,248 @@
+//! Symbols module for CHIP-8
+//!
+//! Maps addresses to the label names of the program's source, so the trace,
+//! debugger and disassembler can print `draw_paddle+4` instead of `0x2A6`.
+//! Symbol files come in two formats:
+//!
+//! ```text
+//! 0x0200 main            one `addr name` pair per line, address in hex, as the
+//! 0x02A2 draw_paddle     assembler writes; blank lines and # or ; comments skipped
+//! ```
+//!
+//! or Octo-style JSON: an object with a `labels` object (name to address)
+//! and/or a `breakpoints` object (address to name), or a flat object of names
+//! to addresses. JSON addresses are numbers, or strings in decimal or 0x hex.
+
+use crate::disassembler::disassemble;
+use crate::instruction::Instruction;
+use crate::json::Json;
+use crate::variant::Variant;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// A malformed symbol file, with the line the problem was found on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+/// Label names by address, and addresses by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    /// The name printed for each address (the first one given for it)
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    /// Creates an empty symbol table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a symbol file, detecting JSON by its leading `{`
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        if text.trim_start().starts_with('{') {
+            Self::parse_json(text)
+        } else {
+            Self::parse_text(text)
+        }
+    }
+
+    fn parse_text(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            let error = |message: String| Err(SymbolError { line: index + 1, message });
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [addr, name] = words[..] else {
+                return error(format!("Expected 'addr name', found '{}'", line));
+            };
+            let Ok(addr) = u16::from_str_radix(addr.trim_start_matches("0x"), 16) else {
+                return error(format!("Invalid address '{}'", addr));
+            };
+            symbols.insert(addr, name);
+        }
+        Ok(symbols)
+    }
+
+    fn parse_json(text: &str) -> Result<Self, SymbolError> {
+        let json = Json::parse(text).map_err(|e| SymbolError { line: e.line, message: e.message })?;
+        // JSON values carry no line numbers, so errors past parsing point at line 1
+        let error = |message: String| SymbolError { line: 1, message };
+        let mut symbols = Symbols::new();
+        let labels = json.get("labels");
+        let breakpoints = json.get("breakpoints");
+        let by_name = match (labels, breakpoints) {
+            (None, None) => Some(&json),
+            _ => labels,
+        };
+        if let Some(members) = by_name {
+            let members = members.as_object().ok_or_else(|| error("Expected an object of labels".to_string()))?;
+            for (name, value) in members {
+                let addr = json_address(value).ok_or_else(|| error(format!("Invalid address for '{}'", name)))?;
+                symbols.insert(addr, name);
+            }
+        }
+        if let Some(members) = breakpoints {
+            let members = members.as_object().ok_or_else(|| error("Expected an object of breakpoints".to_string()))?;
+            for (addr, value) in members {
+                match (address_text(addr), value.as_str()) {
+                    (Some(addr), Some(name)) => symbols.insert(addr, name),
+                    _ => return Err(error(format!("Invalid breakpoint '{}'", addr))),
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Adds a name for `addr`; an address keeps the first name it was given for printing
+    pub fn insert(&mut self, addr: u16, name: &str) {
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// The name at exactly `addr`
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// `name` or `name+offset` for the nearest symbol at or below `addr`
+    pub fn resolve(&self, addr: u16) -> Option<String> {
+        let (&base, name) = self.names.range(..=addr).next_back()?;
+        Some(match addr - base {
+            0 => name.clone(),
+            offset => format!("{}+{}", name, offset),
+        })
+    }
+
+    /// Looks up `name` or `name+offset` (offset in decimal, or hex with 0x)
+    pub fn address_of(&self, text: &str) -> Option<u16> {
+        let (name, offset) = match text.split_once('+') {
+            Some((name, offset)) => {
+                let offset = match offset.strip_prefix("0x") {
+                    Some(hex) => u16::from_str_radix(hex, 16).ok()?,
+                    None => offset.parse().ok()?,
+                };
+                (name, offset)
+            }
+            None => (text, 0),
+        };
+        self.addresses.get(name)?.checked_add(offset)
+    }
+
+    /// The address in hex, followed by `<name+offset>` when a symbol covers it
+    pub fn describe(&self, addr: u16) -> String {
+        match self.resolve(addr) {
+            Some(name) => format!("{:#05x} <{}>", addr, name),
+            None => format!("{:#05x}", addr),
+        }
+    }
+
+    /// Formats an instruction, naming its address operand by symbol where one covers it
+    pub fn instruction_text(&self, instruction: Instruction) -> String {
+        let named = |target: u16| self.resolve(target).unwrap_or_else(|| format!("0x{:03X}", target));
+        match instruction {
+            Instruction::Jump(target) => format!("JP {}", named(target)),
+            Instruction::Call(target) => format!("CALL {}", named(target)),
+            Instruction::LoadIndex(target) => format!("LD I, {}", named(target)),
+            Instruction::JumpOffset(target) => format!("JP V0, {}", named(target)),
+            _ => instruction.to_string(),
+        }
+    }
+
+    /// Like `disassembler::disassemble`, naming address operands by symbol
+    pub fn disassemble(&self, opcode: u16) -> String {
+        match Instruction::decode(opcode, Variant::XoChip) {
+            Ok(instruction) => self.instruction_text(instruction),
+            Err(_) => disassemble(opcode),
+        }
+    }
+}
+
+/// A JSON address: a number, or a string in decimal or 0x hex
+fn json_address(value: &Json) -> Option<u16> {
+    match value {
+        Json::String(text) => address_text(text),
+        _ => value.as_u64().and_then(|addr| u16::try_from(addr).ok()),
+    }
+}
+
+/// Parses a decimal or 0x hex address
+fn address_text(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_text_format() {
+        let symbols = Symbols::parse("# comment\n0x0200 main\n\n2a2 draw_paddle\n").unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.address_of("draw_paddle"), Some(0x2A2));
+        assert_eq!(symbols.name_at(0x200), Some("main"));
+
+        let error = Symbols::parse("0x200 main\nmain\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(Symbols::parse("zz main").is_err());
+    }
+
+    #[test]
+    fn test_parse_json_formats() {
+        let octo = r#"{"labels": {"main": 512, "draw_paddle": "0x2A2"}, "breakpoints": {"516": "check"}}"#;
+        let symbols = Symbols::parse(octo).unwrap();
+        assert_eq!(symbols.address_of("main"), Some(0x200));
+        assert_eq!(symbols.address_of("draw_paddle"), Some(0x2A2));
+        assert_eq!(symbols.name_at(0x204), Some("check"));
+
+        let flat = Symbols::parse(r#"{"main": 512}"#).unwrap();
+        assert_eq!(flat.name_at(0x200), Some("main"));
+        assert!(Symbols::parse(r#"{"main": -1}"#).is_err());
+        assert_eq!(Symbols::parse("{\n\"main\" 1}").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_resolve_and_lookup_offsets() {
+        let mut symbols = Symbols::new();
+        symbols.insert(0x200, "main");
+        symbols.insert(0x2A2, "draw_paddle");
+        symbols.insert(0x2A2, "paddle_alias");
+        assert_eq!(symbols.resolve(0x1FF), None);
+        assert_eq!(symbols.resolve(0x2A2).as_deref(), Some("draw_paddle"));
+        assert_eq!(symbols.resolve(0x2A6).as_deref(), Some("draw_paddle+4"));
+        assert_eq!(symbols.address_of("draw_paddle+4"), Some(0x2A6));
+        assert_eq!(symbols.address_of("paddle_alias+0x10"), Some(0x2B2));
+        assert_eq!(symbols.address_of("missing"), None);
+        assert_eq!(symbols.describe(0x2A6), "0x2a6 <draw_paddle+4>");
+        assert_eq!(symbols.describe(0x100), "0x100");
+        assert_eq!(symbols.disassemble(0x22A6), "CALL draw_paddle+4");
+        assert_eq!(symbols.disassemble(0xA100), "LD I, 0x100");
+        assert_eq!(symbols.disassemble(0x6005), "LD V0, 0x05");
+    }
+}
@@ -0,0 +1,166 @@
+//! Trace module for CHIP-8
+//!
+//! Writes one line per executed instruction, showing the machine state just
+//! before the instruction runs. Every field has a fixed width and position,
+//! so traces from two runs (or from another emulator converted to the same
+//! layout) can be compared with `diff` to find the first divergence:
+//!
+//! ```text
+//! CYCLE      PC   OP   INSTRUCTION          V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I    SP DT ST
+//! 0000000000 0200 6005 LD V0, 0x05          00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 0000 00 00 00
+//! ```
+//!
+//! With symbols loaded, each line ends with the label covering PC
+//! (e.g. `draw_paddle+4`) after the fixed columns.
+
+use crate::disassembler::disassemble;
+use crate::machine::{CycleHook, Machine};
+use crate::symbols::Symbols;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// Column header written at the top of every trace
+pub const TRACE_HEADER: &str =
+    "CYCLE      PC   OP   INSTRUCTION          V0 V1 V2 V3 V4 V5 V6 V7 V8 V9 VA VB VC VD VE VF I    SP DT ST";
+
+/// Formats the trace line for the instruction at PC
+pub fn trace_line(machine: &Machine, cycle: u64) -> String {
+    let cpu = machine.cpu();
+    let opcode = machine.memory().read_opcode(cpu.pc);
+    let registers: Vec<String> = cpu.v.iter().map(|v| format!("{:02X}", v)).collect();
+    format!(
+        "{:010} {:04X} {:04X} {:<20} {} {:04X} {:02X} {:02X} {:02X}",
+        cycle,
+        cpu.pc,
+        opcode,
+        disassemble(opcode),
+        registers.join(" "),
+        cpu.i,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer
+    )
+}
+
+/// Parses a PC range such as `200-2FF` (hex, inclusive, 0x optional)
+pub fn parse_pc_range(text: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = text.split_once('-')?;
+    let parse = |s: &str| u16::from_str_radix(s.trim().trim_start_matches("0x"), 16).ok();
+    let (start, end) = (parse(start)?, parse(end)?);
+    (start <= end).then_some(start..=end)
+}
+
+/// A `CycleHook` that writes a trace line before every instruction
+pub struct Tracer<W: Write> {
+    out: W,
+    /// Only instructions with PC in this range are written (all of them if None)
+    range: Option<RangeInclusive<u16>>,
+    /// Instructions executed so far, including ones outside the range
+    cycle: u64,
+    /// First write error; tracing stops once one occurs
+    error: Option<io::Error>,
+    /// Labels appended to each line
+    symbols: Symbols,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Starts a trace, writing the column header
+    pub fn new(mut out: W, range: Option<RangeInclusive<u16>>) -> Self {
+        let error = writeln!(out, "{}", TRACE_HEADER).err();
+        Tracer { out, range, cycle: 0, error, symbols: Symbols::new() }
+    }
+
+    /// Ends each line with the symbol covering PC
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Number of instructions seen so far
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Flushes the output and reports the first write error, if any
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> CycleHook for Tracer<W> {
+    fn before_cycle(&mut self, machine: &mut Machine) -> bool {
+        let in_range = self.range.as_ref().is_none_or(|range| range.contains(&machine.cpu().pc));
+        if in_range && self.error.is_none() {
+            let line = trace_line(machine, self.cycle);
+            self.error = match self.symbols.resolve(machine.cpu().pc) {
+                Some(label) => writeln!(self.out, "{} {}", line, label),
+                None => writeln!(self.out, "{}", line),
+            }
+            .err();
+        }
+        self.cycle += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_line_layout() {
+        let mut machine = Machine::default();
+        machine.load_rom(&[0x60, 0x05]).unwrap();
+        machine.cpu_mut().v[0xF] = 0xAB;
+        let line = trace_line(&machine, 7);
+        assert_eq!(
+            line,
+            "0000000007 0200 6005 LD V0, 0x05          00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 AB 0000 00 00 00"
+        );
+        assert_eq!(line.len(), TRACE_HEADER.len());
+    }
+
+    #[test]
+    fn test_tracer_filters_by_pc_range() {
+        let mut machine = Machine::default();
+        // 0x200: LD V0, 1 / 0x202: LD V1, 2 / 0x204: JP 0x204
+        machine.load_rom(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x04]).unwrap();
+        let mut tracer = Tracer::new(Vec::new(), parse_pc_range("202-203"));
+        machine.run_frame_with(&mut tracer).unwrap();
+        assert_eq!(tracer.cycles(), 200);
+
+        let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("0000000001 0202 6102 LD V1, 0x02"));
+    }
+
+    #[test]
+    fn test_tracer_appends_symbols() {
+        let mut machine = Machine::default();
+        // 0x200: LD V0, 1 / 0x202: JP 0x202
+        machine.load_rom(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        let symbols = Symbols::parse("0x1FF before\n0x202 spin\n").unwrap();
+        let mut tracer = Tracer::new(Vec::new(), None).with_symbols(symbols);
+        tracer.before_cycle(&mut machine);
+        machine.step().unwrap();
+        tracer.before_cycle(&mut machine);
+
+        let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[1].ends_with(" 00 00 00 before+1"), "{}", lines[1]);
+        assert!(lines[2].ends_with(" 00 00 00 spin"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn test_parse_pc_range() {
+        assert_eq!(parse_pc_range("200-2FF"), Some(0x200..=0x2FF));
+        assert_eq!(parse_pc_range("0x300-0x310"), Some(0x300..=0x310));
+        assert_eq!(parse_pc_range("300-200"), None);
+        assert_eq!(parse_pc_range("300"), None);
+    }
+}
@@ -0,0 +1,97 @@
+//! Variant module for CHIP-8
+//!
+//! Identifies which CHIP-8 platform a program targets. The variant decides
+//! the size of the address space; the per-instruction differences between
+//! interpreters live in `Quirks`.
+
+use crate::quirks::Quirks;
+
+/// The CHIP-8 family member a program was written for
+/// Each variant extends the one before it, so later variants compare greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Variant {
+    /// Original CHIP-8 (COSMAC VIP, CHIP-48)
+    #[default]
+    Chip8,
+    /// Super-CHIP 1.1 (HP48)
+    SuperChip,
+    /// XO-CHIP (Octo)
+    XoChip,
+}
+
+impl Variant {
+    /// Size of the address space in bytes
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::SuperChip => 0x1000, // 4KB
+            Variant::XoChip => 0x10000,                     // 64KB
+        }
+    }
+
+    /// Returns the variant implied by a quirk preset name (see `quirks::PRESET_NAMES`)
+    pub fn from_profile_name(name: &str) -> Option<Self> {
+        match name {
+            "vip" | "chip48" => Some(Variant::Chip8),
+            "schip-legacy" | "schip-modern" => Some(Variant::SuperChip),
+            "xo-chip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The quirk profile normally used with this variant
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::vip(),
+            Variant::SuperChip => Quirks::schip_modern(),
+            Variant::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    /// Preset name of `default_quirks` (see `quirks::PRESET_NAMES`)
+    pub fn default_profile(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "vip",
+            Variant::SuperChip => "schip-modern",
+            Variant::XoChip => "xo-chip",
+        }
+    }
+
+    /// Name as written in documentation
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SuperChip => "Super-CHIP",
+            Variant::XoChip => "XO-CHIP",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::PRESET_NAMES;
+
+    #[test]
+    fn test_variant_memory_size() {
+        assert_eq!(Variant::Chip8.memory_size(), 4096);
+        assert_eq!(Variant::SuperChip.memory_size(), 4096);
+        assert_eq!(Variant::XoChip.memory_size(), 65536);
+    }
+
+    #[test]
+    fn test_variant_for_every_profile() {
+        for name in PRESET_NAMES.iter() {
+            assert!(Variant::from_profile_name(name).is_some(), "Profile '{}' has no variant", name);
+        }
+        assert_eq!(Variant::from_profile_name("xo-chip"), Some(Variant::XoChip));
+    }
+
+    #[test]
+    fn test_default_profile_matches_default_quirks() {
+        for variant in [Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+            let profile = variant.default_profile();
+            assert_eq!(Quirks::from_name(profile), Some(variant.default_quirks()));
+            assert_eq!(Variant::from_profile_name(profile), Some(variant));
+        }
+    }
+}
@@ -2,10 +2,22 @@
 //!
 //! These tests verify that all components work together correctly.
 
+use chip8_emulator::analyzer::analyze;
+use chip8_emulator::assembler::assemble;
 use chip8_emulator::cpu::Cpu;
+use chip8_emulator::debugger::{BreakReason, Command, Debugger};
 use chip8_emulator::display::Display;
+use chip8_emulator::gdbstub::GdbStub;
 use chip8_emulator::keyboard::Keyboard;
+use chip8_emulator::machine::{Machine, MachineConfig};
 use chip8_emulator::memory::Memory;
+use chip8_emulator::quirks::Quirks;
+use chip8_emulator::romdb::RomDatabase;
+use chip8_emulator::sha1::sha1_hex;
+use chip8_emulator::symbols::Symbols;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
 
 #[test]
 fn test_load_and_execute_simple_program() {
@@ -19,10 +31,10 @@ fn test_load_and_execute_simple_program() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute one cycle
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
 
     // Verify V0 = 42
     assert_eq!(cpu.v[0], 42);
@@ -42,11 +54,11 @@ fn test_arithmetic_operations_integration() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute three cycles
     for _ in 0..3 {
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     }
 
     assert_eq!(cpu.v[0], 10);
@@ -71,19 +83,19 @@ fn test_subroutine_call_and_return() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute CALL instruction
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x206); // Should jump to subroutine
     assert_eq!(cpu.sp, 1); // Stack pointer should increment
 
     // Execute LD V1, 255
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.v[1], 255);
 
     // Execute RET
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x202); // Should return to after CALL
     assert_eq!(cpu.sp, 0); // Stack pointer should decrement
 }
@@ -103,11 +115,11 @@ fn test_display_drawing_integration() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute all instructions
     for _ in 0..4 {
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     }
 
     // Verify VF is set (could be 0 or 1 depending on collision)
@@ -131,11 +143,11 @@ fn test_timer_countdown_integration() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Set delay timer
-    cpu.cycle(&mut memory, &mut display, &keyboard);
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
 
     // Verify timer was set
     assert_eq!(cpu.delay_timer, 10);
@@ -146,7 +158,7 @@ fn test_timer_countdown_integration() {
     }
 
     // Read timer value
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
 
     // Timer should have counted down
     assert_eq!(cpu.v[1], 7); // 10 - 3 = 7
@@ -166,19 +178,19 @@ fn test_keyboard_input_integration() {
     let mut display = Display::new();
     let mut keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Test without key pressed
     cpu.v[0] = 0x5; // Check for key 5
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x202); // Should not skip
 
     // Reset and test with key pressed
     let mut cpu = Cpu::new();
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
     keyboard.set_key(0x5, true);
     cpu.v[0] = 0x5;
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x204); // Should skip to 0x204
 }
 
@@ -198,22 +210,22 @@ fn test_jump_and_conditional_skip() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute LD V0, 66
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.v[0], 66);
 
     // Execute SE V0, 66 (should skip)
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x206); // Should have skipped to 0x206
 
     // Execute JP 0x208
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.pc, 0x208);
 
     // Execute LD V2, 1
-    cpu.cycle(&mut memory, &mut display, &keyboard);
+    cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     assert_eq!(cpu.v[2], 1);
     assert_eq!(cpu.v[1], 0); // V1 should still be 0 (instruction was skipped)
 }
@@ -232,11 +244,11 @@ fn test_bcd_conversion_integration() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute all instructions
     for _ in 0..3 {
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     }
 
     // Check BCD values in memory at 0x300, 0x301, 0x302
@@ -266,11 +278,11 @@ fn test_register_save_and_load() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute all instructions
     for _ in 0..10 {
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     }
 
     // Verify registers were restored
@@ -296,11 +308,11 @@ fn test_multiple_cycles_with_all_components() {
     let mut display = Display::new();
     let keyboard = Keyboard::new();
 
-    memory.load_rom(&program);
+    memory.load_rom(&program).unwrap();
 
     // Execute all instructions
     for _ in 0..6 {
-        cpu.cycle(&mut memory, &mut display, &keyboard);
+        cpu.cycle(&mut memory, &mut display, &keyboard).unwrap();
     }
 
     // Verify final state
@@ -316,3 +328,266 @@ fn test_multiple_cycles_with_all_components() {
     let buffer = display.to_buffer();
     assert!(buffer.iter().all(|&pixel| pixel == 0x00000000));
 }
+
+#[test]
+fn test_machine_frame_timer_and_keypad() {
+    // Count down DT one frame at a time until a key is held
+    let program = vec![
+        0x60, 0x0A, // 0x200: LD V0, 10
+        0xF0, 0x15, // 0x202: LD DT, V0
+        0xE1, 0x9E, // 0x204: SKP V1 (key 0)
+        0x12, 0x04, // 0x206: JP 0x204
+        0xF2, 0x07, // 0x208: LD V2, DT
+        0x12, 0x0A, // 0x20A: JP 0x20A
+    ];
+
+    let mut machine = Machine::default();
+    machine.load_rom(&program).unwrap();
+
+    // Each frame runs until the cycle budget is spent, then ticks the timers once
+    for _ in 0..3 {
+        machine.run_frame().unwrap();
+    }
+    assert_eq!(machine.cpu().delay_timer, 7);
+
+    machine.keyboard_mut().set_key(0x0, true);
+    machine.run_frame().unwrap();
+    assert_eq!(machine.cpu().v[2], 7);
+    assert_eq!(machine.cpu().pc, 0x20A);
+}
+
+#[test]
+fn test_machine_display_wait_quirk() {
+    // Two draws in a row: with DISP.WAIT only one runs per frame
+    let program = vec![
+        0xA0, 0x00, // LD I, 0x000 (font "0")
+        0xD0, 0x05, // DRW V0, V0, 5
+        0xD0, 0x05, // DRW V0, V0, 5
+        0x12, 0x06, // JP 0x206
+    ];
+
+    let mut vip = Machine::default();
+    vip.load_rom(&program).unwrap();
+    vip.run_frame().unwrap();
+    assert_eq!(vip.cpu().pc, 0x204);
+    assert!(vip.display().get_pixel(0, 0));
+
+    let mut modern = Machine::new(MachineConfig {
+        quirks: Quirks::schip_modern(),
+        ..MachineConfig::default()
+    });
+    modern.load_rom(&program).unwrap();
+    modern.run_frame().unwrap();
+    assert_eq!(modern.cpu().pc, 0x206);
+    assert!(!modern.display().get_pixel(0, 0)); // Drawn twice, XOR cleared it
+}
+
+#[test]
+fn test_assembled_program_runs() {
+    // Sum 1..=10 into V1 with an Octo loop, then store it as BCD
+    let source = "
+        : main
+            v0 := 0
+            v1 := 0
+            loop
+                v0 += 1
+                v1 += v0
+                while v0 != 10
+            again
+            i := digits
+            bcd v1
+        : halt
+            jump halt
+        : digits
+            0 0 0
+    ";
+    let assembly = assemble(source, 0x200).unwrap();
+    let mut machine = Machine::default();
+    machine.load_rom(&assembly.rom).unwrap();
+    machine.run_frame().unwrap();
+
+    let digits = assembly.labels["digits"];
+    assert_eq!(machine.cpu().v[1], 55);
+    assert_eq!(machine.cpu().pc, assembly.labels["halt"]);
+    assert_eq!([0, 1, 2].map(|i| machine.memory().read(digits + i)), [0, 5, 5]);
+}
+
+#[test]
+fn test_breakpoint_by_label_from_symbol_map() {
+    let source = "
+        : main
+            v0 := 1
+        : bump
+            v0 += 1
+            jump bump
+    ";
+    let assembly = assemble(source, 0x200).unwrap();
+    let symbols = Symbols::parse(&assembly.symbol_map()).unwrap();
+    let mut machine = Machine::default();
+    machine.load_rom(&assembly.rom).unwrap();
+
+    let mut debugger = Debugger::new();
+    let Ok(Command::Break(pc)) = Command::parse_with_symbols("break bump+2", &symbols) else {
+        panic!("break by label did not parse");
+    };
+    debugger.add_breakpoint(pc);
+    let reason = debugger.cont(&mut machine, 1);
+    assert_eq!(reason, BreakReason::Breakpoint { pc: 0x204 });
+    assert_eq!(reason.describe(&symbols), "Breakpoint at 0x204 <bump+2>");
+}
+
+#[test]
+fn test_analyzer_picks_a_profile_that_runs_the_rom() {
+    // Super-CHIP: switch to high resolution, draw a big font digit, halt
+    let source = "
+        : main
+            hires
+            v0 := 3
+            i := bighex v0
+            sprite v0 v0 10
+        : halt
+            jump halt
+    ";
+    let assembly = assemble(source, 0x200).unwrap();
+    let analysis = analyze(&assembly.rom, 0x200);
+    assert_eq!(analysis.profile(), "schip-modern");
+
+    let config = MachineConfig::for_profile(analysis.profile()).unwrap();
+    assert_eq!(config.variant, analysis.variant);
+    let mut machine = Machine::new(config);
+    machine.load_rom(&assembly.rom).unwrap();
+    machine.run_frame().unwrap();
+    assert!(machine.display().is_hires());
+    assert_eq!(machine.cpu().pc, assembly.labels["halt"]);
+}
+
+#[test]
+fn test_rom_database_settings_run_the_rom() {
+    // Shifts V1 into V0: VIP shifts Vy, the database marks this ROM as shifting Vx
+    let source = "
+        : main
+            v0 := 0x10
+            v1 := 0x04
+            v0 >>= v1
+        : halt
+            jump halt
+    ";
+    let assembly = assemble(source, 0x200).unwrap();
+    let programs = format!(
+        r##"[{{"title": "Shifter", "authors": ["Test"], "roms": {{"{}": {{"platforms": ["originalChip8"],
+            "quirkyPlatforms": {{"originalChip8": {{"shift": true}}}}, "tickrate": 15,
+            "colors": {{"pixels": ["#000040", "#40ff40"]}}}}}}}}]"##,
+        sha1_hex(&assembly.rom)
+    );
+    let database = RomDatabase::parse(&programs).unwrap();
+    let info = database.lookup(&assembly.rom).unwrap();
+    assert_eq!(info.title, "Shifter");
+
+    let mut config = MachineConfig::default();
+    info.apply(&mut config);
+    assert_eq!(config.cycles_per_frame, 15);
+    let mut machine = Machine::new(config);
+    machine.load_rom(&assembly.rom).unwrap();
+    machine.run_frame().unwrap();
+    assert_eq!(machine.cpu().v[0], 0x08);
+    assert_eq!(machine.cpu().pc, assembly.labels["halt"]);
+    assert_eq!(machine.display().to_buffer()[0], 0x000040);
+}
+
+/// Sends one RSP packet and returns the reply payload
+fn gdb_exchange(stream: &mut TcpStream, packet: &str) -> String {
+    let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    write!(stream, "${}#{:02x}", packet, checksum).unwrap();
+
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        stream.read_exact(&mut byte).unwrap();
+        match byte[0] {
+            b'+' if reply.is_empty() => {}
+            b'$' => reply.clear(),
+            b'#' => break,
+            other => reply.push(other),
+        }
+    }
+    let mut checksum = [0u8; 2];
+    stream.read_exact(&mut checksum).unwrap();
+    stream.write_all(b"+").unwrap();
+    String::from_utf8(reply).unwrap()
+}
+
+#[test]
+fn test_gdb_stub_over_loopback() {
+    let program = vec![
+        0x60, 0x05, // 0x200: LD V0, 5
+        0xA3, 0x00, // 0x202: LD I, 0x300
+        0xF0, 0x55, // 0x204: LD [I], V0
+        0x12, 0x06, // 0x206: JP 0x206
+    ];
+    let mut machine = Machine::default();
+    machine.load_rom(&program).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        GdbStub::new().serve(&mut machine, stream).unwrap();
+        machine
+    });
+
+    let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    assert_eq!(gdb_exchange(&mut client, "?"), "S05");
+
+    // Single step executes LD V0, 5
+    assert_eq!(gdb_exchange(&mut client, "s"), "S05");
+    assert_eq!(gdb_exchange(&mut client, "p0"), "05");
+    assert_eq!(gdb_exchange(&mut client, "p11"), "0202"); // PC = 0x202, little-endian
+
+    // Continue to a software breakpoint
+    assert_eq!(gdb_exchange(&mut client, "Z0,206,2"), "OK");
+    assert_eq!(gdb_exchange(&mut client, "c"), "S05");
+    assert_eq!(gdb_exchange(&mut client, "p11"), "0602");
+    assert_eq!(gdb_exchange(&mut client, "m300,1"), "05");
+
+    // Registers written by the client are seen by the core
+    assert_eq!(gdb_exchange(&mut client, "P1=2a"), "OK");
+    assert_eq!(gdb_exchange(&mut client, "D"), "OK");
+
+    let machine = server.join().unwrap();
+    assert_eq!(machine.cpu().v[1], 0x2A);
+    assert_eq!(machine.cpu().pc, 0x206);
+}
+
+#[test]
+fn test_disassembly_round_trips_through_assembler() {
+    use chip8_emulator::disassembler::Disassembly;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = StdRng::seed_from_u64(0xC8);
+    for _ in 0..2000 {
+        let len = rng.gen_range(1..=64usize);
+        let mut rom = Vec::with_capacity(len);
+        while rom.len() < len {
+            // Favour flow control into the ROM (odd targets included) so the
+            // tracer mixes code, data and overlapping instructions
+            let target = 0x200 + rng.gen_range(0..len as u16);
+            let word: u16 = match rng.gen_range(0..8) {
+                0 => 0x1000 | target,
+                1 => 0x2000 | target,
+                2 => 0xB000 | target,
+                3 => 0xA000 | target,
+                4 => 0x3000 | rng.gen_range(0..0x1000u16),
+                5 => 0xF000,
+                6 => 0x00EE,
+                _ => rng.gen(),
+            };
+            rom.extend_from_slice(&word.to_be_bytes());
+        }
+        rom.truncate(len);
+
+        let listing = Disassembly::trace(&rom, 0x200).to_string();
+        let assembly = assemble(&listing, 0x200).unwrap_or_else(|e| panic!("{}\n{}", e, listing));
+        assert_eq!(assembly.rom, rom, "{}", listing);
+    }
+}